@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ChallengeKind {
+    /// Hashcash-style proof-of-work: find `answer` such that SHA-256 of `"{token}:{answer}"`
+    /// starts with at least `difficulty` zero bits
+    ProofOfWork,
+}
+
+/// Anti-bot challenge that has to be solved before registration
+#[derive(Deserialize, Serialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Challenge {
+    pub kind: ChallengeKind,
+    pub token: String,
+    pub difficulty: u8,
+    pub expires_at: i64,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ChallengeSolution {
+    /// Token of the issued challenge
+    pub token: String,
+    pub answer: String,
+}
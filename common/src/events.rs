@@ -0,0 +1,76 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{notification::Notification, social::DirectMessage};
+
+/// Event pushed to the clients connected to the gateway
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HubEvent {
+    FriendRequest {
+        uuid: Uuid,
+        username: String,
+    },
+    FriendAccepted {
+        uuid: Uuid,
+        username: String,
+    },
+    Presence {
+        uuid: Uuid,
+        presence: Presence,
+    },
+    /// Session has been ended, `None` if all sessions of the user have been ended
+    SessionRevoked {
+        session: Option<Uuid>,
+    },
+    DirectMessage {
+        message: DirectMessage,
+    },
+    /// Recipient has read messages sent before the timestamp
+    MessagesRead {
+        uuid: Uuid,
+        until: i64,
+    },
+    PartyInvite {
+        party: Uuid,
+        uuid: Uuid,
+        username: String,
+    },
+    /// Party members or leader have changed, `None` if the user is no longer in the party
+    PartyUpdated {
+        party: Option<Uuid>,
+    },
+    /// Party leader has requested PIT for the whole party
+    PartyPit {
+        party: Uuid,
+        sid: String,
+        token: String,
+    },
+    GuildInvite {
+        guild: Uuid,
+        tag: String,
+    },
+    /// Guild membership of the user has changed, `None` if the user is no longer in the guild
+    GuildUpdated {
+        guild: Option<Uuid>,
+    },
+    Notification {
+        notification: Notification,
+    },
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Eq, Clone, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Presence {
+    Offline,
+    Online,
+    InGame { sid: String },
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct FriendPresence {
+    pub uuid: Uuid,
+    pub presence: Presence,
+}
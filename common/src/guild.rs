@@ -0,0 +1,95 @@
+use std::ops::BitOr;
+
+use serde::{Deserialize, Serialize};
+use serde_repr::{Deserialize_repr, Serialize_repr};
+use uuid::Uuid;
+
+/// Set of actions that members of the guild rank are allowed to perform
+#[derive(Deserialize, Serialize, PartialEq, Eq, Clone, Copy, Default, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(transparent)]
+pub struct GuildPermissions(pub i32);
+
+impl GuildPermissions {
+    pub const NONE: Self = Self(0);
+    pub const INVITE: Self = Self(1);
+    pub const APPROVE: Self = Self(1 << 1);
+    pub const KICK: Self = Self(1 << 2);
+    pub const MANAGE_RANKS: Self = Self(1 << 3);
+    pub const EDIT_PROFILE: Self = Self(1 << 4);
+    pub const ALL: Self = Self((1 << 5) - 1);
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for GuildPermissions {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Self(self.0 | rhs.0)
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct GuildRank {
+    pub uuid: Uuid,
+    pub name: String,
+    pub permissions: GuildPermissions,
+    /// Lower position means higher rank, the guild leader has position 0
+    pub position: i16,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct GuildProfile {
+    pub uuid: Uuid,
+    pub tag: String,
+    pub name: String,
+    pub description: String,
+    pub members: i64,
+    pub created_at: i64,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct GuildInfo {
+    #[serde(flatten)]
+    pub profile: GuildProfile,
+    pub ranks: Vec<GuildRank>,
+    /// Rank of the user
+    pub rank: Uuid,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct GuildMember {
+    pub uuid: Uuid,
+    pub username: String,
+    pub rank: Uuid,
+    pub joined_at: i64,
+}
+
+#[derive(Deserialize_repr, Serialize_repr, PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[repr(i16)]
+pub enum GuildRequestKind {
+    /// Guild has invited the user
+    Invite = 0,
+    /// User has applied to the guild
+    Application = 1,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct GuildRequest {
+    pub guild: Uuid,
+    pub tag: String,
+    pub uuid: Uuid,
+    pub username: String,
+    pub kind: GuildRequestKind,
+    pub created_at: i64,
+}
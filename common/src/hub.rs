@@ -1,23 +1,123 @@
+use std::{collections::BTreeMap, str::FromStr};
+
 use serde::{Deserialize, Serialize};
 
 #[derive(Deserialize, Serialize, Debug)]
-pub struct HubStatus<'a> {
-    pub name: &'a str,
-    pub hub_version: &'a str,
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct HubStatus {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contact: Option<String>,
+    pub public_url: String,
+    pub hub_version: String,
+    /// Latest API version supported by the hub
     pub api_version: HubApiVersion,
+    pub api_versions: Vec<HubApiVersion>,
     pub mode: HubMode,
+    pub registration: RegistrationPolicy,
+    pub features: Vec<HubFeature>,
+    /// Seconds since the hub has started
+    pub uptime: u64,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 #[serde(rename_all = "lowercase")]
 pub enum HubApiVersion {
     V1,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+impl HubApiVersion {
+    /// Path prefix segment and `Accept-Version` header value of the version
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::V1 => "v1",
+        }
+    }
+}
+
+impl FromStr for HubApiVersion {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "v1" => Ok(Self::V1),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 #[serde(rename_all = "lowercase")]
 pub enum HubMode {
     Production = 0,
     Testing,
     Debug,
 }
+
+#[derive(Deserialize, Serialize, PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum HubFeature {
+    Gateway,
+    Friends,
+    Messages,
+    Parties,
+    Guilds,
+    Notifications,
+    Avatars,
+    Exports,
+    /// Users can create their own invite codes
+    UserInvites,
+    /// Registration requires solving the anti-bot challenge
+    Challenge,
+    Tls,
+}
+
+/// Who is allowed to create new accounts
+#[derive(Deserialize, Serialize, PartialEq, Eq, Clone, Copy, Default, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum RegistrationPolicy {
+    #[default]
+    Open,
+    /// Registration requires a valid invite code
+    InviteOnly,
+    Closed,
+}
+
+/// Health of the hub or one of its components, ordered from best to worst
+#[derive(Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Ok,
+    /// Serves requests, but some thresholds are exceeded
+    Degraded,
+    /// Can not serve requests
+    Down,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ComponentHealth {
+    pub status: HealthStatus,
+    /// Duration of the check in milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct HealthReport {
+    /// The worst status of the components
+    pub status: HealthStatus,
+    pub components: BTreeMap<String, ComponentHealth>,
+}
@@ -1,3 +1,9 @@
+pub mod challenge;
+pub mod events;
+pub mod guild;
 pub mod hub;
+pub mod notification;
+pub mod profile;
 pub mod responses;
+pub mod social;
 pub mod user;
@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+use serde_repr::{Deserialize_repr, Serialize_repr};
+use uuid::Uuid;
+
+#[derive(Deserialize_repr, Serialize_repr, PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[repr(i16)]
+pub enum NotificationKind {
+    /// Account security changes, e.g. password change
+    Security = 0,
+    /// Activity of other users, e.g. friend requests
+    Social = 1,
+    /// Announcements sent by the hub staff
+    System = 2,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Notification {
+    /// Notification UUID, shared by all recipients of a broadcast
+    pub uuid: Uuid,
+    pub kind: NotificationKind,
+    pub title: String,
+    pub body: String,
+    pub read: bool,
+    pub created_at: i64,
+}
@@ -1,9 +1,10 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::user::UserSession;
+use crate::user::{ExportStatus, UserSession};
 
 #[derive(Deserialize, Serialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct RegistrationResponse {
     pub uuid: Uuid,
 }
@@ -15,6 +16,7 @@ impl RegistrationResponse {
 }
 
 #[derive(Deserialize, Serialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct SessionsResponse {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub web: Option<UserSession>,
@@ -23,3 +25,21 @@ pub struct SessionsResponse {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub mobile: Option<UserSession>,
 }
+
+#[derive(Deserialize, Serialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ExportResponse {
+    pub uuid: Uuid,
+    pub status: ExportStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    pub expires_at: i64,
+    pub created_at: i64,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct BroadcastResponse {
+    pub uuid: Uuid,
+    pub recipients: usize,
+}
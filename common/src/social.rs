@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+use serde_repr::{Deserialize_repr, Serialize_repr};
+use uuid::Uuid;
+
+#[derive(Deserialize_repr, Serialize_repr, PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[repr(i16)]
+pub enum FriendStatus {
+    Pending = 0,
+    Accepted = 1,
+    Blocked = 2,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Friend {
+    pub uuid: Uuid,
+    pub username: String,
+    pub status: FriendStatus,
+    pub since: i64,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct FriendRequests {
+    pub incoming: Vec<Friend>,
+    pub outgoing: Vec<Friend>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DirectMessage {
+    pub uuid: Uuid,
+    pub sender: Uuid,
+    pub recipient: Uuid,
+    pub body: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub read_at: Option<i64>,
+    pub created_at: i64,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Conversation {
+    /// Other participant UUID
+    pub uuid: Uuid,
+    pub username: String,
+    pub last_message: DirectMessage,
+    pub unread: i64,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct PartyMember {
+    pub uuid: Uuid,
+    pub username: String,
+    pub joined_at: i64,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct PartyInfo {
+    pub uuid: Uuid,
+    pub leader: Uuid,
+    pub members: Vec<PartyMember>,
+    pub created_at: i64,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct PartyInvite {
+    pub party: Uuid,
+    /// Inviter UUID
+    pub uuid: Uuid,
+    /// Inviter username
+    pub username: String,
+    pub created_at: i64,
+}
@@ -3,32 +3,55 @@ use serde_repr::{Deserialize_repr, Serialize_repr};
 use uuid::Uuid;
 
 #[derive(Deserialize, Serialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct UserData {
     pub uuid: Uuid,
     pub username: String,
     pub email: String,
     pub status: UserStatus,
+    pub role: UserRole,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deletion_at: Option<i64>,
     pub created_at: i64,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct UserInfo {
     pub uuid: Uuid,
     pub username: String,
     pub status: UserStatus,
 }
 
-#[derive(Deserialize_repr, Serialize_repr, Clone, Copy, Debug)]
+#[derive(Deserialize_repr, Serialize_repr, PartialEq, Eq, Clone, Copy, Debug)]
 #[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 #[repr(i16)]
 pub enum UserStatus {
     Active = 0,
     Inactive = 1,
     Banned = 2,
+    PendingDeletion = 3,
+    Deleted = 4,
+}
+
+/// Role of the user, each role includes the privileges of the roles below it
+#[derive(
+    Deserialize_repr, Serialize_repr, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default, Debug,
+)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[repr(i16)]
+pub enum UserRole {
+    #[default]
+    User = 0,
+    Moderator = 1,
+    Admin = 2,
 }
 
 #[derive(Deserialize_repr, Serialize_repr, PartialEq, Eq, Clone, Copy, Default, Debug)]
 #[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 #[repr(i16)]
 pub enum ClientType {
     #[default]
@@ -37,7 +60,18 @@ pub enum ClientType {
     Mobile = 2,
 }
 
+#[derive(Deserialize_repr, Serialize_repr, PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[repr(i16)]
+pub enum ExportStatus {
+    Pending = 0,
+    Ready = 1,
+    Failed = 2,
+}
+
 #[derive(Deserialize, Serialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct UserSession {
     pub uuid: Uuid,
     pub ct: ClientType,
@@ -45,3 +79,18 @@ pub struct UserSession {
     pub updated_at: i64,
     pub created_at: i64,
 }
+
+#[derive(Deserialize, Serialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Invite {
+    pub code: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub creator: Option<Uuid>,
+    /// Maximum number of registrations, unlimited if `None`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_uses: Option<i32>,
+    pub uses: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<i64>,
+    pub created_at: i64,
+}
@@ -0,0 +1 @@
+alter table "User" drop column deletion_at;
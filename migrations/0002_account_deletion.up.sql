@@ -0,0 +1 @@
+alter table "User" add column deletion_at timestamptz;
@@ -0,0 +1,8 @@
+create table "Export" (
+	uuid uuid primary key default uuid_generate_v4(),
+	sub uuid not null references "User" on delete cascade on update cascade,
+	status smallint not null default 0,
+	data jsonb,
+	expires_at timestamptz not null,
+	created_at timestamptz not null default now()
+);
@@ -0,0 +1,2 @@
+drop table "EmailChange";
+drop table "UsernameHistory";
@@ -0,0 +1,21 @@
+create table "UsernameHistory" (
+	sub uuid not null references "User" on delete cascade on update cascade,
+	username varchar(24) not null,
+	reserved_until timestamptz not null,
+	created_at timestamptz not null default now()
+);
+
+create index on "UsernameHistory" (username);
+
+create table "EmailChange" (
+	uuid uuid primary key default uuid_generate_v4(),
+	sub uuid not null references "User" on delete cascade on update cascade,
+	old_email email not null,
+	new_email email not null,
+	token uuid unique not null default uuid_generate_v4(),
+	revert_token uuid unique not null default uuid_generate_v4(),
+	confirmed_at timestamptz,
+	expires_at timestamptz not null,
+	revertable_until timestamptz not null,
+	created_at timestamptz not null default now()
+);
@@ -0,0 +1,2 @@
+drop trigger if exists updated_at_trigger on "Friendship";
+drop table "Friendship";
@@ -0,0 +1,16 @@
+create table "Friendship" (
+	sub uuid not null references "User" on delete cascade on update cascade,
+	target uuid not null references "User" on delete cascade on update cascade,
+	status smallint not null default 0,
+	updated_at timestamptz not null default now(),
+	created_at timestamptz not null default now(),
+	primary key (sub, target),
+	check (sub <> target)
+);
+
+create index on "Friendship" (target);
+
+create trigger updated_at_trigger
+	before update on "Friendship"
+	for each row
+execute function updated_at_time_func();
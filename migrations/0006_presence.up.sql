@@ -0,0 +1,11 @@
+create table "Presence" (
+	sub uuid primary key references "User" on delete cascade on update cascade,
+	connections integer not null default 0,
+	sid varchar(12),
+	updated_at timestamptz not null default now()
+);
+
+create trigger updated_at_trigger
+	before update on "Presence"
+	for each row
+execute function updated_at_time_func();
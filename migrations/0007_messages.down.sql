@@ -0,0 +1,3 @@
+drop table "Message";
+
+alter table "User" drop column chat_banned_until;
@@ -0,0 +1,15 @@
+alter table "User" add column chat_banned_until timestamptz;
+
+create table "Message" (
+	uuid uuid primary key default uuid_generate_v4(),
+	sender uuid not null references "User" on delete cascade on update cascade,
+	recipient uuid not null references "User" on delete cascade on update cascade,
+	body varchar(2000) not null,
+	read_at timestamptz,
+	sender_deleted boolean not null default false,
+	recipient_deleted boolean not null default false,
+	created_at timestamptz not null default now()
+);
+
+create index on "Message" (sender, recipient, created_at);
+create index on "Message" (recipient, sender, created_at);
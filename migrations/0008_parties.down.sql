@@ -0,0 +1,3 @@
+drop table "PartyInvite";
+drop table "PartyMember";
+drop table "Party";
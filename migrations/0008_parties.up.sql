@@ -0,0 +1,21 @@
+create table "Party" (
+	uuid uuid primary key default uuid_generate_v4(),
+	leader uuid not null references "User" on delete cascade on update cascade,
+	created_at timestamptz not null default now()
+);
+
+create table "PartyMember" (
+	sub uuid primary key references "User" on delete cascade on update cascade,
+	party uuid not null references "Party" on delete cascade on update cascade,
+	created_at timestamptz not null default now()
+);
+
+create index on "PartyMember" (party);
+
+create table "PartyInvite" (
+	party uuid not null references "Party" on delete cascade on update cascade,
+	sub uuid not null references "User" on delete cascade on update cascade,
+	sender uuid not null references "User" on delete cascade on update cascade,
+	created_at timestamptz not null default now(),
+	primary key (party, sub)
+);
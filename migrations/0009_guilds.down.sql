@@ -0,0 +1,5 @@
+drop trigger if exists updated_at_trigger on "Guild";
+drop table "GuildRequest";
+drop table "GuildMember";
+drop table "GuildRank";
+drop table "Guild";
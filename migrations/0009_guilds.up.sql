@@ -0,0 +1,39 @@
+create table "Guild" (
+	uuid uuid primary key default uuid_generate_v4(),
+	tag citext unique not null,
+	name varchar(32) not null,
+	description varchar(512) not null default '',
+	updated_at timestamptz not null default now(),
+	created_at timestamptz not null default now()
+);
+
+create table "GuildRank" (
+	uuid uuid primary key default uuid_generate_v4(),
+	guild uuid not null references "Guild" on delete cascade on update cascade,
+	name varchar(24) not null,
+	permissions integer not null default 0,
+	position smallint not null,
+	unique (guild, name)
+);
+
+create table "GuildMember" (
+	sub uuid primary key references "User" on delete cascade on update cascade,
+	guild uuid not null references "Guild" on delete cascade on update cascade,
+	rank uuid not null references "GuildRank" on delete restrict on update cascade,
+	created_at timestamptz not null default now()
+);
+
+create index on "GuildMember" (guild);
+
+create table "GuildRequest" (
+	guild uuid not null references "Guild" on delete cascade on update cascade,
+	sub uuid not null references "User" on delete cascade on update cascade,
+	kind smallint not null,
+	created_at timestamptz not null default now(),
+	primary key (guild, sub)
+);
+
+create trigger updated_at_trigger
+	before update on "Guild"
+	for each row
+execute function updated_at_time_func();
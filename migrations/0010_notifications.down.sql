@@ -0,0 +1,3 @@
+drop table "Notification";
+
+alter table "User" drop column role;
@@ -0,0 +1,14 @@
+alter table "User" add column role smallint not null default 0;
+
+create table "Notification" (
+	uuid uuid not null default uuid_generate_v4(),
+	sub uuid not null references "User" on delete cascade on update cascade,
+	kind smallint not null,
+	title varchar(64) not null,
+	body varchar(2048) not null,
+	read_at timestamptz,
+	created_at timestamptz not null default now(),
+	primary key (sub, uuid)
+);
+
+create index on "Notification" (sub, created_at);
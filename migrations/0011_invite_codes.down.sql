@@ -0,0 +1,3 @@
+alter table "User" drop column invite_code;
+
+drop table "InviteCode";
@@ -0,0 +1,11 @@
+create table "InviteCode" (
+	code varchar(32) primary key,
+	creator uuid references "User" on delete set null on update cascade,
+	max_uses integer,
+	uses integer not null default 0,
+	expires_at timestamptz,
+	created_at timestamptz not null default now()
+);
+
+alter table "User" add column invite_code varchar(32)
+	references "InviteCode" on delete set null on update cascade;
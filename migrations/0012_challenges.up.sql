@@ -0,0 +1,4 @@
+create table "SpentChallenge" (
+	jti uuid primary key,
+	expires_at timestamptz not null
+);
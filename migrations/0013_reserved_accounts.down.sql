@@ -0,0 +1,16 @@
+insert into "User" (uuid, username, email, password, created_at) values
+	('00000000-0000-ffff-0000-000000000001', 'server', 'server@example.com', 'nopass', 'epoch'),
+	('00000000-0000-ffff-0000-000000000002', 'admin', 'admin@example.com', 'nopass', 'epoch'),
+	('00000000-0000-ffff-0000-000000000003', 'broadcast', 'broadcast@example.com', 'nopass', 'epoch'),
+	('00000000-0000-ffff-0000-000000000004', 'console', 'console@example.com', 'nopass', 'epoch'),
+	('00000000-0000-ffff-0000-000000000005', 'notify', 'notify@example.com', 'nopass', 'epoch'),
+	('00000000-0000-ffff-0000-000000000006', 'example', 'example@example.com', 'nopass', 'epoch'),
+	('00000000-0000-ffff-0000-000000000007', 'blacklist', 'blacklist@example.com', 'nopass', 'epoch'),
+	('00000000-0000-ffff-0000-000000000008', 'blocklist', 'blocklist@example.com', 'nopass', 'epoch'),
+	('00000000-0000-ffff-0000-000000000009', 'whitelist', 'whitelist@example.com', 'nopass', 'epoch'),
+	('00000000-0000-ffff-0000-00000000000a', 'session', 'session@example.com', 'nopass', 'epoch'),
+	('00000000-0000-ffff-0000-00000000000b', 'web', 'web@example.com', 'nopass', 'epoch'),
+	('00000000-0000-ffff-0000-00000000000d', 'mobile', 'mobile@example.com', 'nopass', 'epoch'),
+	('00000000-0000-ffff-0000-00000000000e', 'game', 'game@example.com', 'nopass', 'epoch'),
+	('00000000-0000-ffff-0000-00000000000f', 'block', 'block@example.com', 'nopass', 'epoch')
+on conflict do nothing;
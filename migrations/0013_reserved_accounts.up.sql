@@ -0,0 +1,18 @@
+/* Reserved names are enforced by the name filter, the placeholder accounts are no longer needed */
+
+delete from "User" where uuid in (
+	'00000000-0000-ffff-0000-000000000001',
+	'00000000-0000-ffff-0000-000000000002',
+	'00000000-0000-ffff-0000-000000000003',
+	'00000000-0000-ffff-0000-000000000004',
+	'00000000-0000-ffff-0000-000000000005',
+	'00000000-0000-ffff-0000-000000000006',
+	'00000000-0000-ffff-0000-000000000007',
+	'00000000-0000-ffff-0000-000000000008',
+	'00000000-0000-ffff-0000-000000000009',
+	'00000000-0000-ffff-0000-00000000000a',
+	'00000000-0000-ffff-0000-00000000000b',
+	'00000000-0000-ffff-0000-00000000000d',
+	'00000000-0000-ffff-0000-00000000000e',
+	'00000000-0000-ffff-0000-00000000000f'
+);
@@ -1,66 +1,309 @@
-use std::{net::SocketAddr, sync::Arc, time::Duration};
+use std::{
+    any::Any,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use axum::{
-    routing::{get, post, put},
-    Router,
+    body::Body,
+    extract::DefaultBodyLimit,
+    http::Request,
+    middleware,
+    response::{IntoResponse, Redirect, Response},
+    routing::{delete, get, patch, post, put},
+    Router, ServiceExt,
 };
 use axum_server::{
     accept::DefaultAcceptor,
     bind, bind_rustls,
     tls_rustls::{RustlsAcceptor, RustlsConfig},
-    Server,
+    Handle, Server,
 };
+use common::hub::{HubApiVersion, HubFeature, HubMode, HubStatus};
+use hyper::StatusCode;
+use metrics_exporter_prometheus::PrometheusHandle;
 use sqlx::postgres::PgPoolOptions;
-use tracing::info;
+use tokio::{signal, sync::watch, time::sleep};
+use tower::Layer;
+use tower_http::catch_panic::CatchPanicLayer;
+use tracing::{error, info};
 
 use crate::{
-    config::Config,
+    challenge::{Challenger, NoChallenge, ProofOfWork},
+    config::{ChallengeProvider, Config, API_VERSIONS, VERSION},
     error::Error,
+    gateway::Gateway,
     handlers::{
-        health, pubkey, status, token_pit, token_refresh, token_revoke, token_revoke_all,
-        user_data, user_info, user_login, user_password, user_register, user_sessions,
+        admin_broadcast, admin_invite_create, admin_invite_revoke, admin_invites,
+        admin_user_chat_ban, admin_user_username, avatar, challenge, docs, friends, friends_accept,
+        friends_block, friends_blocked, friends_decline, friends_presence, friends_remove,
+        friends_request, friends_requests, friends_unblock, gateway, guild, guild_accept,
+        guild_applications, guild_apply, guild_approve, guild_create, guild_decline, guild_disband,
+        guild_invite, guild_invites, guild_kick, guild_leave, guild_member_rank, guild_members,
+        guild_profile, guild_rank_create, guild_rank_delete, guild_rank_update, guild_reject,
+        guild_transfer, guild_update, health_live, health_ready, messages, messages_delete,
+        messages_history, messages_read, messages_send, notification_delete, notification_read,
+        notifications, notifications_read, notifications_unread, openapi, party, party_accept,
+        party_create, party_invite, party_invites, party_kick, party_leave, party_pit,
+        party_transfer, profile, prometheus, pubkey, server_blocks, status, token_pit,
+        token_refresh, token_revoke, token_revoke_all, user_avatar, user_avatar_delete, user_data,
+        user_delete, user_email, user_email_confirm, user_email_revert, user_export,
+        user_export_create, user_export_download, user_info, user_invite_create, user_invites,
+        user_login, user_password, user_profile, user_profile_update, user_register, user_sessions,
+        user_username,
     },
+    jobs,
     keys::Keys,
-    DB,
+    mailer::{LogMailer, Mailer},
+    metrics, migrations,
+    names::NameFilter,
+    seed,
+    storage::{LocalStorage, Storage},
+    telemetry, versioning, DB,
 };
 
 pub struct HubState {
+    pub config: Config,
     pub keys: Keys,
     pub db: DB,
+    pub mailer: Box<dyn Mailer>,
+    pub storage: Box<dyn Storage>,
+    pub gateway: Gateway,
+    pub names: NameFilter,
+    pub challenger: Box<dyn Challenger>,
+    pub started: Instant,
+    /// Set to `true` once the hub starts shutting down
+    pub shutdown: watch::Sender<bool>,
+    pub metrics: PrometheusHandle,
 }
 
 impl HubState {
     pub async fn new(config: &Config) -> Result<Self, Error> {
-        let db = PgPoolOptions::new()
-            .min_connections(config.db_pool_min)
-            .max_connections(config.db_pool_max)
-            .acquire_timeout(Duration::from_secs(config.db_timeout))
-            .connect(&config.db_uri())
-            .await?;
+        let db = connect(config).await?;
+        let keys = config.keys();
+
+        let challenger: Box<dyn Challenger> = match config.challenge {
+            ChallengeProvider::None => Box::new(NoChallenge),
+            ChallengeProvider::ProofOfWork => Box::new(ProofOfWork::new(
+                keys.clone(),
+                db.clone(),
+                config.challenge_difficulty,
+            )),
+        };
 
         Ok(Self {
-            keys: config.keys(),
+            config: config.clone(),
+            keys,
             db,
+            mailer: Box::new(LogMailer),
+            storage: Box::new(LocalStorage::new(config.storage_path.clone())),
+            gateway: Gateway::new(),
+            names: NameFilter::new(config)?,
+            challenger,
+            started: Instant::now(),
+            shutdown: watch::channel(false).0,
+            metrics: metrics::install()?,
         })
     }
 
-    pub fn build_router(self) -> Router {
-        Router::new()
+    pub fn is_shutting_down(&self) -> bool {
+        *self.shutdown.borrow()
+    }
+
+    pub fn status(&self) -> HubStatus {
+        let config = &self.config;
+
+        let mut features = vec![
+            HubFeature::Gateway,
+            HubFeature::Friends,
+            HubFeature::Messages,
+            HubFeature::Parties,
+            HubFeature::Guilds,
+            HubFeature::Notifications,
+            HubFeature::Avatars,
+            HubFeature::Exports,
+        ];
+        if config.invite_quota > 0 {
+            features.push(HubFeature::UserInvites);
+        }
+        if !matches!(config.challenge, ChallengeProvider::None) {
+            features.push(HubFeature::Challenge);
+        }
+        if config.ssl_cert.is_some() && config.ssl_key.is_some() {
+            features.push(HubFeature::Tls);
+        }
+
+        HubStatus {
+            name: config.name.clone(),
+            description: config.description.clone(),
+            region: config.region.clone(),
+            contact: config.contact.clone(),
+            public_url: config.public_url.clone(),
+            hub_version: VERSION.to_string(),
+            api_version: *API_VERSIONS.last().expect("no API versions"),
+            api_versions: API_VERSIONS.to_vec(),
+            mode: config.mode,
+            registration: config.registration,
+            features,
+            uptime: self.started.elapsed().as_secs(),
+        }
+    }
+
+    pub fn build_router(self: Arc<Self>) -> Router {
+        // Panic messages are only exposed in Debug mode
+        let verbose = self.config.mode == HubMode::Debug;
+
+        // Discovery and operational endpoints are not versioned
+        let router = Router::new()
             .route("/", get(status))
             .route("/status", get(status))
-            .route("/health", get(health))
+            .route("/health", get(health_ready))
+            .route("/health/live", get(health_live))
+            .route("/health/ready", get(health_ready))
+            .route("/openapi.json", get(openapi));
+
+        let router = if self.config.metrics_enabled && self.config.metrics_port.is_none() {
+            router.route("/metrics", get(prometheus))
+        } else {
+            router
+        };
+
+        let router = if self.config.mode == HubMode::Debug {
+            // Swagger UI loads its assets relative to the page, hence the trailing slash
+            router
+                .route("/docs", get(|| async { Redirect::permanent("/docs/") }))
+                .route("/docs/", get(docs))
+                .route("/docs/*file", get(docs))
+        } else {
+            router
+        };
+
+        let router = API_VERSIONS.iter().fold(router, |router, version| {
+            router.nest(&format!("/{}", version.as_str()), self.api_router(*version))
+        });
+
+        router
+            .route_layer(middleware::from_fn(metrics::track))
+            .route_layer(middleware::from_fn(telemetry::route))
+            .layer(CatchPanicLayer::custom(move |err| {
+                panic_response(err, verbose)
+            }))
+            .layer(middleware::from_fn(telemetry::trace))
+            .with_state(self)
+    }
+
+    /// Routes of the API version, every version has its own handlers and request and response
+    /// types
+    fn api_router(&self, version: HubApiVersion) -> Router<Arc<Self>> {
+        match version {
+            HubApiVersion::V1 => self.v1_router(),
+        }
+    }
+
+    fn v1_router(&self) -> Router<Arc<Self>> {
+        Router::new()
             .route("/pubkey", get(pubkey))
+            .route("/challenge", get(challenge))
+            .route("/ws", get(gateway))
+            .route("/profile/:id", get(profile))
+            .route("/avatar/:hash/:size", get(avatar))
+            .route("/user", delete(user_delete))
             .route("/user/info", get(user_info))
             .route("/user/data", get(user_data))
             .route("/user/login", post(user_login))
             .route("/user/register", post(user_register))
             .route("/user/password", put(user_password))
+            .route(
+                "/user/profile",
+                get(user_profile).patch(user_profile_update),
+            )
+            .route(
+                "/user/avatar",
+                put(user_avatar)
+                    .delete(user_avatar_delete)
+                    .layer(DefaultBodyLimit::max(self.config.avatar_max_size)),
+            )
+            .route("/user/invites", get(user_invites).post(user_invite_create))
+            .route("/user/username", put(user_username))
+            .route("/user/email", put(user_email))
+            .route("/user/email/confirm", get(user_email_confirm))
+            .route("/user/email/revert", get(user_email_revert))
             .route("/user/sessions", get(user_sessions))
+            .route("/user/export", get(user_export).post(user_export_create))
+            .route("/user/export/download", get(user_export_download))
+            .route("/friends", get(friends))
+            .route("/friends/requests", get(friends_requests))
+            .route("/friends/presence", get(friends_presence))
+            .route("/friends/blocked", get(friends_blocked))
+            .route("/friends/request", post(friends_request))
+            .route("/friends/accept", post(friends_accept))
+            .route("/friends/decline", post(friends_decline))
+            .route("/friends/remove", post(friends_remove))
+            .route("/friends/block", post(friends_block))
+            .route("/friends/unblock", post(friends_unblock))
+            .route("/messages", get(messages).post(messages_send))
+            .route("/messages/:peer", get(messages_history))
+            .route("/messages/:peer/read", put(messages_read))
+            .route("/messages/:peer/:uuid", delete(messages_delete))
+            .route("/party", get(party).post(party_create))
+            .route("/party/invites", get(party_invites))
+            .route("/party/invite", post(party_invite))
+            .route("/party/accept", post(party_accept))
+            .route("/party/leave", post(party_leave))
+            .route("/party/kick", post(party_kick))
+            .route("/party/transfer", post(party_transfer))
+            .route("/party/pit", get(party_pit))
+            .route(
+                "/guild",
+                get(guild)
+                    .post(guild_create)
+                    .patch(guild_update)
+                    .delete(guild_disband),
+            )
+            .route("/guild/invites", get(guild_invites))
+            .route("/guild/applications", get(guild_applications))
+            .route("/guild/invite", post(guild_invite))
+            .route("/guild/apply", post(guild_apply))
+            .route("/guild/accept", post(guild_accept))
+            .route("/guild/decline", post(guild_decline))
+            .route("/guild/approve", post(guild_approve))
+            .route("/guild/reject", post(guild_reject))
+            .route("/guild/leave", post(guild_leave))
+            .route("/guild/kick", post(guild_kick))
+            .route("/guild/transfer", post(guild_transfer))
+            .route("/guild/ranks", post(guild_rank_create))
+            .route(
+                "/guild/ranks/:uuid",
+                patch(guild_rank_update).delete(guild_rank_delete),
+            )
+            .route("/guild/members/:uuid/rank", put(guild_member_rank))
+            .route("/guilds/:id", get(guild_profile))
+            .route("/guilds/:id/members", get(guild_members))
+            .route("/notifications", get(notifications))
+            .route("/notifications/unread", get(notifications_unread))
+            .route("/notifications/read", put(notifications_read))
+            .route("/notifications/:uuid/read", put(notification_read))
+            .route("/notifications/:uuid", delete(notification_delete))
+            .route("/admin/broadcast", post(admin_broadcast))
+            .route(
+                "/admin/invites",
+                get(admin_invites).post(admin_invite_create),
+            )
+            .route("/admin/invites/:code", delete(admin_invite_revoke))
+            .route("/admin/users/:uuid/username", put(admin_user_username))
+            .route("/admin/users/:uuid/chat_ban", put(admin_user_chat_ban))
+            .route("/server/blocks/:uuid", get(server_blocks))
             .route("/token/refresh", get(token_refresh))
             .route("/token/revoke", get(token_revoke))
             .route("/token/revoke_all", get(token_revoke_all))
             .route("/token/pit", get(token_pit))
-            .with_state(Arc::new(self))
+    }
+
+    pub fn build_metrics_router(self: Arc<Self>) -> Router {
+        Router::new()
+            .route("/metrics", get(prometheus))
+            .with_state(self)
     }
 }
 
@@ -69,10 +312,79 @@ pub enum ServerMode {
     Http(Server<DefaultAcceptor>),
 }
 
+fn panic_response(err: Box<dyn Any + Send + 'static>, verbose: bool) -> Response {
+    let message = if let Some(message) = err.downcast_ref::<String>() {
+        message.as_str()
+    } else if let Some(message) = err.downcast_ref::<&str>() {
+        message
+    } else {
+        "unknown panic"
+    };
+
+    error!(message, "Request handler has panicked");
+
+    if verbose {
+        (StatusCode::INTERNAL_SERVER_ERROR, message.to_string()).into_response()
+    } else {
+        StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    }
+}
+
+pub async fn connect(config: &Config) -> Result<DB, Error> {
+    Ok(PgPoolOptions::new()
+        .min_connections(config.db_pool_min)
+        .max_connections(config.db_pool_max)
+        .acquire_timeout(Duration::from_secs(config.db_timeout))
+        .connect(&config.db_uri())
+        .await?)
+}
+
 /// ECG Hub entrypoint
 pub async fn run(config: &Config) -> Result<(), Error> {
     let addr = SocketAddr::new(config.addr.parse()?, config.port);
-    let router = HubState::new(config).await?.build_router();
+    let state = Arc::new(HubState::new(config).await?);
+
+    if !migrations::run(&state.db, config.migration_mode).await? {
+        return Ok(());
+    }
+
+    if config.mode == HubMode::Debug && config.seed_users > 0 {
+        seed::seed_users(&state.db, config.seed_users).await?;
+    }
+
+    let workers = jobs::spawn(&state);
+
+    let handle = Handle::new();
+    let mut handles = vec![handle.clone()];
+
+    if let Some(port) = config.metrics_port.filter(|_| config.metrics_enabled) {
+        let addr = SocketAddr::new(config.addr.parse()?, port);
+        let handle = Handle::new();
+        handles.push(handle.clone());
+
+        info!("Serving metrics on {}", addr);
+        let server = bind(addr)
+            .handle(handle)
+            .serve(state.clone().build_metrics_router().into_make_service());
+        tokio::spawn(async move {
+            if let Err(err) = server.await {
+                error!(?err, "Metrics server has failed");
+            }
+        });
+    }
+
+    tokio::spawn(shutdown(
+        state.clone(),
+        handles,
+        Duration::from_secs(config.shutdown_delay),
+        Duration::from_secs(config.shutdown_timeout),
+    ));
+
+    // Versions are negotiated before routing
+    let router = ServiceExt::<Request<Body>>::into_make_service(
+        middleware::from_fn_with_state(state.clone(), versioning::negotiate)
+            .layer(state.clone().build_router()),
+    );
 
     let server = if let (Some(cert), Some(key)) = (&config.ssl_cert, &config.ssl_key) {
         let tls = RustlsConfig::from_pem_file(cert, key).await?;
@@ -86,9 +398,51 @@ pub async fn run(config: &Config) -> Result<(), Error> {
     info!("Listening on {}", addr);
 
     match server {
-        ServerMode::Https(https) => https.serve(router.into_make_service()).await,
-        ServerMode::Http(http) => http.serve(router.into_make_service()).await,
+        ServerMode::Https(https) => https.handle(handle).serve(router).await,
+        ServerMode::Http(http) => http.handle(handle).serve(router).await,
     }?;
 
+    info!("Stopping background workers");
+    for worker in workers {
+        if let Err(err) = worker.await {
+            error!(?err, "Background worker has failed");
+        }
+    }
+
+    info!("Closing database connections");
+    state.db.close().await;
+
     Ok(())
 }
+
+/// Waits for SIGINT or SIGTERM, reports not ready for `delay` so that probes notice it, then stops
+/// accepting connections and drains the active ones
+async fn shutdown(state: Arc<HubState>, handles: Vec<Handle>, delay: Duration, timeout: Duration) {
+    let interrupt = async {
+        signal::ctrl_c().await.expect("failed to listen for SIGINT");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => {},
+        _ = terminate => {},
+    }
+
+    info!(?delay, "Shutting down, reporting not ready");
+    state.shutdown.send_replace(true);
+    sleep(delay).await;
+
+    info!(?timeout, "Draining connections");
+    for handle in handles {
+        handle.graceful_shutdown(Some(timeout));
+    }
+}
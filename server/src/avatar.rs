@@ -0,0 +1,97 @@
+use std::io::Cursor;
+
+use image::{imageops::FilterType, io::Reader, DynamicImage, ImageFormat, ImageOutputFormat};
+use sha2::{Digest, Sha256};
+
+use crate::{error::Error, models::entities::User, storage::Storage, DB};
+
+/// Sizes (in pixels) of the square avatar variants
+pub const SIZES: [u32; 3] = [64, 128, 256];
+
+#[derive(Debug)]
+pub enum AvatarError {
+    UnsupportedFormat,
+    InvalidDimensions,
+    Malformed,
+}
+
+/// Re-encoded avatar variants
+pub struct Avatar {
+    /// Content hash used as the avatar reference
+    pub hash: String,
+    pub variants: Vec<(u32, Vec<u8>)>,
+}
+
+impl Avatar {
+    /// Decodes PNG/JPEG/WebP image, crops it to a square and re-encodes it into PNG variants.
+    /// Re-encoding drops all metadata of the original file.
+    pub fn process(data: &[u8], max_dimension: u32) -> Result<Self, AvatarError> {
+        let reader = Reader::new(Cursor::new(data))
+            .with_guessed_format()
+            .map_err(|_| AvatarError::Malformed)?;
+
+        if !matches!(
+            reader.format(),
+            Some(ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::WebP)
+        ) {
+            return Err(AvatarError::UnsupportedFormat);
+        }
+
+        let (width, height) = Reader::new(Cursor::new(data))
+            .with_guessed_format()
+            .map_err(|_| AvatarError::Malformed)?
+            .into_dimensions()
+            .map_err(|_| AvatarError::Malformed)?;
+
+        if width.min(height) < SIZES[0] || width.max(height) > max_dimension {
+            return Err(AvatarError::InvalidDimensions);
+        }
+
+        let image = reader.decode().map_err(|_| AvatarError::Malformed)?;
+        let side = width.min(height);
+        let image = image.crop_imm((width - side) / 2, (height - side) / 2, side, side);
+
+        let variants = SIZES
+            .iter()
+            .map(|&size| Ok((size, Self::encode(&image, size)?)))
+            .collect::<Result<Vec<_>, AvatarError>>()?;
+
+        let mut hasher = Sha256::new();
+        for (_, variant) in &variants {
+            hasher.update(variant);
+        }
+
+        Ok(Self {
+            hash: hex::encode(&hasher.finalize()[..16]),
+            variants,
+        })
+    }
+
+    fn encode(image: &DynamicImage, size: u32) -> Result<Vec<u8>, AvatarError> {
+        let mut buf = Cursor::new(Vec::new());
+
+        image
+            .resize_exact(size, size, FilterType::Lanczos3)
+            .write_to(&mut buf, ImageOutputFormat::Png)
+            .map_err(|_| AvatarError::Malformed)?;
+
+        Ok(buf.into_inner())
+    }
+
+    pub fn key(hash: &str, size: u32) -> String {
+        format!("avatars/{hash}/{size}.png")
+    }
+
+    /// Removes variants of the avatar unless another profile still references the same content
+    pub async fn delete_unused(db: &DB, storage: &dyn Storage, hash: &str) -> Result<(), Error> {
+        if User::is_avatar_used(db, hash).await? {
+            return Ok(());
+        }
+
+        for size in SIZES {
+            storage.delete(&Self::key(hash, size)).await?;
+        }
+
+        Ok(())
+    }
+}
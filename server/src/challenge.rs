@@ -0,0 +1,268 @@
+use async_trait::async_trait;
+use common::challenge::{Challenge, ChallengeKind, ChallengeSolution};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::error;
+use uuid::Uuid;
+
+use crate::{
+    keys::Keys,
+    models::{entities::SpentChallenge, tokens::SecurityToken},
+    DB,
+};
+
+/// Anti-bot check performed before registration
+#[async_trait]
+pub trait Challenger: Send + Sync {
+    /// Issues new challenge, `None` if the provider does not need one
+    fn issue(&self) -> Option<Challenge>;
+
+    /// Checks the solution and spends it so that it can not be reused
+    async fn verify(&self, solution: Option<&ChallengeSolution>) -> bool;
+}
+
+/// Record of spent challenges that prevents solutions from being reused
+#[async_trait]
+pub trait SpentChallenges: Send + Sync {
+    /// Marks the challenge as spent, returns `false` if it has been spent before
+    async fn spend(&self, jti: Uuid, exp: i64) -> Result<bool, sqlx::Error>;
+}
+
+#[async_trait]
+impl SpentChallenges for DB {
+    async fn spend(&self, jti: Uuid, exp: i64) -> Result<bool, sqlx::Error> {
+        SpentChallenge::spend(self, jti, exp).await
+    }
+}
+
+/// Lets every registration through
+pub struct NoChallenge;
+
+#[async_trait]
+impl Challenger for NoChallenge {
+    fn issue(&self) -> Option<Challenge> {
+        None
+    }
+
+    async fn verify(&self, _: Option<&ChallengeSolution>) -> bool {
+        true
+    }
+}
+
+/// Contains proof-of-work challenge claims
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ChallengeToken {
+    /// Challenge UUID
+    pub jti: Uuid,
+    /// Expire time (UTC timestamp)
+    pub exp: i64,
+    /// Required number of leading zero bits
+    pub difficulty: u8,
+}
+
+impl SecurityToken for ChallengeToken {
+    /// Challenge lifetime: 5 minutes
+    const LIFETIME: i64 = 60 * 5;
+}
+
+/// Stateless hashcash-style challenge, only spent challenges are stored
+pub struct ProofOfWork<S = DB> {
+    keys: Keys,
+    spent: S,
+    difficulty: u8,
+}
+
+impl<S: SpentChallenges> ProofOfWork<S> {
+    pub fn new(keys: Keys, spent: S, difficulty: u8) -> Self {
+        Self {
+            keys,
+            spent,
+            difficulty,
+        }
+    }
+
+    fn leading_zero_bits(hash: &[u8]) -> u32 {
+        let mut bits = 0;
+        for byte in hash {
+            bits += byte.leading_zeros();
+            if *byte != 0 {
+                break;
+            }
+        }
+        bits
+    }
+}
+
+#[async_trait]
+impl<S: SpentChallenges> Challenger for ProofOfWork<S> {
+    fn issue(&self) -> Option<Challenge> {
+        let claims = ChallengeToken {
+            jti: Uuid::new_v4(),
+            exp: ChallengeToken::new_exp(),
+            difficulty: self.difficulty,
+        };
+
+        Some(Challenge {
+            kind: ChallengeKind::ProofOfWork,
+            token: claims.sign(&self.keys),
+            difficulty: claims.difficulty,
+            expires_at: claims.exp,
+        })
+    }
+
+    async fn verify(&self, solution: Option<&ChallengeSolution>) -> bool {
+        let Some(solution) = solution else {
+            return false;
+        };
+
+        let Ok(claims) = ChallengeToken::decode(&solution.token, &self.keys) else {
+            return false;
+        };
+
+        // Challenges issued before the difficulty was raised are no longer accepted
+        if claims.difficulty < self.difficulty {
+            return false;
+        }
+
+        let hash = Sha256::digest(format!("{}:{}", solution.token, solution.answer));
+        if Self::leading_zero_bits(&hash) < u32::from(claims.difficulty) {
+            return false;
+        }
+
+        match self.spent.spend(claims.jti, claims.exp).await {
+            Ok(spent) => spent,
+            Err(err) => {
+                error!(?err, "Failed to spend challenge");
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, sync::Mutex};
+
+    use super::*;
+
+    /// Keeps spent challenges in memory instead of the database
+    #[derive(Default)]
+    struct MemorySpent(Mutex<HashSet<Uuid>>);
+
+    #[async_trait]
+    impl SpentChallenges for MemorySpent {
+        async fn spend(&self, jti: Uuid, _: i64) -> Result<bool, sqlx::Error> {
+            Ok(self.0.lock().unwrap().insert(jti))
+        }
+    }
+
+    const DIFFICULTY: u8 = 8;
+
+    fn challenger(keys: &Keys) -> ProofOfWork<MemorySpent> {
+        ProofOfWork::new(keys.clone(), MemorySpent::default(), DIFFICULTY)
+    }
+
+    fn bits(token: &str, answer: u64) -> u32 {
+        ProofOfWork::<MemorySpent>::leading_zero_bits(&Sha256::digest(format!("{token}:{answer}")))
+    }
+
+    /// Finds answer whose hash has at least `difficulty` leading zero bits
+    fn solve(token: &str, difficulty: u8) -> ChallengeSolution {
+        let answer = (0..)
+            .find(|answer| bits(token, *answer) >= u32::from(difficulty))
+            .unwrap();
+
+        ChallengeSolution {
+            token: token.to_string(),
+            answer: answer.to_string(),
+        }
+    }
+
+    fn sign(keys: &Keys, exp: i64, difficulty: u8) -> String {
+        ChallengeToken {
+            jti: Uuid::new_v4(),
+            exp,
+            difficulty,
+        }
+        .sign(keys)
+    }
+
+    #[test]
+    fn leading_zero_bits_counts_across_bytes() {
+        let count = ProofOfWork::<MemorySpent>::leading_zero_bits;
+
+        assert_eq!(count(&[0xff, 0x00]), 0);
+        assert_eq!(count(&[0x01, 0xff]), 7);
+        assert_eq!(count(&[0x00, 0x0f, 0x00]), 12);
+        assert_eq!(count(&[0x00, 0x00]), 16);
+        assert_eq!(count(&[]), 0);
+    }
+
+    #[tokio::test]
+    async fn no_challenge_lets_everyone_through() {
+        assert!(NoChallenge.issue().is_none());
+        assert!(NoChallenge.verify(None).await);
+    }
+
+    #[tokio::test]
+    async fn solved_challenge_is_accepted_once() {
+        let keys = Keys::rand();
+        let challenger = challenger(&keys);
+
+        let challenge = challenger.issue().unwrap();
+        assert_eq!(challenge.kind, ChallengeKind::ProofOfWork);
+        assert_eq!(challenge.difficulty, DIFFICULTY);
+
+        let solution = solve(&challenge.token, challenge.difficulty);
+        assert!(challenger.verify(Some(&solution)).await);
+        assert!(!challenger.verify(Some(&solution)).await);
+    }
+
+    #[tokio::test]
+    async fn missing_or_wrong_solution_is_rejected() {
+        let keys = Keys::rand();
+        let challenger = challenger(&keys);
+        let token = challenger.issue().unwrap().token;
+
+        let answer = (0..)
+            .find(|answer| bits(&token, *answer) < u32::from(DIFFICULTY))
+            .unwrap();
+        let wrong = ChallengeSolution {
+            token,
+            answer: answer.to_string(),
+        };
+
+        assert!(!challenger.verify(None).await);
+        assert!(!challenger.verify(Some(&wrong)).await);
+    }
+
+    #[tokio::test]
+    async fn foreign_token_is_rejected() {
+        let challenger = challenger(&Keys::rand());
+        let token = sign(&Keys::rand(), ChallengeToken::new_exp(), DIFFICULTY);
+
+        assert!(!challenger.verify(Some(&solve(&token, DIFFICULTY))).await);
+    }
+
+    #[tokio::test]
+    async fn expired_challenge_is_rejected() {
+        let keys = Keys::rand();
+        let challenger = challenger(&keys);
+        let token = sign(
+            &keys,
+            ChallengeToken::new_exp() - 2 * ChallengeToken::LIFETIME,
+            DIFFICULTY,
+        );
+
+        assert!(!challenger.verify(Some(&solve(&token, DIFFICULTY))).await);
+    }
+
+    #[tokio::test]
+    async fn easier_challenge_is_rejected_after_difficulty_increase() {
+        let keys = Keys::rand();
+        let challenger = challenger(&keys);
+        let token = sign(&keys, ChallengeToken::new_exp(), DIFFICULTY - 4);
+
+        assert!(!challenger.verify(Some(&solve(&token, DIFFICULTY))).await);
+    }
+}
@@ -0,0 +1,437 @@
+use std::{fs::OpenOptions, io::Write, path::PathBuf};
+
+use clap::{Parser, Subcommand, ValueEnum};
+use common::{
+    events::HubEvent,
+    user::{UserRole, UserStatus},
+};
+use ed25519_compact::KeyPair;
+use hex::ToHex;
+use jsonwebtoken::{decode, decode_header, DecodingKey, Validation};
+use serde_json::Value;
+use time::{Duration, OffsetDateTime};
+use uuid::Uuid;
+use validator::validate_email;
+
+use crate::{
+    app,
+    config::Config,
+    error::Error,
+    gateway::Gateway,
+    migrations::{Migrator, MIGRATIONS},
+    models::{
+        entities::{Session, User},
+        parsers::USERNAME_REGEX,
+    },
+    utils::{generate_code, hash_password},
+    DB,
+};
+
+/// Length of generated passwords
+const PASSWORD_LEN: usize = 16;
+
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// TOML config file, environment variables and flags take precedence over it
+    #[arg(short, long, global = true, env = "HUB_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// Overrides config value, e.g. `--set db_pool_max=16`
+    #[arg(short = 's', long = "set", value_name = "KEY=VALUE", value_parser = parse_override, global = true)]
+    pub overrides: Vec<(String, String)>,
+    /// Overrides address to listen on
+    #[arg(long, global = true)]
+    pub addr: Option<String>,
+    /// Overrides port to listen on
+    #[arg(long, global = true)]
+    pub port: Option<u16>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Starts the hub (default)
+    Serve,
+    /// Manages database schema
+    #[command(subcommand)]
+    Migrate(MigrateCommand),
+    /// Writes new Ed25519 private key to the file and prints the public key
+    Keygen {
+        /// File to write hex encoded private key seed to
+        path: PathBuf,
+        /// Overwrites existing file
+        #[arg(long)]
+        force: bool,
+    },
+    /// Manages user accounts
+    #[command(subcommand)]
+    User(UserCommand),
+    /// Manages user sessions
+    #[command(subcommand)]
+    Sessions(SessionsCommand),
+    /// Works with security tokens
+    #[command(subcommand)]
+    Token(TokenCommand),
+    /// Inspects configuration
+    #[command(subcommand)]
+    Config(ConfigCommand),
+}
+
+#[derive(Subcommand, Debug)]
+pub enum MigrateCommand {
+    /// Applies pending migrations
+    Up,
+    /// Reverts applied migrations down to the target version
+    Down {
+        /// Version to keep, `0` reverts everything
+        #[arg(long, default_value_t = 0)]
+        target: i64,
+    },
+    /// Lists migrations and whether they have been applied
+    Status,
+    /// Records migrations up to the target version as applied without running them,
+    /// for schemas that have been applied by hand
+    Baseline {
+        /// Latest version already present in the schema
+        target: i64,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum UserCommand {
+    /// Creates active user, the password is generated unless provided
+    Create {
+        username: String,
+        email: String,
+        #[arg(long)]
+        password: Option<String>,
+        #[arg(long, value_enum, default_value_t = Role::User)]
+        role: Role,
+    },
+    /// Bans the user and ends all of their sessions
+    Ban {
+        /// Username or UUID
+        user: String,
+    },
+    /// Lifts the ban
+    Unban {
+        /// Username or UUID
+        user: String,
+    },
+    /// Bans the user from sending direct messages
+    ChatBan {
+        /// Username or UUID
+        user: String,
+        /// Length of the ban in seconds
+        #[arg(value_parser = clap::value_parser!(i64).range(1..=315360000))]
+        duration: i64,
+    },
+    /// Lifts the chat ban
+    ChatUnban {
+        /// Username or UUID
+        user: String,
+    },
+    /// Sets new password and ends all sessions, the password is generated unless provided
+    ResetPassword {
+        /// Username or UUID
+        user: String,
+        #[arg(long)]
+        password: Option<String>,
+    },
+    /// Changes role of the user
+    SetRole {
+        /// Username or UUID
+        user: String,
+        #[arg(value_enum)]
+        role: Role,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SessionsCommand {
+    /// Deletes expired sessions
+    PurgeExpired,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum TokenCommand {
+    /// Decodes the token and verifies it with the configured key
+    Inspect { token: String },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Prints merged configuration with secrets redacted
+    Print,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Role {
+    User,
+    Moderator,
+    Admin,
+}
+
+impl From<Role> for UserRole {
+    fn from(role: Role) -> Self {
+        match role {
+            Role::User => UserRole::User,
+            Role::Moderator => UserRole::Moderator,
+            Role::Admin => UserRole::Admin,
+        }
+    }
+}
+
+fn parse_override(value: &str) -> Result<(String, String), String> {
+    let (key, value) = value
+        .split_once('=')
+        .ok_or_else(|| format!("`{value}` is not in KEY=VALUE format"))?;
+
+    Ok((key.trim().to_lowercase(), value.to_string()))
+}
+
+impl Cli {
+    /// Config overrides from flags
+    pub fn overrides(&self) -> Vec<(String, String)> {
+        let mut overrides = self.overrides.clone();
+
+        if let Some(addr) = &self.addr {
+            overrides.push((String::from("addr"), addr.clone()));
+        }
+        if let Some(port) = self.port {
+            overrides.push((String::from("port"), port.to_string()));
+        }
+
+        overrides
+    }
+
+    pub async fn run(self, config: &Config) -> Result<(), Error> {
+        let command = self.command.unwrap_or(Command::Serve);
+
+        match command {
+            // Maintenance commands work with incomplete configs, e.g. keygen before TLS is set up
+            Command::Serve => {
+                config.validate()?;
+                app::run(config).await
+            }
+            Command::Migrate(command) => migrate(config, command).await,
+            Command::Keygen { path, force } => keygen(path, force),
+            Command::User(command) => user(config, command).await,
+            Command::Sessions(SessionsCommand::PurgeExpired) => {
+                let purged = Session::purge_expired(&app::connect(config).await?).await?;
+                println!("Purged {purged} expired session(s)");
+
+                Ok(())
+            }
+            Command::Token(TokenCommand::Inspect { token }) => inspect_token(config, &token),
+            Command::Config(ConfigCommand::Print) => {
+                print!("{}", config.redacted());
+
+                Ok(())
+            }
+        }
+    }
+}
+
+async fn migrate(config: &Config, command: MigrateCommand) -> Result<(), Error> {
+    let db = app::connect(config).await?;
+    let mut migrator = Migrator::new(&db).await?;
+
+    match command {
+        MigrateCommand::Up => {
+            let applied = migrator.up().await?;
+            println!("Applied {applied} migration(s)");
+        }
+        MigrateCommand::Down { target } => {
+            let reverted = migrator.down(target).await?;
+            println!("Reverted {reverted} migration(s)");
+        }
+        MigrateCommand::Baseline { target } => {
+            let recorded = migrator.baseline(target).await?;
+            println!("Recorded {recorded} migration(s) as applied");
+        }
+        MigrateCommand::Status => {
+            let pending = migrator.pending().await?;
+
+            for migration in MIGRATIONS {
+                let status = if pending
+                    .iter()
+                    .any(|pending| pending.version == migration.version)
+                {
+                    "pending"
+                } else {
+                    "applied"
+                };
+
+                println!("{:>6} {} ({status})", migration.version, migration.name);
+            }
+        }
+    }
+
+    migrator.unlock().await
+}
+
+fn keygen(path: PathBuf, force: bool) -> Result<(), Error> {
+    let pair = KeyPair::generate();
+
+    let mut options = OpenOptions::new();
+    options.write(true);
+    if force {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    writeln!(
+        options.open(&path)?,
+        "{}",
+        pair.sk.seed().as_slice().encode_hex::<String>()
+    )?;
+
+    println!("{}", pair.pk.as_slice().encode_hex::<String>());
+
+    Ok(())
+}
+
+async fn find_user(db: &DB, user: &str) -> Result<User, Error> {
+    let found = match Uuid::parse_str(user) {
+        Ok(uuid) => User::find_by_uuid(db, uuid).await?,
+        Err(_) => User::find_by_username(db, user).await?,
+    };
+
+    found.ok_or_else(|| Error::CommandError(format!("user {user} not found")))
+}
+
+async fn user(config: &Config, command: UserCommand) -> Result<(), Error> {
+    let db = app::connect(config).await?;
+
+    match command {
+        UserCommand::Create {
+            username,
+            email,
+            password,
+            role,
+        } => {
+            if !USERNAME_REGEX.is_match(&username) || !validate_email(&email) {
+                return Err(Error::CommandError(String::from(
+                    "invalid username or email",
+                )));
+            }
+
+            let password = password.unwrap_or_else(|| {
+                let password = generate_code(PASSWORD_LEN);
+                println!("Password: {password}");
+                password
+            });
+
+            let mut user = User::new(
+                Uuid::new_v4(),
+                username,
+                email,
+                hash_password(&password),
+                UserStatus::Active,
+            );
+            user.insert(&db).await?;
+            user.update_role(&db, role.into()).await?;
+
+            println!("Created user {} ({})", user.username, user.uuid);
+        }
+        UserCommand::Ban { user } => {
+            let mut user = find_user(&db, &user).await?;
+            user.update_status(&db, UserStatus::Banned).await?;
+            Session::delete_all(&db, user.uuid).await?;
+            Gateway::notify(&db, user.uuid, HubEvent::SessionRevoked { session: None }).await;
+            Gateway::leave_game(&db, user.uuid).await;
+
+            println!("Banned user {} ({})", user.username, user.uuid);
+        }
+        UserCommand::Unban { user } => {
+            let mut user = find_user(&db, &user).await?;
+            if user.status != UserStatus::Banned {
+                return Err(Error::CommandError(format!(
+                    "user {} is not banned",
+                    user.username
+                )));
+            }
+            user.update_status(&db, UserStatus::Active).await?;
+
+            println!("Unbanned user {} ({})", user.username, user.uuid);
+        }
+        UserCommand::ChatBan { user, duration } => {
+            let mut user = find_user(&db, &user).await?;
+            let until = OffsetDateTime::now_utc() + Duration::seconds(duration);
+            user.update_chat_ban(&db, Some(until)).await?;
+
+            println!(
+                "Banned user {} ({}) from chat until {until}",
+                user.username, user.uuid
+            );
+        }
+        UserCommand::ChatUnban { user } => {
+            let mut user = find_user(&db, &user).await?;
+            user.update_chat_ban(&db, None).await?;
+
+            println!("Lifted chat ban of user {} ({})", user.username, user.uuid);
+        }
+        UserCommand::ResetPassword { user, password } => {
+            let mut user = find_user(&db, &user).await?;
+            let password = password.unwrap_or_else(|| {
+                let password = generate_code(PASSWORD_LEN);
+                println!("Password: {password}");
+                password
+            });
+
+            user.password = hash_password(&password);
+            user.update_password(&db).await?;
+            Session::delete_all(&db, user.uuid).await?;
+            Gateway::notify(&db, user.uuid, HubEvent::SessionRevoked { session: None }).await;
+            Gateway::leave_game(&db, user.uuid).await;
+
+            println!("Reset password of user {} ({})", user.username, user.uuid);
+        }
+        UserCommand::SetRole { user, role } => {
+            let mut user = find_user(&db, &user).await?;
+            user.update_role(&db, role.into()).await?;
+
+            println!(
+                "Set role of user {} ({}) to {role:?}",
+                user.username, user.uuid
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn inspect_token(config: &Config, token: &str) -> Result<(), Error> {
+    let header = decode_header(token)?;
+
+    let mut validation = Validation::new(header.alg);
+    validation.insecure_disable_signature_validation();
+    validation.validate_exp = false;
+    validation.required_spec_claims.clear();
+    let claims = decode::<Value>(token, &DecodingKey::from_secret(&[]), &validation)?.claims;
+
+    println!("Header: {}", serde_json::to_string_pretty(&header).unwrap());
+    println!("Claims: {}", serde_json::to_string_pretty(&claims).unwrap());
+
+    if config.private_key.is_none() {
+        println!("Verification: skipped, private key is not configured");
+        return Ok(());
+    }
+
+    let keys = config.keys();
+    match decode::<Value>(token, &keys.decoding, &keys.validation) {
+        Ok(_) => println!("Verification: valid"),
+        Err(err) => println!("Verification: failed ({err})"),
+    }
+
+    Ok(())
+}
@@ -1,33 +1,84 @@
-use std::{path::PathBuf, str::FromStr};
+use std::{
+    collections::BTreeMap,
+    env, fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
-use common::hub::{HubApiVersion, HubMode, HubStatus};
+use common::hub::{HubApiVersion, HubMode, RegistrationPolicy};
 use hex::ToHex;
-use serde::{de, Deserialize, Deserializer};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use tracing::{metadata::LevelFilter, warn};
 use tracing_subscriber::EnvFilter;
 
-use crate::keys::Keys;
+use crate::{
+    error::Error,
+    keys::Keys,
+    logging::{LogFormat, LogRotation, REDACTED},
+    migrations::MigrationMode,
+};
 
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
-pub const STATUS: HubStatus = HubStatus {
-    name: "Official ECG Hub",
-    hub_version: VERSION,
-    api_version: HubApiVersion::V1,
-    #[cfg(debug_assertions)]
-    mode: HubMode::Debug,
-    #[cfg(not(debug_assertions))]
-    mode: HubMode::Testing,
-};
+/// API versions served by the hub, the latest one goes last
+pub const API_VERSIONS: &[HubApiVersion] = &[HubApiVersion::V1];
+
+/// Anti-bot challenge required for registration
+#[derive(Deserialize, Serialize, Clone, Copy, Default, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ChallengeProvider {
+    #[default]
+    None,
+    ProofOfWork,
+}
+
+/// Flat key-value configuration layer, keys match `Config` fields
+type Layer = BTreeMap<String, String>;
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Clone)]
 #[serde(default)]
 pub struct Config {
+    // Identity
+    pub name: String,
+    pub description: Option<String>,
+    pub region: Option<String>,
+    /// Contact of the hub operator, e.g. email or website
+    pub contact: Option<String>,
+    pub mode: HubMode,
+    /// Number of test users created on startup in Debug mode, opt-in
+    pub seed_users: u32,
+
     // Main
     pub addr: String,
     pub port: u16,
-    #[serde(deserialize_with = "Config::log_level_deserialize")]
+    pub public_url: String,
+    /// Seconds to report not ready on shutdown before draining connections, lets load balancers
+    /// stop routing to the hub
+    pub shutdown_delay: u64,
+    /// Seconds to wait for active connections to finish on shutdown
+    pub shutdown_timeout: u64,
+    /// Exposes `/metrics` and samples the session gauges
+    pub metrics_enabled: bool,
+    /// Serves `/metrics` on this port, which should be kept private, instead of the main port
+    pub metrics_port: Option<u16>,
+    /// Serves unprefixed routes as deprecated aliases of v1
+    pub legacy_routes: bool,
+    /// Timestamp announced in the `Sunset` header of the unprefixed routes
+    pub legacy_sunset: Option<i64>,
+    #[serde(
+        deserialize_with = "Config::log_level_deserialize",
+        serialize_with = "Config::log_level_serialize"
+    )]
     pub log_level: LevelFilter,
     pub log_verbose: bool,
+    pub log_format: LogFormat,
+    /// Writes logs to rotating files with this path prefix instead of stdout
+    pub log_file: Option<PathBuf>,
+    pub log_rotation: LogRotation,
+    /// Additional field names that are never logged
+    pub log_redact: Vec<String>,
+    /// OTLP/HTTP endpoint of the trace collector, e.g. `http://localhost:4318/v1/traces`
+    pub otlp_endpoint: Option<String>,
+    pub otlp_service_name: String,
 
     // DB
     pub db_addr: String,
@@ -38,18 +89,228 @@ pub struct Config {
     pub db_timeout: u64,
     pub db_pool_min: u32,
     pub db_pool_max: u32,
+    pub migration_mode: MigrationMode,
 
     // SSL
     pub ssl_cert: Option<PathBuf>,
     pub ssl_key: Option<PathBuf>,
 
+    // Storage
+    pub storage_path: PathBuf,
+    pub avatar_max_size: usize,
+    pub avatar_max_dimension: u32,
+
     // Security
-    #[serde(deserialize_with = "Config::private_key_deserialize")]
+    #[serde(
+        deserialize_with = "Config::private_key_deserialize",
+        serialize_with = "Config::private_key_serialize"
+    )]
     pub private_key: Option<[u8; 32]>,
+    /// Keys that game servers use to access server endpoints
+    pub server_keys: Vec<String>,
+
+    // Accounts
+    pub registration: RegistrationPolicy,
+    pub challenge: ChallengeProvider,
+    /// Number of leading zero bits required by the proof-of-work challenge
+    pub challenge_difficulty: u8,
+    /// Number of invite codes each user can create
+    pub invite_quota: i64,
+    /// Usernames that can only be granted by the staff
+    pub reserved_names: Vec<String>,
+    /// Prefixes of usernames that can only be granted by the staff
+    pub reserved_prefixes: Vec<String>,
+    /// Regular expressions matching usernames that can only be granted by the staff
+    pub reserved_patterns: Vec<String>,
+    /// Words that usernames can not contain
+    pub profanity: Vec<String>,
+    pub deletion_grace_period: i64,
+    pub export_lifetime: i64,
+    /// Number of unexpired exports each user can have
+    pub export_limit: i64,
+    /// Seconds after which pending exports are considered interrupted and failed
+    pub export_timeout: i64,
+    pub username_change_cooldown: i64,
+    pub username_reservation_period: i64,
+    pub email_confirmation_period: i64,
+    pub email_revert_period: i64,
+
+    // Messages
+    pub message_retention: i64,
+    pub message_page_limit: i64,
+
+    // Notifications
+    pub notification_retention: i64,
+    pub notification_page_limit: i64,
+
+    // Parties
+    pub party_max_size: i64,
+
+    // Jobs
+    pub jobs_interval: u64,
+
+    // Presence
+    /// Seconds after which presence that is not refreshed by a gateway connection or a game server
+    /// join expires, e.g. because the hub instance holding the connection has crashed
+    pub presence_ttl: u64,
+
+    // Health
+    /// Milliseconds after which a dependency check fails
+    pub health_timeout: u64,
+    /// Milliseconds of database latency after which the hub reports degraded
+    pub health_db_latency: u64,
+    /// Percentage of pool connections in use after which the hub reports degraded
+    pub health_pool_saturation: u8,
 }
 
 impl Config {
     pub const DEFAULT_LOG_FILTER: &[&'static str] = &["hyper=info", "mio=info", "sqlx::query=warn"];
+    pub const ENV_PREFIX: &str = "HUB_";
+    /// Keys that can be read from `<key>_file` paths and are redacted when printed
+    pub const SECRETS: &[&'static str] = &["db_pass", "private_key", "server_keys"];
+    /// Sections of the config file whose keys are not prefixed with the section name
+    pub const GROUPS: &[&'static str] = &["hub", "server", "storage", "security", "accounts"];
+
+    /// Merges config file, `HUB_` environment variables and overrides, later sources take
+    /// precedence
+    pub fn load(path: Option<&Path>, overrides: &[(String, String)]) -> Result<Self, Error> {
+        let mut values = Layer::new();
+
+        if let Some(path) = path {
+            let content = fs::read_to_string(path).map_err(|err| {
+                Error::ConfigError(format!("failed to read {}: {err}", path.display()))
+            })?;
+            let table: toml::Table = content.parse().map_err(|err| {
+                Error::ConfigError(format!("failed to parse {}: {err}", path.display()))
+            })?;
+
+            let mut layer = Layer::new();
+            for (key, value) in table {
+                match value {
+                    toml::Value::Table(table) if Self::GROUPS.contains(&key.as_str()) => {
+                        Self::flatten(&mut layer, "", table)
+                    }
+                    toml::Value::Table(table) => {
+                        Self::flatten(&mut layer, &format!("{key}_"), table)
+                    }
+                    value => {
+                        layer.insert(key, Self::flatten_value(value));
+                    }
+                }
+            }
+
+            let layer = Self::resolve_secrets(layer)?;
+            Self::check_keys(&layer, &path.display().to_string())?;
+            values.extend(layer);
+        }
+
+        let layer = Self::resolve_secrets(
+            env::vars()
+                .filter_map(|(key, value)| {
+                    key.strip_prefix(Self::ENV_PREFIX)
+                        .map(|key| (key.to_lowercase(), value))
+                })
+                .filter(|(key, _)| key != "config")
+                .collect(),
+        )?;
+        Self::check_keys(&layer, "environment")?;
+        values.extend(layer);
+
+        let layer = Self::resolve_secrets(overrides.iter().cloned().collect())?;
+        Self::check_keys(&layer, "overrides")?;
+        values.extend(layer);
+
+        envy::from_iter(values)
+            .map_err(|err| Error::ConfigError(format!("invalid configuration: {err}")))
+    }
+
+    fn flatten(layer: &mut Layer, prefix: &str, table: toml::Table) {
+        for (key, value) in table {
+            match value {
+                toml::Value::Table(table) => {
+                    Self::flatten(layer, &format!("{prefix}{key}_"), table)
+                }
+                value => {
+                    layer.insert(format!("{prefix}{key}"), Self::flatten_value(value));
+                }
+            }
+        }
+    }
+
+    /// Converts the value to the format of environment variables, i.e. lists are comma-separated
+    fn flatten_value(value: toml::Value) -> String {
+        match value {
+            toml::Value::String(value) => value,
+            toml::Value::Array(values) => values
+                .into_iter()
+                .map(Self::flatten_value)
+                .collect::<Vec<_>>()
+                .join(","),
+            value => value.to_string(),
+        }
+    }
+
+    /// Names of all config fields
+    fn field_names() -> Vec<String> {
+        match serde_json::to_value(Self::default()) {
+            Ok(serde_json::Value::Object(fields)) => {
+                fields.into_iter().map(|(key, _)| key).collect()
+            }
+            _ => unreachable!("config is serialized as a map"),
+        }
+    }
+
+    /// Rejects keys that do not match any config field, e.g. misspelled ones
+    fn check_keys(layer: &Layer, source: &str) -> Result<(), Error> {
+        let known = Self::field_names();
+        let unknown = layer
+            .keys()
+            .filter(|key| !known.contains(key))
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+
+        if unknown.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::ConfigError(format!(
+                "unknown keys in {source}: {}",
+                unknown.join(", ")
+            )))
+        }
+    }
+
+    /// Replaces `<secret>_file` keys with the content of the files they point to
+    fn resolve_secrets(mut layer: Layer) -> Result<Layer, Error> {
+        for secret in Self::SECRETS {
+            let Some(path) = layer.remove(&format!("{secret}_file")) else {
+                continue;
+            };
+
+            let value = fs::read_to_string(&path).map_err(|err| {
+                Error::ConfigError(format!("failed to read {secret} from {path}: {err}"))
+            })?;
+            layer.insert(secret.to_string(), value.trim_end().to_string());
+        }
+
+        Ok(layer)
+    }
+
+    /// Renders the config as TOML with secrets redacted
+    pub fn redacted(&self) -> String {
+        let mut table = toml::Table::try_from(self).expect("config is serializable");
+
+        for secret in Self::SECRETS {
+            if let Some(value) = table.get_mut(*secret) {
+                if matches!(value, toml::Value::Array(values) if values.is_empty()) {
+                    continue;
+                }
+
+                *value = toml::Value::String(String::from(REDACTED));
+            }
+        }
+
+        table.to_string()
+    }
 
     fn log_level_deserialize<'de, D>(deserializer: D) -> Result<LevelFilter, D::Error>
     where
@@ -57,6 +318,13 @@ impl Config {
     {
         LevelFilter::from_str(&String::deserialize(deserializer)?).map_err(de::Error::custom)
     }
+
+    fn log_level_serialize<S>(level: &LevelFilter, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&level.to_string().to_lowercase())
+    }
     pub fn log_filter(&self) -> EnvFilter {
         let mut filter = EnvFilter::default().add_directive(self.log_level.into());
 
@@ -79,6 +347,134 @@ impl Config {
         Ok(Some(buf))
     }
 
+    fn private_key_serialize<S>(key: &Option<[u8; 32]>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match key {
+            Some(key) => serializer.serialize_str(&key.encode_hex::<String>()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    /// Checks consistency of the values and requirements of the hub mode
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.db_pool_max == 0 || self.db_pool_min > self.db_pool_max {
+            return Err(Error::ConfigError(format!(
+                "db_pool_min ({}) must not exceed db_pool_max ({}), which must be positive",
+                self.db_pool_min, self.db_pool_max
+            )));
+        }
+
+        if !self.public_url.starts_with("http://") && !self.public_url.starts_with("https://") {
+            return Err(Error::ConfigError(format!(
+                "public_url ({}) must be an http(s) URL",
+                self.public_url
+            )));
+        }
+
+        match (&self.ssl_cert, &self.ssl_key) {
+            (Some(cert), Some(key)) => {
+                for path in [cert, key] {
+                    if !path.is_file() {
+                        return Err(Error::ConfigError(format!(
+                            "TLS file {} does not exist",
+                            path.display()
+                        )));
+                    }
+                }
+            }
+            (None, None) => {}
+            _ => {
+                return Err(Error::ConfigError(String::from(
+                    "ssl_cert and ssl_key must be set together",
+                )))
+            }
+        }
+
+        // Zero disables the cooldown, the grace period or exports; periods that bound how long
+        // data is kept or valid must be positive, otherwise it is purged or expires immediately
+        let minimums: [(&str, i128, i128); 15] = [
+            ("jobs_interval", self.jobs_interval.into(), 1),
+            (
+                "deletion_grace_period",
+                self.deletion_grace_period.into(),
+                0,
+            ),
+            ("export_lifetime", self.export_lifetime.into(), 1),
+            ("export_limit", self.export_limit.into(), 0),
+            ("export_timeout", self.export_timeout.into(), 1),
+            (
+                "username_change_cooldown",
+                self.username_change_cooldown.into(),
+                0,
+            ),
+            (
+                "username_reservation_period",
+                self.username_reservation_period.into(),
+                0,
+            ),
+            (
+                "email_confirmation_period",
+                self.email_confirmation_period.into(),
+                1,
+            ),
+            ("email_revert_period", self.email_revert_period.into(), 1),
+            ("message_retention", self.message_retention.into(), 1),
+            (
+                "notification_retention",
+                self.notification_retention.into(),
+                1,
+            ),
+            ("presence_ttl", self.presence_ttl.into(), 3),
+            ("message_page_limit", self.message_page_limit.into(), 1),
+            (
+                "notification_page_limit",
+                self.notification_page_limit.into(),
+                1,
+            ),
+            ("party_max_size", self.party_max_size.into(), 2),
+        ];
+        for (key, value, min) in minimums {
+            if value < min {
+                return Err(Error::ConfigError(format!(
+                    "{key} ({value}) must be at least {min}"
+                )));
+            }
+        }
+
+        if matches!(self.challenge, ChallengeProvider::ProofOfWork)
+            && !(1..=32).contains(&self.challenge_difficulty)
+        {
+            return Err(Error::ConfigError(format!(
+                "challenge_difficulty ({}) must be between 1 and 32",
+                self.challenge_difficulty
+            )));
+        }
+
+        if self.mode == HubMode::Production {
+            if self.ssl_cert.is_none() || self.ssl_key.is_none() {
+                return Err(Error::ConfigError(String::from(
+                    "production mode requires TLS certificate and key",
+                )));
+            }
+
+            if self.private_key.is_none() {
+                return Err(Error::ConfigError(String::from(
+                    "production mode requires persistent private key",
+                )));
+            }
+
+            if self.log_verbose {
+                return Err(Error::ConfigError(String::from(
+                    "verbose logging is not allowed in production mode",
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn db_uri(&self) -> String {
         format!(
             "postgres://{}:{}@{}:{}/{}",
@@ -96,7 +492,6 @@ impl Config {
         if self.log_verbose {
             warn!(
                 public = keys.pair.pk.as_slice().encode_hex::<String>(),
-                seed = keys.pair.sk.seed().as_slice().encode_hex::<String>(),
                 "New keypair"
             );
         }
@@ -105,19 +500,48 @@ impl Config {
     }
 }
 
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.redacted())
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
+            name: String::from("ECG Hub"),
+            description: None,
+            region: None,
+            contact: None,
+            #[cfg(debug_assertions)]
+            mode: HubMode::Debug,
+            #[cfg(not(debug_assertions))]
+            mode: HubMode::Testing,
+            seed_users: 0,
+
             addr: String::from("0.0.0.0"),
             #[cfg(debug_assertions)]
             port: 8080,
             #[cfg(not(debug_assertions))]
             port: 80,
+            public_url: String::from("http://localhost:8080"),
+            shutdown_delay: 5,
+            shutdown_timeout: 30,
+            metrics_enabled: true,
+            metrics_port: None,
+            legacy_routes: true,
+            legacy_sunset: None,
             #[cfg(debug_assertions)]
             log_level: LevelFilter::DEBUG,
             #[cfg(not(debug_assertions))]
             log_level: LevelFilter::INFO,
             log_verbose: false,
+            log_format: LogFormat::Text,
+            log_file: None,
+            log_rotation: LogRotation::Daily,
+            log_redact: Vec::new(),
+            otlp_endpoint: None,
+            otlp_service_name: String::from("ecg-hub"),
 
             db_addr: String::from("localhost"),
             db_port: 5432,
@@ -127,11 +551,67 @@ impl Default for Config {
             db_timeout: 8,
             db_pool_min: 1,
             db_pool_max: 8,
+            migration_mode: MigrationMode::Auto,
 
             ssl_cert: None,
             ssl_key: None,
 
+            storage_path: PathBuf::from("storage"),
+            avatar_max_size: 4 * 1024 * 1024,
+            avatar_max_dimension: 4096,
+
             private_key: None,
+            server_keys: Vec::new(),
+
+            registration: RegistrationPolicy::Open,
+            challenge: ChallengeProvider::None,
+            challenge_difficulty: 20,
+            invite_quota: 0,
+            reserved_names: [
+                "server",
+                "admin",
+                "broadcast",
+                "console",
+                "notify",
+                "example",
+                "blacklist",
+                "blocklist",
+                "whitelist",
+                "session",
+                "web",
+                "mobile",
+                "game",
+                "block",
+            ]
+            .map(String::from)
+            .to_vec(),
+            reserved_prefixes: ["admin", "moderator", "staff"].map(String::from).to_vec(),
+            reserved_patterns: Vec::new(),
+            profanity: Vec::new(),
+            deletion_grace_period: 60 * 60 * 24 * 14,
+            export_lifetime: 60 * 60 * 24,
+            export_limit: 3,
+            export_timeout: 60 * 15,
+            username_change_cooldown: 60 * 60 * 24 * 30,
+            username_reservation_period: 60 * 60 * 24 * 90,
+            email_confirmation_period: 60 * 60 * 24,
+            email_revert_period: 60 * 60 * 24 * 14,
+
+            message_retention: 60 * 60 * 24 * 365,
+            message_page_limit: 100,
+
+            notification_retention: 60 * 60 * 24 * 90,
+            notification_page_limit: 50,
+
+            party_max_size: 8,
+
+            jobs_interval: 60 * 60,
+
+            presence_ttl: 90,
+
+            health_timeout: 1000,
+            health_db_latency: 250,
+            health_pool_saturation: 90,
         }
     }
 }
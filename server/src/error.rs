@@ -17,6 +17,8 @@ macro_rules! impl_from_error {
 #[derive(Debug)]
 pub enum Error {
     ConfigError(String),
+    MigrationError(String),
+    CommandError(String),
     JWTError(jsonwebtoken::errors::Error),
     SqlxError(sqlx::Error),
     HyperError(hyper::Error),
@@ -40,6 +42,8 @@ impl IntoResponse for Error {
             StatusCode::INTERNAL_SERVER_ERROR,
             match self {
                 Error::ConfigError(err) => err,
+                Error::MigrationError(err) => err,
+                Error::CommandError(err) => err,
                 Error::JWTError(err) => err.to_string(),
                 Error::SqlxError(err) => err.to_string(),
                 Error::HyperError(err) => err.to_string(),
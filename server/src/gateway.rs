@@ -0,0 +1,244 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::extract::ws::{close_code, CloseFrame, Message, WebSocket};
+use common::events::{HubEvent, Presence};
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgListener;
+use tokio::{
+    sync::{
+        broadcast::{self, error::RecvError},
+        watch,
+    },
+    time::{interval_at, sleep, Instant},
+};
+use tracing::{error, warn};
+use uuid::Uuid;
+
+use crate::{
+    app::HubState,
+    models::{
+        entities::{Friendship, UserPresence},
+        tokens::AccessToken,
+    },
+    DB,
+};
+
+/// Event addressed to the gateway clients
+#[derive(Deserialize, Serialize, Debug)]
+pub struct Envelope {
+    /// Recipients of the event, everyone if `None`
+    pub recipients: Option<Vec<Uuid>>,
+    pub event: HubEvent,
+}
+
+impl Envelope {
+    pub fn is_for(&self, sub: Uuid) -> bool {
+        self.recipients
+            .as_ref()
+            .is_none_or(|recipients| recipients.contains(&sub))
+    }
+}
+
+/// Delivers events to the clients connected to this hub instance.
+/// Events are published through Postgres NOTIFY so that every instance receives them.
+pub struct Gateway {
+    sender: broadcast::Sender<Arc<Envelope>>,
+}
+
+impl Gateway {
+    pub const CHANNEL: &str = "hub_events";
+    /// Number of recipients addressed by a single event, keeps the NOTIFY payload below the
+    /// Postgres limit of 8000 bytes together with the largest broadcast of about 4.5 KB
+    pub const CHUNK_SIZE: usize = 50;
+    const CAPACITY: usize = 1024;
+    /// Delay between attempts to start the listener
+    const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+    pub fn new() -> Self {
+        Self {
+            sender: broadcast::channel(Self::CAPACITY).0,
+        }
+    }
+
+    /// Publishes event to all hub instances
+    pub async fn publish(db: &DB, recipients: Option<Vec<Uuid>>, event: HubEvent) {
+        let payload = serde_json::to_string(&Envelope { recipients, event })
+            .expect("failed to serialize gateway event");
+
+        if let Err(err) = sqlx::query("SELECT pg_notify($1, $2)")
+            .bind(Self::CHANNEL)
+            .bind(payload)
+            .execute(db)
+            .await
+        {
+            error!(?err, "Failed to publish gateway event");
+        }
+    }
+
+    /// Publishes event to the users in chunks of `CHUNK_SIZE` recipients
+    pub async fn publish_to(db: &DB, recipients: &[Uuid], event: HubEvent) {
+        for chunk in recipients.chunks(Self::CHUNK_SIZE) {
+            Self::publish(db, Some(chunk.to_vec()), event.clone()).await;
+        }
+    }
+
+    /// Publishes event to the user
+    pub async fn notify(db: &DB, sub: Uuid, event: HubEvent) {
+        Self::publish(db, Some(vec![sub]), event).await
+    }
+
+    /// Publishes presence of the user to their friends
+    pub async fn publish_presence(db: &DB, sub: Uuid, presence: Presence) {
+        match Friendship::list_uuids(db, sub).await {
+            Ok(friends) => {
+                Self::publish_to(
+                    db,
+                    &friends,
+                    HubEvent::Presence {
+                        uuid: sub,
+                        presence,
+                    },
+                )
+                .await
+            }
+            Err(err) => error!(?err, "Failed to retrieve friends for presence update"),
+        }
+    }
+
+    /// Clears game server of the user once their sessions are ended
+    pub async fn leave_game(db: &DB, sub: Uuid) {
+        match UserPresence::leave(db, sub).await {
+            Ok(Some(presence)) => Self::publish_presence(db, sub, presence).await,
+            Ok(None) => {}
+            Err(err) => error!(?err, "Failed to clear game server of the user"),
+        }
+    }
+
+    async fn subscribe(db: &DB) -> Result<PgListener, sqlx::Error> {
+        let mut listener = PgListener::connect_with(db).await?;
+        listener.listen(Self::CHANNEL).await?;
+
+        Ok(listener)
+    }
+
+    /// Forwards notifications from Postgres to the local connections until the hub shuts down
+    pub fn listen(
+        &self,
+        db: DB,
+        mut shutdown: watch::Receiver<bool>,
+    ) -> impl std::future::Future<Output = ()> {
+        let sender = self.sender.clone();
+
+        async move {
+            let mut listener = loop {
+                match Self::subscribe(&db).await {
+                    Ok(listener) => break listener,
+                    Err(err) => error!(?err, "Failed to start gateway listener, retrying"),
+                }
+
+                tokio::select! {
+                    _ = sleep(Self::RETRY_DELAY) => {},
+                    _ = shutdown.changed() => return,
+                }
+            };
+
+            loop {
+                let notification = tokio::select! {
+                    notification = listener.recv() => notification,
+                    _ = shutdown.changed() => break,
+                };
+
+                match notification {
+                    Ok(notification) => {
+                        match serde_json::from_str::<Envelope>(notification.payload()) {
+                            Ok(envelope) => {
+                                // No receivers means there are no local connections
+                                let _ = sender.send(Arc::new(envelope));
+                            }
+                            Err(err) => warn!(?err, "Malformed gateway event"),
+                        }
+                    }
+                    Err(err) => {
+                        // The listener reconnects on the next receive, which fails right away
+                        // while the database is down
+                        error!(?err, "Gateway listener error, retrying");
+
+                        tokio::select! {
+                            _ = sleep(Self::RETRY_DELAY) => {},
+                            _ = shutdown.changed() => break,
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Serves the gateway connection until the client leaves or the session is revoked
+    pub async fn serve(state: Arc<HubState>, mut socket: WebSocket, token: AccessToken) {
+        let AccessToken { iss, sub, .. } = token;
+        let mut events = state.gateway.sender.subscribe();
+        let mut shutdown = state.shutdown.subscribe();
+        let period = Duration::from_secs(state.config.presence_ttl) / 3;
+        let mut heartbeat = interval_at(Instant::now() + period, period);
+
+        match UserPresence::connect(&state.db, sub).await {
+            Ok(true) => Self::publish_presence(&state.db, sub, Presence::Online).await,
+            Ok(false) => {}
+            Err(err) => error!(?err, "Failed to register gateway connection"),
+        }
+
+        loop {
+            tokio::select! {
+                message = socket.recv() => match message {
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => {}
+                },
+                envelope = events.recv() => match envelope {
+                    Ok(envelope) if envelope.is_for(sub) => {
+                        let text = serde_json::to_string(&envelope.event)
+                            .expect("failed to serialize gateway event");
+
+                        if socket.send(Message::Text(text)).await.is_err() {
+                            break;
+                        }
+
+                        if let HubEvent::SessionRevoked { session } = envelope.event {
+                            if session.is_none_or(|session| session == iss) {
+                                let _ = socket.close().await;
+                                break;
+                            }
+                        }
+                    }
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => break,
+                },
+                _ = heartbeat.tick() => {
+                    if let Err(err) = UserPresence::heartbeat(&state.db, sub).await {
+                        error!(?err, "Failed to refresh user presence");
+                    }
+                }
+                _ = shutdown.changed() => {
+                    let _ = socket
+                        .send(Message::Close(Some(CloseFrame {
+                            code: close_code::RESTART,
+                            reason: "Hub is shutting down".into(),
+                        })))
+                        .await;
+                    break;
+                }
+            }
+        }
+
+        match UserPresence::disconnect(&state.db, sub).await {
+            Ok(true) => Self::publish_presence(&state.db, sub, Presence::Offline).await,
+            Ok(false) => {}
+            Err(err) => error!(?err, "Failed to unregister gateway connection"),
+        }
+    }
+}
+
+impl Default for Gateway {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,118 +1,3128 @@
 use std::sync::Arc;
 
-use argon2::{Argon2, PasswordHash, PasswordVerifier};
 use axum::{
-    extract::{Query, State},
-    response::IntoResponse,
-    Json,
+    body::Bytes,
+    extract::{ws::WebSocketUpgrade, Path, Query, State},
+    headers::{authorization::Bearer, Authorization},
+    http::header,
+    response::{IntoResponse, Response},
+    Json, TypedHeader,
 };
 use axum_extra::extract::CookieJar;
 use hyper::StatusCode;
-use sqlx::{postgres::PgDatabaseError, types::Uuid};
-use time::OffsetDateTime;
-use tracing::error;
+use sqlx::types::Uuid;
+use time::{Duration, OffsetDateTime};
+use tokio::task;
+use tracing::{error, info};
+use utoipa::OpenApi;
 use validator::Validate;
 
 use common::{
-    hub::HubStatus,
-    responses::{RegistrationResponse, SessionsResponse},
-    user::{ClientType, UserData, UserInfo, UserSession, UserStatus},
+    challenge::Challenge,
+    events::{FriendPresence, HubEvent, Presence},
+    guild::{
+        GuildInfo, GuildMember, GuildPermissions, GuildProfile, GuildRequest, GuildRequestKind,
+    },
+    hub::{HealthReport, HealthStatus, HubStatus, RegistrationPolicy},
+    notification::{Notification as NotificationData, NotificationKind},
+    profile::{Profile, PublicProfile},
+    responses::{BroadcastResponse, ExportResponse, RegistrationResponse, SessionsResponse},
+    social::{
+        Conversation, DirectMessage, Friend, FriendRequests, FriendStatus, PartyInfo, PartyInvite,
+    },
+    user::{ClientType, ExportStatus, Invite, UserData, UserInfo, UserStatus},
+};
+
+use crate::{
+    app::HubState,
+    avatar::{Avatar, AvatarError, SIZES},
+    gateway::Gateway,
+    health, jobs,
+    keys::Keys,
+    metrics,
+    models::{
+        entities::{
+            EmailChange, Export, FindBy, Friendship, Guild, GuildRank, InviteCode, Message,
+            Notification, Party, Session, User, UserPresence, UsernameHistory,
+        },
+        parsers::{
+            AccountDeleteBody, BroadcastBody, ChatBanBody, EmailChangeBody, EmailTokenQuery,
+            ExportDownloadQuery, FriendBody, GatewayQuery, GuildBody, GuildCreateBody,
+            GuildMemberRankBody, GuildPatchBody, GuildRankBody, GuildRankPatchBody, HistoryQuery,
+            InviteCreateBody, KeyFormat, KeyFormatQuery, LoginBody, MessageBody, NotificationQuery,
+            PITQuery, PartyBody, PasswordChangeBody, ProfilePatchBody, RegisterBody, UserInfoQuery,
+            UsernameChangeBody, USERNAME_REGEX,
+        },
+        tokens::{
+            AccessToken, Admin, ExportToken, PlayerIdentityToken, RefreshToken, SecurityToken,
+            ServerKey, Staff,
+        },
+    },
+    openapi::ApiDoc,
+    utils::{generate_code, hash_password, verify_password},
 };
 
-use crate::{
-    app::HubState,
-    config::STATUS,
-    models::{
-        entities::{FindBy, Session, User},
-        parsers::{
-            KeyFormat, KeyFormatQuery, LoginBody, PITQuery, PasswordChangeBody, RegisterBody,
-            UserInfoQuery,
-        },
-        tokens::{AccessToken, PlayerIdentityToken, RefreshToken, SecurityToken},
-    },
-    utils::hash_password,
-};
+/// Length of the generated invite codes
+const INVITE_CODE_LEN: usize = 12;
+
+/// Public Endpoint: Liveness probe, succeeds as long as the hub process serves requests
+#[utoipa::path(
+    get,
+    path = "/health/live",
+    tag = "health",
+    responses(
+        (status = StatusCode::OK, description = "Hub is alive"),
+    ),
+)]
+pub async fn health_live() -> StatusCode {
+    StatusCode::OK
+}
+
+/// Public Endpoint: Readiness probe, fails if the hub is shutting down or a dependency is down
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    tag = "health",
+    responses(
+        (status = StatusCode::OK, description = "Hub is ready", body = HealthReport),
+        (
+            status = StatusCode::SERVICE_UNAVAILABLE,
+            description = "Hub is shutting down or a dependency is down",
+            body = HealthReport,
+        ),
+    ),
+)]
+pub async fn health_ready(State(state): State<Arc<HubState>>) -> impl IntoResponse {
+    let report = health::check(&state).await;
+
+    let status = match report.status {
+        HealthStatus::Ok | HealthStatus::Degraded => StatusCode::OK,
+        HealthStatus::Down => StatusCode::SERVICE_UNAVAILABLE,
+    };
+
+    (status, Json(report))
+}
+
+/// Metrics Endpoint: Exposes metrics in Prometheus text format, served on the metrics port if set
+pub async fn prometheus(State(state): State<Arc<HubState>>) -> String {
+    metrics::render(&state)
+}
+
+/// Public Endpoint: Returns OpenAPI document of the hub API
+#[utoipa::path(
+    get,
+    path = "/openapi.json",
+    tag = "hub",
+    responses(
+        (status = StatusCode::OK, description = "OpenAPI document", body = Object),
+    ),
+)]
+pub async fn openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// Debug Endpoint: Serves the bundled Swagger UI rendering the OpenAPI document
+pub async fn docs(file: Option<Path<String>>) -> Response {
+    let config = Arc::new(utoipa_swagger_ui::Config::from("/openapi.json"));
+    let file = file.map(|Path(file)| file).unwrap_or_default();
+
+    match utoipa_swagger_ui::serve(&file, config) {
+        Ok(Some(file)) => (
+            [(header::CONTENT_TYPE, file.content_type)],
+            file.bytes.into_owned(),
+        )
+            .into_response(),
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(err) => {
+            error!(?err, "Failed to serve Swagger UI");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Public Endpoint: Returns hub status
+#[utoipa::path(
+    get,
+    path = "/status",
+    tag = "hub",
+    responses(
+        (status = StatusCode::OK, description = "Success", body = HubStatus),
+    ),
+)]
+pub async fn status(State(state): State<Arc<HubState>>) -> Json<HubStatus> {
+    Json(state.status())
+}
+
+/// Public Endpoint: Issues anti-bot challenge that has to be solved before registration
+#[utoipa::path(
+    get,
+    path = "/challenge",
+    tag = "hub",
+    responses(
+        (status = StatusCode::OK, description = "Success", body = Challenge),
+        (status = StatusCode::NO_CONTENT, description = "Challenge is disabled"),
+    ),
+)]
+pub async fn challenge(State(state): State<Arc<HubState>>) -> Result<Json<Challenge>, StatusCode> {
+    state
+        .challenger
+        .issue()
+        .map(Json)
+        .ok_or(StatusCode::NO_CONTENT)
+}
+
+/// Public Endpoint: Returns the public key used to verify the signature of the tokens
+#[utoipa::path(
+    get,
+    path = "/pubkey",
+    tag = "hub",
+    params(KeyFormatQuery),
+    responses(
+        (
+            status = StatusCode::OK,
+            description = "Public key in the requested format",
+            body = String,
+            content_type = "text/plain",
+        ),
+    ),
+)]
+pub async fn pubkey(
+    State(state): State<Arc<HubState>>,
+    Query(format): Query<KeyFormatQuery>,
+) -> String {
+    match format.format {
+        KeyFormat::Hex => state.keys.public_hex.clone(),
+        KeyFormat::Pem => state.keys.public_pem.clone(),
+    }
+}
+
+// User
+
+/// Public Endpoint: Looks up for the uuid and username of the account
+#[utoipa::path(
+    get,
+    path = "/user/info",
+    tag = "user",
+    params(UserInfoQuery),
+    responses(
+        (status = StatusCode::OK, description = "Success", body = UserInfo),
+        (status = StatusCode::BAD_REQUEST, description = "Invalid request"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+    ),
+)]
+pub async fn user_info(
+    State(state): State<Arc<HubState>>,
+    user_id: Query<UserInfoQuery>,
+) -> Result<Json<UserInfo>, StatusCode> {
+    if user_id.validate().is_ok() {
+        Ok(Json(
+            match (user_id.uuid, &user_id.username) {
+                (Some(uuid), _) => {
+                    if let Some(user) = User::find_by_uuid(&state.db, uuid)
+                        .await
+                        .expect("failed to retrieve user data from db")
+                    {
+                        user
+                    } else {
+                        return Err(StatusCode::NOT_FOUND);
+                    }
+                }
+                (None, Some(username)) => {
+                    if let Some(user) = User::find_by_username(&state.db, username)
+                        .await
+                        .expect("failed to retrieve user data from db")
+                    {
+                        user
+                    } else if let Some(user) = User::find_by_former_username(&state.db, username)
+                        .await
+                        .expect("failed to retrieve user data from db")
+                    {
+                        user
+                    } else {
+                        return Err(StatusCode::NOT_FOUND);
+                    }
+                }
+                _ => return Err(StatusCode::BAD_REQUEST),
+            }
+            .into(),
+        ))
+    } else {
+        Err(StatusCode::BAD_REQUEST)
+    }
+}
+
+/// Private Endpoint: Allows the user to retrieve their personal data
+#[utoipa::path(
+    get,
+    path = "/user/data",
+    tag = "user",
+    responses(
+        (status = StatusCode::OK, description = "Success", body = UserData),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn user_data(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+) -> Result<Json<UserData>, StatusCode> {
+    Ok(Json(
+        if let Some(user) = User::find_by_uuid(&state.db, sub)
+            .await
+            .expect("failed to retrieve user info from db")
+        {
+            user.into()
+        } else {
+            return Err(StatusCode::NOT_FOUND);
+        },
+    ))
+}
+
+// Profile
+
+/// Private Endpoint: Returns the profile of the user including privacy settings
+#[utoipa::path(
+    get,
+    path = "/user/profile",
+    tag = "user",
+    responses(
+        (status = StatusCode::OK, description = "Success", body = Profile),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn user_profile(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+) -> Result<Json<Profile>, StatusCode> {
+    match User::find_by_uuid(&state.db, sub)
+        .await
+        .expect("failed to retrieve user data from db")
+    {
+        Some(user) => Ok(Json(user.profile())),
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+/// Private Endpoint: Partially updates the profile of the user
+#[utoipa::path(
+    patch,
+    path = "/user/profile",
+    tag = "user",
+    request_body = ProfilePatchBody,
+    responses(
+        (status = StatusCode::OK, description = "Success", body = Profile),
+        (status = StatusCode::BAD_REQUEST, description = "Invalid request"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn user_profile_update(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+    Json(body): Json<ProfilePatchBody>,
+) -> Result<Json<Profile>, StatusCode> {
+    if body.validate().is_err() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let Some(mut user) = User::find_by_uuid(&state.db, sub)
+        .await
+        .expect("failed to retrieve user data from db")
+    else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    let mut profile = user.profile();
+    body.apply(&mut profile);
+
+    user.update_profile(&state.db, &profile)
+        .await
+        .expect("failed to update user profile");
+
+    Ok(Json(profile))
+}
+
+/// Private Endpoint: Uploads PNG/JPEG/WebP avatar and sets it in the profile
+#[utoipa::path(
+    put,
+    path = "/user/avatar",
+    tag = "user",
+    request_body(
+        content = String,
+        description = "PNG, JPEG or WebP image",
+        content_type = "application/octet-stream"
+    ),
+    responses(
+        (status = StatusCode::OK, description = "Success", body = Profile),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+        (status = StatusCode::UNSUPPORTED_MEDIA_TYPE, description = "Unsupported image format"),
+        (status = StatusCode::UNPROCESSABLE_ENTITY, description = "Image can not be decoded"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn user_avatar(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+    body: Bytes,
+) -> Result<Json<Profile>, StatusCode> {
+    let Some(mut user) = User::find_by_uuid(&state.db, sub)
+        .await
+        .expect("failed to retrieve user data from db")
+    else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    let max_dimension = state.config.avatar_max_dimension;
+    let avatar = task::spawn_blocking(move || Avatar::process(&body, max_dimension))
+        .await
+        .expect("avatar processing task panicked")
+        .map_err(|err| match err {
+            AvatarError::UnsupportedFormat => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            AvatarError::InvalidDimensions | AvatarError::Malformed => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+        })?;
+
+    for (size, data) in &avatar.variants {
+        if let Err(err) = state
+            .storage
+            .put(&Avatar::key(&avatar.hash, *size), data)
+            .await
+        {
+            error!(?err);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    let mut profile = user.profile();
+    let previous = profile.avatar.replace(avatar.hash);
+
+    user.update_profile(&state.db, &profile)
+        .await
+        .expect("failed to update user profile");
+
+    if let Some(previous) = previous.filter(|previous| Some(previous) != profile.avatar.as_ref()) {
+        if let Err(err) = Avatar::delete_unused(&state.db, &*state.storage, &previous).await {
+            error!(?err, "Failed to delete previous avatar");
+        }
+    }
+
+    Ok(Json(profile))
+}
+
+/// Private Endpoint: Removes avatar from the profile and deletes its files unless they are shared
+#[utoipa::path(
+    delete,
+    path = "/user/avatar",
+    tag = "user",
+    responses(
+        (status = StatusCode::OK, description = "Success", body = Profile),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn user_avatar_delete(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+) -> Result<Json<Profile>, StatusCode> {
+    let Some(mut user) = User::find_by_uuid(&state.db, sub)
+        .await
+        .expect("failed to retrieve user data from db")
+    else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    let mut profile = user.profile();
+    let previous = profile.avatar.take();
+
+    user.update_profile(&state.db, &profile)
+        .await
+        .expect("failed to update user profile");
+
+    if let Some(previous) = previous {
+        if let Err(err) = Avatar::delete_unused(&state.db, &*state.storage, &previous).await {
+            error!(?err, "Failed to delete avatar");
+        }
+    }
+
+    Ok(Json(profile))
+}
+
+/// Public Endpoint: Serves avatar variant by its content hash
+#[utoipa::path(
+    get,
+    path = "/avatar/{hash}/{size}",
+    tag = "profile",
+    params(
+        ("hash" = String, Path, description = "Content hash of the avatar"),
+        ("size" = u32, Path, description = "Size of the avatar variant in pixels"),
+    ),
+    responses(
+        (
+            status = StatusCode::OK,
+            description = "Avatar image",
+            body = String,
+            content_type = "image/png",
+        ),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+    ),
+)]
+pub async fn avatar(
+    State(state): State<Arc<HubState>>,
+    Path((hash, size)): Path<(String, u32)>,
+) -> Result<impl IntoResponse, StatusCode> {
+    if !SIZES.contains(&size) || hash.len() != 32 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    match state.storage.get(&Avatar::key(&hash, size)).await {
+        Ok(Some(data)) => Ok((
+            [
+                (header::CONTENT_TYPE, String::from("image/png")),
+                (
+                    header::CACHE_CONTROL,
+                    String::from("public, max-age=31536000, immutable"),
+                ),
+                (header::ETAG, format!("\"{hash}\"")),
+            ],
+            data,
+        )),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(err) => {
+            error!(?err);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Public Endpoint: Returns the profile of the player by uuid or username respecting privacy settings
+#[utoipa::path(
+    get,
+    path = "/profile/{id}",
+    tag = "profile",
+    params(("id" = String, Path, description = "UUID or username of the player")),
+    responses(
+        (status = StatusCode::OK, description = "Success", body = PublicProfile),
+        (status = StatusCode::BAD_REQUEST, description = "Invalid request"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+    ),
+)]
+pub async fn profile(
+    State(state): State<Arc<HubState>>,
+    Path(id): Path<String>,
+) -> Result<Json<PublicProfile>, StatusCode> {
+    let user = if let Ok(uuid) = Uuid::parse_str(&id) {
+        User::find_by_uuid(&state.db, uuid).await
+    } else if USERNAME_REGEX.is_match(&id) {
+        User::find_by_username(&state.db, &id).await
+    } else {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    .expect("failed to retrieve user data from db");
+
+    match user {
+        Some(user) if user.status != UserStatus::Deleted => Ok(Json(user.into())),
+        _ => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+// Friends
+
+/// Private Endpoint: Lists friends of the user
+#[utoipa::path(
+    get,
+    path = "/friends",
+    tag = "friends",
+    responses(
+        (status = StatusCode::OK, description = "Success", body = Vec<Friend>),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn friends(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+) -> Json<Vec<Friend>> {
+    Json(
+        Friendship::list(&state.db, sub, FriendStatus::Accepted)
+            .await
+            .expect("failed to retrieve friends from db"),
+    )
+}
+
+/// Private Endpoint: Lists incoming and outgoing friend requests
+#[utoipa::path(
+    get,
+    path = "/friends/requests",
+    tag = "friends",
+    responses(
+        (status = StatusCode::OK, description = "Success", body = FriendRequests),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn friends_requests(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+) -> Json<FriendRequests> {
+    Json(FriendRequests {
+        incoming: Friendship::list_incoming(&state.db, sub)
+            .await
+            .expect("failed to retrieve friend requests from db"),
+        outgoing: Friendship::list(&state.db, sub, FriendStatus::Pending)
+            .await
+            .expect("failed to retrieve friend requests from db"),
+    })
+}
+
+/// Private Endpoint: Lists users blocked by the user
+#[utoipa::path(
+    get,
+    path = "/friends/blocked",
+    tag = "friends",
+    responses(
+        (status = StatusCode::OK, description = "Success", body = Vec<Friend>),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn friends_blocked(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+) -> Json<Vec<Friend>> {
+    Json(
+        Friendship::list(&state.db, sub, FriendStatus::Blocked)
+            .await
+            .expect("failed to retrieve blocked users from db"),
+    )
+}
+
+/// Private Endpoint: Sends friend request, or accepts the one sent by the target
+#[utoipa::path(
+    post,
+    path = "/friends/request",
+    tag = "friends",
+    request_body = FriendBody,
+    responses(
+        (status = StatusCode::OK, description = "Success"),
+        (status = StatusCode::CREATED, description = "Created"),
+        (status = StatusCode::NOT_MODIFIED, description = "Nothing has changed"),
+        (status = StatusCode::BAD_REQUEST, description = "Invalid request"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token or not allowed"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn friends_request(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+    Json(FriendBody { uuid }): Json<FriendBody>,
+) -> StatusCode {
+    if uuid == sub {
+        return StatusCode::BAD_REQUEST;
+    }
+
+    match User::find_by_uuid(&state.db, uuid)
+        .await
+        .expect("failed to retrieve user data from db")
+    {
+        Some(user) if user.status != UserStatus::Deleted => {}
+        _ => return StatusCode::NOT_FOUND,
+    }
+
+    let Some(user) = User::find_by_uuid(&state.db, sub)
+        .await
+        .expect("failed to retrieve user data from db")
+    else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    if Friendship::is_blocked(&state.db, sub, uuid)
+        .await
+        .expect("failed to check blocks")
+    {
+        return StatusCode::FORBIDDEN;
+    }
+
+    if Friendship::accept(&state.db, sub, uuid)
+        .await
+        .expect("failed to accept friend request")
+    {
+        Gateway::notify(
+            &state.db,
+            uuid,
+            HubEvent::FriendAccepted {
+                uuid: sub,
+                username: user.username,
+            },
+        )
+        .await;
+        return StatusCode::OK;
+    }
+
+    if Friendship::find(&state.db, sub, uuid)
+        .await
+        .expect("failed to retrieve friendship from db")
+        .is_some()
+    {
+        return StatusCode::NOT_MODIFIED;
+    }
+
+    Friendship::request(&state.db, sub, uuid)
+        .await
+        .expect("failed to create friend request");
+    send_notification(
+        &state,
+        uuid,
+        NotificationKind::Social,
+        "Friend request",
+        &format!("{} has sent you a friend request.", user.username),
+    )
+    .await;
+    Gateway::notify(
+        &state.db,
+        uuid,
+        HubEvent::FriendRequest {
+            uuid: sub,
+            username: user.username,
+        },
+    )
+    .await;
+
+    StatusCode::CREATED
+}
+
+/// Private Endpoint: Accepts friend request
+#[utoipa::path(
+    post,
+    path = "/friends/accept",
+    tag = "friends",
+    request_body = FriendBody,
+    responses(
+        (status = StatusCode::OK, description = "Success"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn friends_accept(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+    Json(FriendBody { uuid }): Json<FriendBody>,
+) -> StatusCode {
+    let Some(user) = User::find_by_uuid(&state.db, sub)
+        .await
+        .expect("failed to retrieve user data from db")
+    else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    if Friendship::accept(&state.db, sub, uuid)
+        .await
+        .expect("failed to accept friend request")
+    {
+        Gateway::notify(
+            &state.db,
+            uuid,
+            HubEvent::FriendAccepted {
+                uuid: sub,
+                username: user.username,
+            },
+        )
+        .await;
+        StatusCode::OK
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+/// Private Endpoint: Declines friend request
+#[utoipa::path(
+    post,
+    path = "/friends/decline",
+    tag = "friends",
+    request_body = FriendBody,
+    responses(
+        (status = StatusCode::OK, description = "Success"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn friends_decline(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+    Json(FriendBody { uuid }): Json<FriendBody>,
+) -> StatusCode {
+    if Friendship::decline(&state.db, sub, uuid)
+        .await
+        .expect("failed to decline friend request")
+    {
+        StatusCode::OK
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+/// Private Endpoint: Removes friend or cancels outgoing friend request
+#[utoipa::path(
+    post,
+    path = "/friends/remove",
+    tag = "friends",
+    request_body = FriendBody,
+    responses(
+        (status = StatusCode::OK, description = "Success"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn friends_remove(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+    Json(FriendBody { uuid }): Json<FriendBody>,
+) -> StatusCode {
+    if Friendship::remove(&state.db, sub, uuid)
+        .await
+        .expect("failed to remove friend")
+    {
+        StatusCode::OK
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+/// Private Endpoint: Blocks user, ending friendship and pending requests
+#[utoipa::path(
+    post,
+    path = "/friends/block",
+    tag = "friends",
+    request_body = FriendBody,
+    responses(
+        (status = StatusCode::OK, description = "Success"),
+        (status = StatusCode::BAD_REQUEST, description = "Invalid request"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn friends_block(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+    Json(FriendBody { uuid }): Json<FriendBody>,
+) -> StatusCode {
+    if uuid == sub {
+        return StatusCode::BAD_REQUEST;
+    }
+
+    if User::find_by_uuid(&state.db, uuid)
+        .await
+        .expect("failed to retrieve user data from db")
+        .is_none()
+    {
+        return StatusCode::NOT_FOUND;
+    }
+
+    Friendship::block(&state.db, sub, uuid)
+        .await
+        .expect("failed to block user");
+
+    StatusCode::OK
+}
+
+/// Private Endpoint: Unblocks user
+#[utoipa::path(
+    post,
+    path = "/friends/unblock",
+    tag = "friends",
+    request_body = FriendBody,
+    responses(
+        (status = StatusCode::OK, description = "Success"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn friends_unblock(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+    Json(FriendBody { uuid }): Json<FriendBody>,
+) -> StatusCode {
+    if Friendship::unblock(&state.db, sub, uuid)
+        .await
+        .expect("failed to unblock user")
+    {
+        StatusCode::OK
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+/// Private Endpoint: Lists presence of the friends
+#[utoipa::path(
+    get,
+    path = "/friends/presence",
+    tag = "friends",
+    responses(
+        (status = StatusCode::OK, description = "Success", body = Vec<FriendPresence>),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn friends_presence(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+) -> Json<Vec<FriendPresence>> {
+    Json(
+        UserPresence::find_friends(&state.db, sub)
+            .await
+            .expect("failed to retrieve presence from db"),
+    )
+}
+
+/// Private Endpoint: Opens WebSocket connection that receives hub events.
+/// Access token can be passed in the `token` query parameter for clients that can't set headers.
+#[utoipa::path(
+    get,
+    path = "/ws",
+    tag = "gateway",
+    params(GatewayQuery),
+    responses(
+        (
+            status = StatusCode::SWITCHING_PROTOCOLS,
+            description = "Upgraded to WebSocket connection",
+        ),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn gateway(
+    State(state): State<Arc<HubState>>,
+    bearer: Option<TypedHeader<Authorization<Bearer>>>,
+    Query(query): Query<GatewayQuery>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, StatusCode> {
+    let token = match (&bearer, &query.token) {
+        (Some(TypedHeader(Authorization(bearer))), _) => bearer.token(),
+        (None, Some(token)) => token.as_str(),
+        (None, None) => return Err(StatusCode::EXPECTATION_FAILED),
+    };
+    let token = AccessToken::decode(token, &state.keys).map_err(|_| StatusCode::FORBIDDEN)?;
+
+    Ok(ws.on_upgrade(move |socket| Gateway::serve(state, socket, token)))
+}
+
+/// Server Endpoint: Lists users that block or are blocked by the player
+#[utoipa::path(
+    get,
+    path = "/server/blocks/{uuid}",
+    tag = "server",
+    params(("uuid" = Uuid, Path, description = "UUID of the player")),
+    responses(
+        (status = StatusCode::OK, description = "Success", body = Vec<Uuid>),
+        (status = StatusCode::FORBIDDEN, description = "Invalid server key"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing server key"),
+    ),
+    security(("server_key" = [])),
+)]
+pub async fn server_blocks(
+    State(state): State<Arc<HubState>>,
+    _: ServerKey,
+    Path(uuid): Path<Uuid>,
+) -> Json<Vec<Uuid>> {
+    Json(
+        Friendship::list_blocks(&state.db, uuid)
+            .await
+            .expect("failed to retrieve blocks from db"),
+    )
+}
+
+// Messages
+
+/// Private Endpoint: Sends direct message to a friend
+#[utoipa::path(
+    post,
+    path = "/messages",
+    tag = "messages",
+    request_body = MessageBody,
+    responses(
+        (status = StatusCode::CREATED, description = "Created", body = DirectMessage),
+        (status = StatusCode::BAD_REQUEST, description = "Invalid request"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token or not allowed"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn messages_send(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+    Json(body): Json<MessageBody>,
+) -> Result<(StatusCode, Json<DirectMessage>), StatusCode> {
+    if body.validate().is_err() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let Some(user) = User::find_by_uuid(&state.db, sub)
+        .await
+        .expect("failed to retrieve user data from db")
+    else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    if user.is_chat_banned()
+        || !Friendship::are_friends(&state.db, sub, body.recipient)
+            .await
+            .expect("failed to retrieve friendship from db")
+    {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let message: DirectMessage = Message::new(&state.db, sub, body.recipient, &body.body)
+        .await
+        .expect("failed to create message")
+        .into();
+
+    Gateway::notify(
+        &state.db,
+        body.recipient,
+        HubEvent::DirectMessage {
+            message: message.clone(),
+        },
+    )
+    .await;
+
+    Ok((StatusCode::CREATED, Json(message)))
+}
+
+/// Private Endpoint: Lists conversations with the last message and unread count
+#[utoipa::path(
+    get,
+    path = "/messages",
+    tag = "messages",
+    responses(
+        (status = StatusCode::OK, description = "Success", body = Vec<Conversation>),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn messages(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+) -> Json<Vec<Conversation>> {
+    Json(
+        Message::conversations(&state.db, sub)
+            .await
+            .expect("failed to retrieve conversations from db"),
+    )
+}
+
+/// Private Endpoint: Returns page of the conversation history, newest first
+#[utoipa::path(
+    get,
+    path = "/messages/{peer}",
+    tag = "messages",
+    params(
+        ("peer" = Uuid, Path, description = "UUID of the other participant"),
+        HistoryQuery,
+    ),
+    responses(
+        (status = StatusCode::OK, description = "Success", body = Vec<DirectMessage>),
+        (status = StatusCode::BAD_REQUEST, description = "Invalid request"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn messages_history(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+    Path(peer): Path<Uuid>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<Vec<DirectMessage>>, StatusCode> {
+    let limit = query
+        .limit
+        .unwrap_or(state.config.message_page_limit)
+        .clamp(1, state.config.message_page_limit);
+
+    Ok(Json(
+        Message::history(&state.db, sub, peer, query.before, limit)
+            .await
+            .expect("failed to retrieve messages from db")
+            .into_iter()
+            .map(DirectMessage::from)
+            .collect(),
+    ))
+}
+
+/// Private Endpoint: Marks all messages from the peer as read
+#[utoipa::path(
+    put,
+    path = "/messages/{peer}/read",
+    tag = "messages",
+    params(("peer" = Uuid, Path, description = "UUID of the other participant")),
+    responses(
+        (status = StatusCode::OK, description = "Success"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn messages_read(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+    Path(peer): Path<Uuid>,
+) -> StatusCode {
+    if Message::mark_read(&state.db, sub, peer)
+        .await
+        .expect("failed to mark messages as read")
+        > 0
+    {
+        Gateway::notify(
+            &state.db,
+            peer,
+            HubEvent::MessagesRead {
+                uuid: sub,
+                until: OffsetDateTime::now_utc().unix_timestamp(),
+            },
+        )
+        .await;
+    }
+
+    StatusCode::OK
+}
+
+/// Private Endpoint: Deletes the message for the user only
+#[utoipa::path(
+    delete,
+    path = "/messages/{peer}/{uuid}",
+    tag = "messages",
+    params(
+        ("peer" = Uuid, Path, description = "UUID of the other participant"),
+        ("uuid" = Uuid, Path, description = "UUID of the message"),
+    ),
+    responses(
+        (status = StatusCode::OK, description = "Success"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn messages_delete(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+    Path((peer, uuid)): Path<(Uuid, Uuid)>,
+) -> StatusCode {
+    if Message::delete_for(&state.db, sub, peer, uuid)
+        .await
+        .expect("failed to delete message")
+    {
+        StatusCode::OK
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+// Party
+
+async fn party_updated(state: &HubState, party: &Party) {
+    let members = party
+        .members(&state.db)
+        .await
+        .expect("failed to retrieve party members from db")
+        .into_iter()
+        .map(|member| member.uuid)
+        .collect();
+
+    Gateway::publish(
+        &state.db,
+        Some(members),
+        HubEvent::PartyUpdated {
+            party: Some(party.uuid),
+        },
+    )
+    .await;
+}
+
+/// Private Endpoint: Returns the party of the user
+#[utoipa::path(
+    get,
+    path = "/party",
+    tag = "party",
+    responses(
+        (status = StatusCode::OK, description = "Success", body = PartyInfo),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn party(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+) -> Result<Json<PartyInfo>, StatusCode> {
+    match Party::find_by_member(&state.db, sub)
+        .await
+        .expect("failed to retrieve party from db")
+    {
+        Some(party) => Ok(Json(
+            party
+                .info(&state.db)
+                .await
+                .expect("failed to retrieve party members from db"),
+        )),
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+/// Private Endpoint: Creates new party led by the user
+#[utoipa::path(
+    post,
+    path = "/party",
+    tag = "party",
+    responses(
+        (status = StatusCode::CREATED, description = "Created", body = PartyInfo),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::CONFLICT, description = "User is already in a party"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn party_create(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+) -> Result<(StatusCode, Json<PartyInfo>), StatusCode> {
+    if Party::find_by_member(&state.db, sub)
+        .await
+        .expect("failed to retrieve party from db")
+        .is_some()
+    {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let party = match Party::new(&state.db, sub).await {
+        Ok(party) => party,
+        Err(sqlx::Error::Database(err)) if err.constraint() == Some("PartyMember_pkey") => {
+            return Err(StatusCode::CONFLICT);
+        }
+        Err(err) => {
+            error!(?err);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    Ok((
+        StatusCode::CREATED,
+        Json(
+            party
+                .info(&state.db)
+                .await
+                .expect("failed to retrieve party members from db"),
+        ),
+    ))
+}
+
+/// Private Endpoint: Lists party invites received by the user
+#[utoipa::path(
+    get,
+    path = "/party/invites",
+    tag = "party",
+    responses(
+        (status = StatusCode::OK, description = "Success", body = Vec<PartyInvite>),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn party_invites(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+) -> Json<Vec<PartyInvite>> {
+    Json(
+        Party::invites(&state.db, sub)
+            .await
+            .expect("failed to retrieve party invites from db"),
+    )
+}
+
+/// Private Endpoint: Invites a friend to the party of the user
+#[utoipa::path(
+    post,
+    path = "/party/invite",
+    tag = "party",
+    request_body = FriendBody,
+    responses(
+        (status = StatusCode::CREATED, description = "Created"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token or not allowed"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn party_invite(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+    Json(FriendBody { uuid }): Json<FriendBody>,
+) -> StatusCode {
+    let Some(party) = Party::find_by_member(&state.db, sub)
+        .await
+        .expect("failed to retrieve party from db")
+    else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    if party.leader != sub {
+        return StatusCode::FORBIDDEN;
+    }
+
+    if !Friendship::are_friends(&state.db, sub, uuid)
+        .await
+        .expect("failed to retrieve friendship from db")
+    {
+        return StatusCode::FORBIDDEN;
+    }
+
+    let Some(user) = User::find_by_uuid(&state.db, sub)
+        .await
+        .expect("failed to retrieve user data from db")
+    else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    party
+        .invite(&state.db, uuid, sub)
+        .await
+        .expect("failed to create party invite");
+    Gateway::notify(
+        &state.db,
+        uuid,
+        HubEvent::PartyInvite {
+            party: party.uuid,
+            uuid: sub,
+            username: user.username,
+        },
+    )
+    .await;
+
+    StatusCode::CREATED
+}
+
+/// Private Endpoint: Joins the party using the received invite
+#[utoipa::path(
+    post,
+    path = "/party/accept",
+    tag = "party",
+    request_body = PartyBody,
+    responses(
+        (status = StatusCode::OK, description = "Success", body = PartyInfo),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token or not allowed"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::CONFLICT, description = "Conflicts with the current state"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn party_accept(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+    Json(PartyBody { party }): Json<PartyBody>,
+) -> Result<Json<PartyInfo>, StatusCode> {
+    if Party::find_by_member(&state.db, sub)
+        .await
+        .expect("failed to retrieve party from db")
+        .is_some()
+    {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let Some(party) = Party::find_by_uuid(&state.db, party)
+        .await
+        .expect("failed to retrieve party from db")
+    else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    match party
+        .accept(&state.db, sub, state.config.party_max_size)
+        .await
+    {
+        Ok(true) => {}
+        Ok(false) => return Err(StatusCode::FORBIDDEN),
+        // Joined another party concurrently
+        Err(sqlx::Error::Database(err)) if err.constraint() == Some("PartyMember_pkey") => {
+            return Err(StatusCode::CONFLICT);
+        }
+        Err(err) => {
+            error!(?err);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    party_updated(&state, &party).await;
+
+    Ok(Json(
+        party
+            .info(&state.db)
+            .await
+            .expect("failed to retrieve party members from db"),
+    ))
+}
+
+/// Private Endpoint: Leaves the party, passing leadership to the oldest member or disbanding it
+#[utoipa::path(
+    post,
+    path = "/party/leave",
+    tag = "party",
+    responses(
+        (status = StatusCode::OK, description = "Success"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn party_leave(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+) -> StatusCode {
+    let Some(mut party) = Party::find_by_member(&state.db, sub)
+        .await
+        .expect("failed to retrieve party from db")
+    else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    party
+        .remove_member(&state.db, sub)
+        .await
+        .expect("failed to leave party");
+
+    if party.leader == sub {
+        let members = party
+            .members(&state.db)
+            .await
+            .expect("failed to retrieve party members from db");
+
+        match members.first() {
+            Some(member) => party
+                .transfer(&state.db, member.uuid)
+                .await
+                .expect("failed to transfer party leadership"),
+            None => {
+                party
+                    .disband(&state.db)
+                    .await
+                    .expect("failed to disband party");
+                return StatusCode::OK;
+            }
+        }
+    }
+
+    party_updated(&state, &party).await;
+
+    StatusCode::OK
+}
+
+/// Private Endpoint: Removes member from the party led by the user
+#[utoipa::path(
+    post,
+    path = "/party/kick",
+    tag = "party",
+    request_body = FriendBody,
+    responses(
+        (status = StatusCode::OK, description = "Success"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token or not allowed"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn party_kick(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+    Json(FriendBody { uuid }): Json<FriendBody>,
+) -> StatusCode {
+    let Some(party) = Party::find_by_member(&state.db, sub)
+        .await
+        .expect("failed to retrieve party from db")
+    else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    if party.leader != sub || uuid == sub {
+        return StatusCode::FORBIDDEN;
+    }
+
+    if !party
+        .remove_member(&state.db, uuid)
+        .await
+        .expect("failed to kick party member")
+    {
+        return StatusCode::NOT_FOUND;
+    }
+
+    Gateway::notify(&state.db, uuid, HubEvent::PartyUpdated { party: None }).await;
+    party_updated(&state, &party).await;
+
+    StatusCode::OK
+}
+
+/// Private Endpoint: Passes party leadership to another member
+#[utoipa::path(
+    post,
+    path = "/party/transfer",
+    tag = "party",
+    request_body = FriendBody,
+    responses(
+        (status = StatusCode::OK, description = "Success"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token or not allowed"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn party_transfer(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+    Json(FriendBody { uuid }): Json<FriendBody>,
+) -> StatusCode {
+    let Some(mut party) = Party::find_by_member(&state.db, sub)
+        .await
+        .expect("failed to retrieve party from db")
+    else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    if party.leader != sub {
+        return StatusCode::FORBIDDEN;
+    }
+
+    if !party
+        .members(&state.db)
+        .await
+        .expect("failed to retrieve party members from db")
+        .iter()
+        .any(|member| member.uuid == uuid)
+    {
+        return StatusCode::NOT_FOUND;
+    }
+
+    party
+        .transfer(&state.db, uuid)
+        .await
+        .expect("failed to transfer party leadership");
+    party_updated(&state, &party).await;
+
+    StatusCode::OK
+}
+
+/// Adds guild claims to PIT if its subject is in a guild
+async fn with_guild(state: &HubState, token: PlayerIdentityToken) -> PlayerIdentityToken {
+    match Guild::find_by_member(&state.db, token.sub)
+        .await
+        .expect("failed to retrieve guild from db")
+    {
+        Some(guild) => token.with_guild(guild.uuid, guild.tag.0),
+        None => token,
+    }
+}
+
+/// Private Endpoint: Allows party leader to send every member to the same server, the members
+/// receive their PITs through the gateway and the leader's PIT is returned
+#[utoipa::path(
+    get,
+    path = "/party/pit",
+    tag = "party",
+    params(PITQuery),
+    responses(
+        (status = StatusCode::OK, description = "Player identity token of the leader", body = String),
+        (status = StatusCode::BAD_REQUEST, description = "Invalid request"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token or not allowed"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn party_pit(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, ct, .. }: AccessToken,
+    Query(query): Query<PITQuery>,
+) -> Result<String, StatusCode> {
+    if query.validate().is_err() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let Some(party) = Party::find_by_member(&state.db, sub)
+        .await
+        .expect("failed to retrieve party from db")
+    else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    if party.leader != sub {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let mut leader_token = None;
+    let mut issued = 0;
+
+    for member in party
+        .members(&state.db)
+        .await
+        .expect("failed to retrieve party members from db")
+    {
+        // Tokens are bound to the client type of the session of each member
+        let member_ct = if member.uuid == sub {
+            ct
+        } else {
+            match Session::find_client_type(&state.db, member.uuid)
+                .await
+                .expect("failed to retrieve session from db")
+            {
+                Some(ct) => ct,
+                None => continue,
+            }
+        };
+
+        let mut token = PlayerIdentityToken::new(query.sid.clone(), member.uuid, member_ct)
+            .with_party(party.uuid);
+        if query.guild {
+            token = with_guild(&state, token).await;
+        }
+        let token = token.sign(&state.keys);
+
+        UserPresence::join(&state.db, member.uuid, &query.sid)
+            .await
+            .expect("failed to update user presence");
+        Gateway::publish_presence(
+            &state.db,
+            member.uuid,
+            Presence::InGame {
+                sid: query.sid.clone(),
+            },
+        )
+        .await;
+        Gateway::notify(
+            &state.db,
+            member.uuid,
+            HubEvent::PartyPit {
+                party: party.uuid,
+                sid: query.sid.clone(),
+                token: token.clone(),
+            },
+        )
+        .await;
+
+        issued += 1;
+        if member.uuid == sub {
+            leader_token = Some(token);
+        }
+    }
+    metrics::pits_issued(issued);
+
+    leader_token.ok_or(StatusCode::NOT_FOUND)
+}
+
+// Guild
+
+/// Returns the guild of the user along with their rank
+async fn guild_membership(state: &HubState, sub: Uuid) -> Option<(Guild, GuildRank)> {
+    let guild = Guild::find_by_member(&state.db, sub)
+        .await
+        .expect("failed to retrieve guild from db")?;
+    let rank = GuildRank::find_by_member(&state.db, sub)
+        .await
+        .expect("failed to retrieve guild rank from db")?;
+
+    Some((guild, rank))
+}
+
+async fn guild_updated(state: &HubState, guild: &Guild) {
+    let members = guild
+        .member_uuids(&state.db)
+        .await
+        .expect("failed to retrieve guild members from db");
+
+    Gateway::publish_to(
+        &state.db,
+        &members,
+        HubEvent::GuildUpdated {
+            guild: Some(guild.uuid),
+        },
+    )
+    .await;
+}
+
+async fn guild_info(state: &HubState, guild: &Guild, rank: Uuid) -> GuildInfo {
+    GuildInfo {
+        profile: guild
+            .profile(&state.db)
+            .await
+            .expect("failed to retrieve guild profile from db"),
+        ranks: guild
+            .ranks(&state.db)
+            .await
+            .expect("failed to retrieve guild ranks from db")
+            .into_iter()
+            .map(Into::into)
+            .collect(),
+        rank,
+    }
+}
+
+/// Finds guild by its UUID or tag
+async fn find_guild(state: &HubState, id: &str) -> Option<Guild> {
+    match Uuid::parse_str(id) {
+        Ok(uuid) => Guild::find_by_uuid(&state.db, uuid).await,
+        Err(_) => Guild::find_by_tag(&state.db, id).await,
+    }
+    .expect("failed to retrieve guild from db")
+}
+
+/// Public Endpoint: Returns public profile of the guild found by its UUID or tag
+#[utoipa::path(
+    get,
+    path = "/guilds/{id}",
+    tag = "guild",
+    params(("id" = String, Path, description = "UUID or tag of the guild")),
+    responses(
+        (status = StatusCode::OK, description = "Success", body = GuildProfile),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+    ),
+)]
+pub async fn guild_profile(
+    State(state): State<Arc<HubState>>,
+    Path(id): Path<String>,
+) -> Result<Json<GuildProfile>, StatusCode> {
+    let Some(guild) = find_guild(&state, &id).await else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    Ok(Json(
+        guild
+            .profile(&state.db)
+            .await
+            .expect("failed to retrieve guild profile from db"),
+    ))
+}
+
+/// Public Endpoint: Lists members of the guild found by its UUID or tag
+#[utoipa::path(
+    get,
+    path = "/guilds/{id}/members",
+    tag = "guild",
+    params(("id" = String, Path, description = "UUID or tag of the guild")),
+    responses(
+        (status = StatusCode::OK, description = "Success", body = Vec<GuildMember>),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+    ),
+)]
+pub async fn guild_members(
+    State(state): State<Arc<HubState>>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<GuildMember>>, StatusCode> {
+    let Some(guild) = find_guild(&state, &id).await else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    Ok(Json(
+        guild
+            .members(&state.db)
+            .await
+            .expect("failed to retrieve guild members from db"),
+    ))
+}
+
+/// Private Endpoint: Returns the guild of the user
+#[utoipa::path(
+    get,
+    path = "/guild",
+    tag = "guild",
+    responses(
+        (status = StatusCode::OK, description = "Success", body = GuildInfo),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn guild(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+) -> Result<Json<GuildInfo>, StatusCode> {
+    let Some((guild, rank)) = guild_membership(&state, sub).await else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    Ok(Json(guild_info(&state, &guild, rank.uuid).await))
+}
+
+/// Private Endpoint: Founds new guild led by the user
+#[utoipa::path(
+    post,
+    path = "/guild",
+    tag = "guild",
+    request_body = GuildCreateBody,
+    responses(
+        (status = StatusCode::CREATED, description = "Created", body = GuildInfo),
+        (status = StatusCode::BAD_REQUEST, description = "Invalid request"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (
+            status = StatusCode::CONFLICT,
+            description = "User is already in a guild or the tag is taken",
+        ),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn guild_create(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+    Json(body): Json<GuildCreateBody>,
+) -> Result<(StatusCode, Json<GuildInfo>), StatusCode> {
+    if body.validate().is_err() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    if guild_membership(&state, sub).await.is_some() {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let guild = match Guild::new(
+        &state.db,
+        sub,
+        &body.tag,
+        body.name.trim(),
+        body.description.trim(),
+    )
+    .await
+    {
+        Ok(guild) => guild,
+        // Tag is taken or the user has joined another guild concurrently
+        Err(sqlx::Error::Database(err))
+            if matches!(err.constraint(), Some("Guild_tag_key" | "GuildMember_pkey")) =>
+        {
+            return Err(StatusCode::CONFLICT);
+        }
+        Err(err) => {
+            error!(?err);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let Some(rank) = GuildRank::find_by_member(&state.db, sub)
+        .await
+        .expect("failed to retrieve guild rank from db")
+    else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    Ok((
+        StatusCode::CREATED,
+        Json(guild_info(&state, &guild, rank.uuid).await),
+    ))
+}
+
+/// Private Endpoint: Updates name and description of the guild
+#[utoipa::path(
+    patch,
+    path = "/guild",
+    tag = "guild",
+    request_body = GuildPatchBody,
+    responses(
+        (status = StatusCode::OK, description = "Success", body = GuildProfile),
+        (status = StatusCode::BAD_REQUEST, description = "Invalid request"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token or not allowed"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn guild_update(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+    Json(body): Json<GuildPatchBody>,
+) -> Result<Json<GuildProfile>, StatusCode> {
+    if body.validate().is_err() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let Some((mut guild, rank)) = guild_membership(&state, sub).await else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    if !rank.can(GuildPermissions::EDIT_PROFILE) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Some(name) = body.name {
+        guild.name = name.trim().to_string();
+    }
+    if let Some(description) = body.description {
+        guild.description = description.trim().to_string();
+    }
+
+    guild
+        .update(&state.db)
+        .await
+        .expect("failed to update guild");
+    guild_updated(&state, &guild).await;
+
+    Ok(Json(
+        guild
+            .profile(&state.db)
+            .await
+            .expect("failed to retrieve guild profile from db"),
+    ))
+}
+
+/// Private Endpoint: Disbands the guild led by the user
+#[utoipa::path(
+    delete,
+    path = "/guild",
+    tag = "guild",
+    responses(
+        (status = StatusCode::OK, description = "Success"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token or not allowed"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn guild_disband(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+) -> StatusCode {
+    let Some((guild, rank)) = guild_membership(&state, sub).await else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    if !rank.is_leader() {
+        return StatusCode::FORBIDDEN;
+    }
+
+    let members = guild
+        .member_uuids(&state.db)
+        .await
+        .expect("failed to retrieve guild members from db");
+
+    guild
+        .disband(&state.db)
+        .await
+        .expect("failed to disband guild");
+    Gateway::publish_to(&state.db, &members, HubEvent::GuildUpdated { guild: None }).await;
+
+    StatusCode::OK
+}
+
+/// Private Endpoint: Lists guild invites received by the user
+#[utoipa::path(
+    get,
+    path = "/guild/invites",
+    tag = "guild",
+    responses(
+        (status = StatusCode::OK, description = "Success", body = Vec<GuildRequest>),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn guild_invites(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+) -> Json<Vec<GuildRequest>> {
+    Json(
+        Guild::requests(&state.db, FindBy::Sub, sub, GuildRequestKind::Invite)
+            .await
+            .expect("failed to retrieve guild invites from db"),
+    )
+}
+
+/// Private Endpoint: Lists pending applications to the guild of the user
+#[utoipa::path(
+    get,
+    path = "/guild/applications",
+    tag = "guild",
+    responses(
+        (status = StatusCode::OK, description = "Success", body = Vec<GuildRequest>),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token or not allowed"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn guild_applications(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+) -> Result<Json<Vec<GuildRequest>>, StatusCode> {
+    let Some((guild, rank)) = guild_membership(&state, sub).await else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    if !rank.can(GuildPermissions::APPROVE) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(
+        Guild::requests(
+            &state.db,
+            FindBy::Uuid,
+            guild.uuid,
+            GuildRequestKind::Application,
+        )
+        .await
+        .expect("failed to retrieve guild applications from db"),
+    ))
+}
+
+/// Private Endpoint: Invites a user to the guild, accepting their application if they have sent one
+#[utoipa::path(
+    post,
+    path = "/guild/invite",
+    tag = "guild",
+    request_body = FriendBody,
+    responses(
+        (status = StatusCode::OK, description = "Success"),
+        (status = StatusCode::CREATED, description = "Created"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token or not allowed"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::CONFLICT, description = "Conflicts with the current state"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn guild_invite(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+    Json(FriendBody { uuid }): Json<FriendBody>,
+) -> StatusCode {
+    let Some((guild, rank)) = guild_membership(&state, sub).await else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    if !rank.can(GuildPermissions::INVITE) {
+        return StatusCode::FORBIDDEN;
+    }
+
+    if User::find_by_uuid(&state.db, uuid)
+        .await
+        .expect("failed to retrieve user data from db")
+        .is_none()
+    {
+        return StatusCode::NOT_FOUND;
+    }
+
+    if Friendship::is_blocked(&state.db, sub, uuid)
+        .await
+        .expect("failed to retrieve friendship from db")
+    {
+        return StatusCode::FORBIDDEN;
+    }
+
+    if Guild::find_by_member(&state.db, uuid)
+        .await
+        .expect("failed to retrieve guild from db")
+        .is_some()
+    {
+        return StatusCode::CONFLICT;
+    }
+
+    if guild
+        .request(&state.db, uuid, GuildRequestKind::Invite)
+        .await
+        .expect("failed to create guild invite")
+    {
+        guild_updated(&state, &guild).await;
+        return StatusCode::OK;
+    }
+
+    send_notification(
+        &state,
+        uuid,
+        NotificationKind::Social,
+        "Guild invite",
+        &format!(
+            "You have been invited to join [{}] {}.",
+            *guild.tag, guild.name
+        ),
+    )
+    .await;
+    Gateway::notify(
+        &state.db,
+        uuid,
+        HubEvent::GuildInvite {
+            guild: guild.uuid,
+            tag: guild.tag.0,
+        },
+    )
+    .await;
+
+    StatusCode::CREATED
+}
+
+/// Private Endpoint: Applies to the guild, joining it right away if the user has been invited
+#[utoipa::path(
+    post,
+    path = "/guild/apply",
+    tag = "guild",
+    request_body = GuildBody,
+    responses(
+        (status = StatusCode::OK, description = "Success"),
+        (status = StatusCode::CREATED, description = "Created"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token or not allowed"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::CONFLICT, description = "Conflicts with the current state"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn guild_apply(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+    Json(GuildBody { guild }): Json<GuildBody>,
+) -> StatusCode {
+    if guild_membership(&state, sub).await.is_some() {
+        return StatusCode::CONFLICT;
+    }
+
+    let Some(guild) = Guild::find_by_uuid(&state.db, guild)
+        .await
+        .expect("failed to retrieve guild from db")
+    else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    if let Some(leader) = guild
+        .leader(&state.db)
+        .await
+        .expect("failed to retrieve guild leader from db")
+    {
+        if Friendship::is_blocked(&state.db, sub, leader)
+            .await
+            .expect("failed to retrieve friendship from db")
+        {
+            return StatusCode::FORBIDDEN;
+        }
+    }
+
+    if guild
+        .request(&state.db, sub, GuildRequestKind::Application)
+        .await
+        .expect("failed to create guild application")
+    {
+        guild_updated(&state, &guild).await;
+        return StatusCode::OK;
+    }
+
+    StatusCode::CREATED
+}
+
+/// Private Endpoint: Joins the guild using the received invite
+#[utoipa::path(
+    post,
+    path = "/guild/accept",
+    tag = "guild",
+    request_body = GuildBody,
+    responses(
+        (status = StatusCode::OK, description = "Success", body = GuildInfo),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::CONFLICT, description = "Conflicts with the current state"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn guild_accept(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+    Json(GuildBody { guild }): Json<GuildBody>,
+) -> Result<Json<GuildInfo>, StatusCode> {
+    if guild_membership(&state, sub).await.is_some() {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let Some(guild) = Guild::find_by_uuid(&state.db, guild)
+        .await
+        .expect("failed to retrieve guild from db")
+    else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    if !guild
+        .accept(&state.db, sub, GuildRequestKind::Invite)
+        .await
+        .expect("failed to join guild")
+    {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    guild_updated(&state, &guild).await;
+
+    let Some((guild, rank)) = guild_membership(&state, sub).await else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    Ok(Json(guild_info(&state, &guild, rank.uuid).await))
+}
+
+/// Private Endpoint: Declines the received guild invite
+#[utoipa::path(
+    post,
+    path = "/guild/decline",
+    tag = "guild",
+    request_body = GuildBody,
+    responses(
+        (status = StatusCode::OK, description = "Success"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn guild_decline(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+    Json(GuildBody { guild }): Json<GuildBody>,
+) -> StatusCode {
+    let Some(guild) = Guild::find_by_uuid(&state.db, guild)
+        .await
+        .expect("failed to retrieve guild from db")
+    else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    if guild
+        .take_request(&state.db, sub, GuildRequestKind::Invite)
+        .await
+        .expect("failed to decline guild invite")
+    {
+        StatusCode::OK
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+/// Private Endpoint: Approves pending application to the guild of the user
+#[utoipa::path(
+    post,
+    path = "/guild/approve",
+    tag = "guild",
+    request_body = FriendBody,
+    responses(
+        (status = StatusCode::OK, description = "Success"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token or not allowed"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::CONFLICT, description = "Conflicts with the current state"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn guild_approve(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+    Json(FriendBody { uuid }): Json<FriendBody>,
+) -> StatusCode {
+    let Some((guild, rank)) = guild_membership(&state, sub).await else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    if !rank.can(GuildPermissions::APPROVE) {
+        return StatusCode::FORBIDDEN;
+    }
+
+    if Guild::find_by_member(&state.db, uuid)
+        .await
+        .expect("failed to retrieve guild from db")
+        .is_some()
+    {
+        return StatusCode::CONFLICT;
+    }
+
+    if !guild
+        .accept(&state.db, uuid, GuildRequestKind::Application)
+        .await
+        .expect("failed to join guild")
+    {
+        return StatusCode::NOT_FOUND;
+    }
+
+    guild_updated(&state, &guild).await;
+
+    StatusCode::OK
+}
+
+/// Private Endpoint: Rejects pending application to the guild of the user
+#[utoipa::path(
+    post,
+    path = "/guild/reject",
+    tag = "guild",
+    request_body = FriendBody,
+    responses(
+        (status = StatusCode::OK, description = "Success"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token or not allowed"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn guild_reject(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+    Json(FriendBody { uuid }): Json<FriendBody>,
+) -> StatusCode {
+    let Some((guild, rank)) = guild_membership(&state, sub).await else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    if !rank.can(GuildPermissions::APPROVE) {
+        return StatusCode::FORBIDDEN;
+    }
+
+    if guild
+        .take_request(&state.db, uuid, GuildRequestKind::Application)
+        .await
+        .expect("failed to reject guild application")
+    {
+        StatusCode::OK
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+/// Private Endpoint: Leaves the guild, the leader has to pass leadership or disband it instead
+#[utoipa::path(
+    post,
+    path = "/guild/leave",
+    tag = "guild",
+    responses(
+        (status = StatusCode::OK, description = "Success"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token or not allowed"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn guild_leave(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+) -> StatusCode {
+    let Some((guild, rank)) = guild_membership(&state, sub).await else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    if rank.is_leader() {
+        return StatusCode::FORBIDDEN;
+    }
+
+    guild
+        .remove_member(&state.db, sub)
+        .await
+        .expect("failed to leave guild");
+    Gateway::notify(&state.db, sub, HubEvent::GuildUpdated { guild: None }).await;
+    guild_updated(&state, &guild).await;
+
+    StatusCode::OK
+}
+
+/// Private Endpoint: Removes lower ranked member from the guild
+#[utoipa::path(
+    post,
+    path = "/guild/kick",
+    tag = "guild",
+    request_body = FriendBody,
+    responses(
+        (status = StatusCode::OK, description = "Success"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token or not allowed"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn guild_kick(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+    Json(FriendBody { uuid }): Json<FriendBody>,
+) -> StatusCode {
+    let Some((guild, rank)) = guild_membership(&state, sub).await else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    let Some(target) = GuildRank::find_by_member(&state.db, uuid)
+        .await
+        .expect("failed to retrieve guild rank from db")
+        .filter(|target| target.guild == guild.uuid)
+    else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    if !rank.can(GuildPermissions::KICK) || !rank.outranks(&target) {
+        return StatusCode::FORBIDDEN;
+    }
+
+    guild
+        .remove_member(&state.db, uuid)
+        .await
+        .expect("failed to kick guild member");
+    Gateway::notify(&state.db, uuid, HubEvent::GuildUpdated { guild: None }).await;
+    guild_updated(&state, &guild).await;
+
+    StatusCode::OK
+}
+
+/// Private Endpoint: Passes guild leadership to another member, the user takes their rank
+#[utoipa::path(
+    post,
+    path = "/guild/transfer",
+    tag = "guild",
+    request_body = FriendBody,
+    responses(
+        (status = StatusCode::OK, description = "Success"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token or not allowed"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn guild_transfer(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+    Json(FriendBody { uuid }): Json<FriendBody>,
+) -> StatusCode {
+    let Some((guild, rank)) = guild_membership(&state, sub).await else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    if !rank.is_leader() || uuid == sub {
+        return StatusCode::FORBIDDEN;
+    }
+
+    if !guild
+        .transfer(&state.db, sub, uuid)
+        .await
+        .expect("failed to transfer guild leadership")
+    {
+        return StatusCode::NOT_FOUND;
+    }
+
+    guild_updated(&state, &guild).await;
+
+    StatusCode::OK
+}
+
+/// Private Endpoint: Creates new rank below the rank of the user
+#[utoipa::path(
+    post,
+    path = "/guild/ranks",
+    tag = "guild",
+    request_body = GuildRankBody,
+    responses(
+        (status = StatusCode::CREATED, description = "Created", body = common::guild::GuildRank),
+        (status = StatusCode::BAD_REQUEST, description = "Invalid request"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token or not allowed"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::CONFLICT, description = "Conflicts with the current state"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn guild_rank_create(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+    Json(body): Json<GuildRankBody>,
+) -> Result<(StatusCode, Json<common::guild::GuildRank>), StatusCode> {
+    if body.validate().is_err() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let Some((guild, rank)) = guild_membership(&state, sub).await else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    // Members can not grant permissions they do not hold themselves
+    if !rank.can(GuildPermissions::MANAGE_RANKS)
+        || body.position <= rank.position
+        || !rank.can(body.permissions)
+    {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    match GuildRank::new(
+        &state.db,
+        guild.uuid,
+        body.name.trim(),
+        body.permissions,
+        body.position,
+    )
+    .await
+    {
+        Ok(rank) => {
+            guild_updated(&state, &guild).await;
+            Ok((StatusCode::CREATED, Json(rank.into())))
+        }
+        Err(sqlx::Error::Database(err)) if err.constraint() == Some("GuildRank_guild_name_key") => {
+            Err(StatusCode::CONFLICT)
+        }
+        Err(err) => {
+            error!(?err);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Private Endpoint: Renames rank below the rank of the user or changes its permissions
+#[utoipa::path(
+    patch,
+    path = "/guild/ranks/{uuid}",
+    tag = "guild",
+    params(("uuid" = Uuid, Path, description = "UUID of the rank")),
+    request_body = GuildRankPatchBody,
+    responses(
+        (status = StatusCode::OK, description = "Success", body = common::guild::GuildRank),
+        (status = StatusCode::BAD_REQUEST, description = "Invalid request"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token or not allowed"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::CONFLICT, description = "Conflicts with the current state"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn guild_rank_update(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+    Path(uuid): Path<Uuid>,
+    Json(body): Json<GuildRankPatchBody>,
+) -> Result<Json<common::guild::GuildRank>, StatusCode> {
+    if body.validate().is_err() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let Some((guild, rank)) = guild_membership(&state, sub).await else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    let Some(mut target) = GuildRank::find_by_uuid(&state.db, guild.uuid, uuid)
+        .await
+        .expect("failed to retrieve guild rank from db")
+    else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    if !rank.can(GuildPermissions::MANAGE_RANKS)
+        || !rank.outranks(&target)
+        || body
+            .permissions
+            .is_some_and(|permissions| !rank.can(permissions))
+    {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Some(name) = body.name {
+        target.name = name.trim().to_string();
+    }
+    if let Some(permissions) = body.permissions {
+        target.permissions = permissions.0;
+    }
+
+    match target.update(&state.db).await {
+        Ok(()) => {
+            guild_updated(&state, &guild).await;
+            Ok(Json(target.into()))
+        }
+        Err(sqlx::Error::Database(err)) if err.constraint() == Some("GuildRank_guild_name_key") => {
+            Err(StatusCode::CONFLICT)
+        }
+        Err(err) => {
+            error!(?err);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Private Endpoint: Deletes rank below the rank of the user, the rank must have no members
+#[utoipa::path(
+    delete,
+    path = "/guild/ranks/{uuid}",
+    tag = "guild",
+    params(("uuid" = Uuid, Path, description = "UUID of the rank")),
+    responses(
+        (status = StatusCode::OK, description = "Success"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token or not allowed"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::CONFLICT, description = "Conflicts with the current state"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn guild_rank_delete(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+    Path(uuid): Path<Uuid>,
+) -> StatusCode {
+    let Some((guild, rank)) = guild_membership(&state, sub).await else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    let Some(target) = GuildRank::find_by_uuid(&state.db, guild.uuid, uuid)
+        .await
+        .expect("failed to retrieve guild rank from db")
+    else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    if !rank.can(GuildPermissions::MANAGE_RANKS) || !rank.outranks(&target) {
+        return StatusCode::FORBIDDEN;
+    }
+
+    if !target
+        .delete(&state.db)
+        .await
+        .expect("failed to delete guild rank")
+    {
+        return StatusCode::CONFLICT;
+    }
+
+    guild_updated(&state, &guild).await;
+
+    StatusCode::OK
+}
+
+/// Private Endpoint: Assigns rank to a member, both ranks must be below the rank of the user
+#[utoipa::path(
+    put,
+    path = "/guild/members/{uuid}/rank",
+    tag = "guild",
+    params(("uuid" = Uuid, Path, description = "UUID of the member")),
+    request_body = GuildMemberRankBody,
+    responses(
+        (status = StatusCode::OK, description = "Success"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token or not allowed"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn guild_member_rank(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+    Path(uuid): Path<Uuid>,
+    Json(body): Json<GuildMemberRankBody>,
+) -> StatusCode {
+    let Some((guild, rank)) = guild_membership(&state, sub).await else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    let Some(current) = GuildRank::find_by_member(&state.db, uuid)
+        .await
+        .expect("failed to retrieve guild rank from db")
+        .filter(|current| current.guild == guild.uuid)
+    else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    let Some(target) = GuildRank::find_by_uuid(&state.db, guild.uuid, body.rank)
+        .await
+        .expect("failed to retrieve guild rank from db")
+    else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    if !rank.can(GuildPermissions::MANAGE_RANKS)
+        || !rank.outranks(&current)
+        || !rank.outranks(&target)
+    {
+        return StatusCode::FORBIDDEN;
+    }
+
+    guild
+        .set_rank(&state.db, uuid, target.uuid)
+        .await
+        .expect("failed to update guild member rank");
+    guild_updated(&state, &guild).await;
+
+    StatusCode::OK
+}
+
+// Notifications
+
+/// Stores notification in the inbox of the user and pushes it to their gateway connections
+async fn send_notification(
+    state: &HubState,
+    sub: Uuid,
+    kind: NotificationKind,
+    title: &str,
+    body: &str,
+) {
+    let notification = Notification::new(&state.db, sub, kind, title, body)
+        .await
+        .expect("failed to create notification");
+
+    Gateway::notify(
+        &state.db,
+        sub,
+        HubEvent::Notification {
+            notification: notification.into(),
+        },
+    )
+    .await;
+}
+
+/// Private Endpoint: Lists notifications of the user from the newest to the oldest
+#[utoipa::path(
+    get,
+    path = "/notifications",
+    tag = "notifications",
+    params(NotificationQuery),
+    responses(
+        (status = StatusCode::OK, description = "Success", body = Vec<NotificationData>),
+        (status = StatusCode::BAD_REQUEST, description = "Invalid request"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn notifications(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+    Query(query): Query<NotificationQuery>,
+) -> Result<Json<Vec<NotificationData>>, StatusCode> {
+    let limit = query
+        .limit
+        .unwrap_or(state.config.notification_page_limit)
+        .clamp(1, state.config.notification_page_limit);
+
+    Ok(Json(
+        Notification::list(&state.db, sub, query.before, limit, query.unread)
+            .await
+            .expect("failed to retrieve notifications from db")
+            .into_iter()
+            .map(NotificationData::from)
+            .collect(),
+    ))
+}
+
+/// Private Endpoint: Returns the number of unread notifications
+#[utoipa::path(
+    get,
+    path = "/notifications/unread",
+    tag = "notifications",
+    responses(
+        (status = StatusCode::OK, description = "Success", body = i64),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn notifications_unread(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+) -> Json<i64> {
+    Json(
+        Notification::count_unread(&state.db, sub)
+            .await
+            .expect("failed to count unread notifications"),
+    )
+}
+
+/// Private Endpoint: Marks all notifications as read
+#[utoipa::path(
+    put,
+    path = "/notifications/read",
+    tag = "notifications",
+    responses(
+        (status = StatusCode::OK, description = "Success"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn notifications_read(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+) -> StatusCode {
+    Notification::mark_read(&state.db, sub, None)
+        .await
+        .expect("failed to mark notifications as read");
+
+    StatusCode::OK
+}
+
+/// Private Endpoint: Marks the notification as read
+#[utoipa::path(
+    put,
+    path = "/notifications/{uuid}/read",
+    tag = "notifications",
+    params(("uuid" = Uuid, Path, description = "UUID of the notification")),
+    responses(
+        (status = StatusCode::OK, description = "Success"),
+        (status = StatusCode::NOT_MODIFIED, description = "Nothing has changed"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn notification_read(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+    Path(uuid): Path<Uuid>,
+) -> StatusCode {
+    if Notification::mark_read(&state.db, sub, Some(uuid))
+        .await
+        .expect("failed to mark notification as read")
+        > 0
+    {
+        StatusCode::OK
+    } else {
+        StatusCode::NOT_MODIFIED
+    }
+}
+
+/// Private Endpoint: Deletes the notification from the inbox
+#[utoipa::path(
+    delete,
+    path = "/notifications/{uuid}",
+    tag = "notifications",
+    params(("uuid" = Uuid, Path, description = "UUID of the notification")),
+    responses(
+        (status = StatusCode::OK, description = "Success"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn notification_delete(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+    Path(uuid): Path<Uuid>,
+) -> StatusCode {
+    if Notification::delete(&state.db, sub, uuid)
+        .await
+        .expect("failed to delete notification")
+    {
+        StatusCode::OK
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+/// Staff Endpoint: Renames the user bypassing reserved names and the rename cooldown
+#[utoipa::path(
+    put,
+    path = "/admin/users/{uuid}/username",
+    tag = "admin",
+    params(("uuid" = Uuid, Path, description = "UUID of the user")),
+    request_body = UsernameChangeBody,
+    responses(
+        (status = StatusCode::OK, description = "Success", body = UserInfo),
+        (status = StatusCode::NOT_MODIFIED, description = "Nothing has changed"),
+        (status = StatusCode::BAD_REQUEST, description = "Invalid request"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token or insufficient role"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::CONFLICT, description = "Conflicts with the current state"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn admin_user_username(
+    State(state): State<Arc<HubState>>,
+    Staff { sub }: Staff,
+    Path(uuid): Path<Uuid>,
+    Json(body): Json<UsernameChangeBody>,
+) -> impl IntoResponse {
+    if body.validate().is_err() {
+        return StatusCode::BAD_REQUEST.into_response();
+    }
+
+    let Some(mut user) = User::find_by_uuid(&state.db, uuid)
+        .await
+        .expect("failed to retrieve user data from db")
+    else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    if user.username == body.username {
+        return StatusCode::NOT_MODIFIED.into_response();
+    }
+
+    if UsernameHistory::is_reserved(&state.db, &body.username, Some(uuid))
+        .await
+        .expect("failed to check username history")
+    {
+        return (
+            StatusCode::CONFLICT,
+            format!("username '{}' already taken", body.username),
+        )
+            .into_response();
+    }
+
+    match user
+        .update_username(
+            &state.db,
+            body.username.clone(),
+            OffsetDateTime::now_utc() + Duration::seconds(state.config.username_reservation_period),
+            None,
+        )
+        .await
+    {
+        Ok(_) => {
+            info!(%sub, %uuid, username = body.username, "Username granted");
+            Json(UserInfo::from(user)).into_response()
+        }
+        Err(sqlx::Error::Database(err)) if err.constraint() == Some("User_username_key") => (
+            StatusCode::CONFLICT,
+            format!("username '{}' already taken", body.username),
+        )
+            .into_response(),
+        Err(err) => {
+            error!(?err);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Staff Endpoint: Bans the user from sending direct messages or lifts the ban
+#[utoipa::path(
+    put,
+    path = "/admin/users/{uuid}/chat_ban",
+    tag = "admin",
+    params(("uuid" = Uuid, Path, description = "UUID of the user")),
+    request_body = ChatBanBody,
+    responses(
+        (status = StatusCode::OK, description = "Success"),
+        (status = StatusCode::BAD_REQUEST, description = "Invalid request"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token or insufficient role"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn admin_user_chat_ban(
+    State(state): State<Arc<HubState>>,
+    Staff { sub }: Staff,
+    Path(uuid): Path<Uuid>,
+    Json(body): Json<ChatBanBody>,
+) -> StatusCode {
+    if body.validate().is_err() {
+        return StatusCode::BAD_REQUEST;
+    }
+
+    let Some(mut user) = User::find_by_uuid(&state.db, uuid)
+        .await
+        .expect("failed to retrieve user data from db")
+    else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    let until = body
+        .duration
+        .map(|duration| OffsetDateTime::now_utc() + Duration::seconds(duration));
+    user.update_chat_ban(&state.db, until)
+        .await
+        .expect("failed to update chat ban");
+    info!(%sub, %uuid, ?until, "Chat ban updated");
 
-/// Public Endpoint: For health checks
-pub async fn health() -> StatusCode {
     StatusCode::OK
 }
 
-/// Public Endpoint: Returns hub status
-pub async fn status() -> Json<HubStatus<'static>> {
-    Json(STATUS)
+/// Admin Endpoint: Lists all invite codes
+#[utoipa::path(
+    get,
+    path = "/admin/invites",
+    tag = "admin",
+    responses(
+        (status = StatusCode::OK, description = "Success", body = Vec<Invite>),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token or insufficient role"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn admin_invites(State(state): State<Arc<HubState>>, _: Admin) -> Json<Vec<Invite>> {
+    Json(
+        InviteCode::list(&state.db)
+            .await
+            .expect("failed to retrieve invite codes from db")
+            .into_iter()
+            .map(Invite::from)
+            .collect(),
+    )
 }
 
-/// Public Endpoint: Returns the public key used to verify the signature of the tokens
-pub async fn pubkey(
+/// Admin Endpoint: Creates invite code with optional use limit and lifetime
+#[utoipa::path(
+    post,
+    path = "/admin/invites",
+    tag = "admin",
+    request_body = InviteCreateBody,
+    responses(
+        (status = StatusCode::CREATED, description = "Created", body = Invite),
+        (status = StatusCode::BAD_REQUEST, description = "Invalid request"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token or insufficient role"),
+        (status = StatusCode::CONFLICT, description = "Conflicts with the current state"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn admin_invite_create(
     State(state): State<Arc<HubState>>,
-    Query(format): Query<KeyFormatQuery>,
-) -> String {
-    match format.format {
-        KeyFormat::Hex => state.keys.public_hex.clone(),
-        KeyFormat::Pem => state.keys.public_pem.clone(),
+    Admin { sub }: Admin,
+    Json(body): Json<InviteCreateBody>,
+) -> Result<(StatusCode, Json<Invite>), StatusCode> {
+    if body.validate().is_err() {
+        return Err(StatusCode::BAD_REQUEST);
     }
-}
 
-// User
+    let code = body.code.unwrap_or_else(|| generate_code(INVITE_CODE_LEN));
+    let expires_at = body
+        .lifetime
+        .map(|lifetime| OffsetDateTime::now_utc() + Duration::seconds(lifetime));
 
-/// Public Endpoint: Looks up for the uuid and username of the account
-pub async fn user_info(
+    match InviteCode::new(&state.db, &code, sub, body.max_uses, expires_at).await {
+        Ok(code) => Ok((StatusCode::CREATED, Json(code.into()))),
+        Err(sqlx::Error::Database(err)) if err.constraint() == Some("InviteCode_pkey") => {
+            Err(StatusCode::CONFLICT)
+        }
+        Err(err) => {
+            error!(?err);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Admin Endpoint: Expires the invite code right away
+#[utoipa::path(
+    delete,
+    path = "/admin/invites/{code}",
+    tag = "admin",
+    params(("code" = String, Path, description = "Invite code")),
+    responses(
+        (status = StatusCode::OK, description = "Success"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token or insufficient role"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn admin_invite_revoke(
     State(state): State<Arc<HubState>>,
-    user_id: Query<UserInfoQuery>,
-) -> Result<Json<UserInfo>, StatusCode> {
-    if user_id.validate().is_ok() {
-        Ok(Json(
-            match (user_id.uuid, &user_id.username) {
-                (Some(uuid), _) => {
-                    if let Some(user) = User::find_by_uuid(&state.db, uuid)
-                        .await
-                        .expect("failed to retrieve user data from db")
-                    {
-                        user
-                    } else {
-                        return Err(StatusCode::NOT_FOUND);
-                    }
-                }
-                (None, Some(username)) => {
-                    if let Some(user) = User::find_by_username(&state.db, username)
-                        .await
-                        .expect("failed to retrieve user data from db")
-                    {
-                        user
-                    } else {
-                        return Err(StatusCode::NOT_FOUND);
-                    }
-                }
-                _ => return Err(StatusCode::BAD_REQUEST),
-            }
-            .into(),
-        ))
+    _: Admin,
+    Path(code): Path<String>,
+) -> StatusCode {
+    if InviteCode::revoke(&state.db, &code)
+        .await
+        .expect("failed to revoke invite code")
+    {
+        StatusCode::OK
     } else {
-        Err(StatusCode::BAD_REQUEST)
+        StatusCode::NOT_FOUND
     }
 }
 
-/// Private Endpoint: Allows the user to retrieve their personal data
-pub async fn user_data(
+/// Admin Endpoint: Sends system notification to everyone or to the users matching the segment
+#[utoipa::path(
+    post,
+    path = "/admin/broadcast",
+    tag = "admin",
+    request_body = BroadcastBody,
+    responses(
+        (status = StatusCode::OK, description = "Success", body = BroadcastResponse),
+        (status = StatusCode::BAD_REQUEST, description = "Invalid request"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token or insufficient role"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn admin_broadcast(
     State(state): State<Arc<HubState>>,
-    AccessToken { sub, .. }: AccessToken,
-) -> Result<Json<UserData>, StatusCode> {
-    Ok(Json(
-        if let Some(user) = User::find_by_uuid(&state.db, sub)
+    Admin { sub }: Admin,
+    Json(body): Json<BroadcastBody>,
+) -> Result<Json<BroadcastResponse>, StatusCode> {
+    if body.validate().is_err() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let uuid = Uuid::new_v4();
+    let recipients =
+        Notification::broadcast(&state.db, uuid, &body.segment, &body.title, &body.body)
             .await
-            .expect("failed to retrieve user info from db")
-        {
-            user.into()
-        } else {
-            return Err(StatusCode::NOT_FOUND);
-        },
-    ))
+            .expect("failed to create broadcast notifications");
+
+    info!(%sub, %uuid, recipients = recipients.len(), "Broadcast sent");
+
+    let notification = NotificationData {
+        uuid,
+        kind: NotificationKind::System,
+        title: body.title,
+        body: body.body,
+        read: false,
+        created_at: OffsetDateTime::now_utc().unix_timestamp(),
+    };
+
+    // NOTIFY payloads are limited in size, so segments are published in chunks
+    if body.segment.is_everyone() {
+        Gateway::publish(&state.db, None, HubEvent::Notification { notification }).await;
+    } else {
+        Gateway::publish_to(
+            &state.db,
+            &recipients,
+            HubEvent::Notification {
+                notification: notification.clone(),
+            },
+        )
+        .await;
+    }
+
+    Ok(Json(BroadcastResponse {
+        uuid,
+        recipients: recipients.len(),
+    }))
 }
 
 // Security
 
 /// Private Endpoint: Allows user to create a new session and a refresh/access token pair
+#[utoipa::path(
+    post,
+    path = "/user/login",
+    tag = "user",
+    request_body = LoginBody,
+    responses(
+        (
+            status = StatusCode::OK,
+            description = "Access token, the refresh token is set in a cookie",
+            body = String,
+            content_type = "text/plain",
+        ),
+        (status = StatusCode::BAD_REQUEST, description = "Invalid request"),
+        (status = StatusCode::UNAUTHORIZED, description = "Invalid username or password"),
+        (status = StatusCode::GONE, description = "Account is banned"),
+        (status = StatusCode::IM_A_TEAPOT, description = "Account is inactive"),
+    ),
+)]
 pub async fn user_login(
     State(state): State<Arc<HubState>>,
     jar: CookieJar,
@@ -125,31 +3135,43 @@ pub async fn user_login(
             ct,
         } = body;
 
-        if let Some(user) = User::find_by_username(&state.db, &username)
+        if let Some(mut user) = User::find_by_username(&state.db, &username)
             .await
             .expect("failed to retrieve user data from db")
         {
-            if Argon2::default()
-                .verify_password(
-                    password.as_bytes(),
-                    &PasswordHash::new(&user.password).expect("failed to parse password hash"),
-                )
-                .is_ok()
-            {
+            if verify_password(&password, &user.password) {
                 return match user.status {
-                    UserStatus::Active => {
+                    UserStatus::Active | UserStatus::PendingDeletion => {
+                        if user.status == UserStatus::PendingDeletion {
+                            user.cancel_deletion(&state.db)
+                                .await
+                                .expect("failed to cancel account deletion");
+                        }
+
                         let session = Session::new(&state.db, ct, user.uuid).await.unwrap();
+                        metrics::login(ct, true);
 
                         Ok((
                             jar.add(RefreshToken::from((&session, ct)).to_cookie(&state.keys)),
                             AccessToken::from((&session, ct)).sign(&state.keys),
                         ))
                     }
-                    UserStatus::Inactive => Err(StatusCode::IM_A_TEAPOT),
-                    UserStatus::Banned => Err(StatusCode::GONE),
+                    UserStatus::Inactive => {
+                        metrics::login(ct, false);
+                        Err(StatusCode::IM_A_TEAPOT)
+                    }
+                    UserStatus::Banned => {
+                        metrics::login(ct, false);
+                        Err(StatusCode::GONE)
+                    }
+                    UserStatus::Deleted => {
+                        metrics::login(ct, false);
+                        Err(StatusCode::UNAUTHORIZED)
+                    }
                 };
             }
         }
+        metrics::login(ct, false);
         Err(StatusCode::UNAUTHORIZED)
     } else {
         Err(StatusCode::BAD_REQUEST)
@@ -157,6 +3179,21 @@ pub async fn user_login(
 }
 
 /// Endpoint: Creates new a user account
+#[utoipa::path(
+    post,
+    path = "/user/register",
+    tag = "user",
+    request_body = RegisterBody,
+    responses(
+        (status = StatusCode::CREATED, description = "Created", body = RegistrationResponse),
+        (status = StatusCode::BAD_REQUEST, description = "Invalid request"),
+        (
+            status = StatusCode::FORBIDDEN,
+            description = "Registration is closed or invite code or challenge is invalid",
+        ),
+        (status = StatusCode::CONFLICT, description = "Username or email is taken or not allowed"),
+    ),
+)]
 pub async fn user_register(
     State(state): State<Arc<HubState>>,
     Json(body): Json<RegisterBody>,
@@ -166,42 +3203,74 @@ pub async fn user_register(
             username,
             email,
             password,
+            invite,
+            challenge,
         } = body;
 
+        match (state.config.registration, &invite) {
+            (RegistrationPolicy::Closed, _) => {
+                return (StatusCode::FORBIDDEN, "registration is closed").into_response();
+            }
+            (RegistrationPolicy::InviteOnly, None) => {
+                return (StatusCode::FORBIDDEN, "invite code required").into_response();
+            }
+            _ => {}
+        }
+
+        if !state.challenger.verify(challenge.as_ref()).await {
+            return (StatusCode::FORBIDDEN, "challenge failed").into_response();
+        }
+
+        if let Err(violation) = state.names.check(&username) {
+            return (StatusCode::CONFLICT, violation.to_string()).into_response();
+        }
+
+        if UsernameHistory::is_reserved(&state.db, &username, None)
+            .await
+            .expect("failed to check username history")
+        {
+            return (
+                StatusCode::CONFLICT,
+                format!("username '{username}' already taken"),
+            )
+                .into_response();
+        }
+
         let uuid = Uuid::new_v4();
 
-        match User::new(
+        let mut user = User::new(
             uuid,
             username.clone(),
             email.clone(),
             hash_password(&password),
             UserStatus::Active,
-        )
-        .insert(&state.db)
-        .await
-        {
-            Err(sqlx::Error::Database(err))
-                if err.try_downcast_ref::<PgDatabaseError>().is_some() =>
-            {
-                (
-                    StatusCode::CONFLICT,
-                    match err
-                        .downcast::<PgDatabaseError>()
-                        .constraint()
-                        .expect("db error without constraint")
-                    {
-                        "User_username_key" => format!("username '{username}' already taken"),
-                        "User_email_key" => format!("email '{email}' already taken"),
-                        _ => String::from("db error"),
-                    },
-                )
-                    .into_response()
-            }
+        );
+
+        let inserted = match &invite {
+            Some(code) => user.insert_invited(&state.db, code).await,
+            None => user.insert(&state.db).await.map(|_| true),
+        };
+
+        match inserted {
+            // Lock timeouts and serialization failures of the invite have no constraint
+            Err(sqlx::Error::Database(err)) if err.constraint().is_some() => (
+                StatusCode::CONFLICT,
+                match err.constraint() {
+                    Some("User_username_key") => format!("username '{username}' already taken"),
+                    Some("User_email_key") => format!("email '{email}' already taken"),
+                    _ => String::from("db error"),
+                },
+            )
+                .into_response(),
             Err(err) => {
                 error!(?err);
                 StatusCode::INTERNAL_SERVER_ERROR.into_response()
             }
-            Ok(_) => (StatusCode::CREATED, Json(RegistrationResponse::new(uuid))).into_response(),
+            Ok(false) => (StatusCode::FORBIDDEN, "invalid invite code").into_response(),
+            Ok(true) => {
+                metrics::registration();
+                (StatusCode::CREATED, Json(RegistrationResponse::new(uuid))).into_response()
+            }
         }
     } else {
         StatusCode::BAD_REQUEST.into_response()
@@ -209,6 +3278,21 @@ pub async fn user_register(
 }
 
 /// Private Endpoint: Allows user to change the password with their old password and access token
+#[utoipa::path(
+    put,
+    path = "/user/password",
+    tag = "user",
+    request_body = PasswordChangeBody,
+    responses(
+        (status = StatusCode::OK, description = "Success"),
+        (status = StatusCode::NOT_MODIFIED, description = "Nothing has changed"),
+        (status = StatusCode::BAD_REQUEST, description = "Invalid request"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
 pub async fn user_password(
     State(state): State<Arc<HubState>>,
     AccessToken { sub, .. }: AccessToken,
@@ -219,17 +3303,19 @@ pub async fn user_password(
             .await
             .expect("Failed to find user")
         {
-            if Argon2::default()
-                .verify_password(
-                    body.old_password.as_bytes(),
-                    &PasswordHash::new(&user.password).expect("Failed to parse password hash"),
-                )
-                .is_ok()
-            {
+            if verify_password(&body.old_password, &user.password) {
                 user.password = hash_password(&body.new_password);
                 user.update_password(&state.db)
                     .await
                     .expect("Failed to make update request to DB");
+                send_notification(
+                    &state,
+                    sub,
+                    NotificationKind::Security,
+                    "Password changed",
+                    "The password of your account has been changed.",
+                )
+                .await;
                 StatusCode::OK
             } else {
                 StatusCode::NOT_MODIFIED
@@ -242,7 +3328,525 @@ pub async fn user_password(
     }
 }
 
+/// Private Endpoint: Lists invite codes created by the user
+#[utoipa::path(
+    get,
+    path = "/user/invites",
+    tag = "user",
+    responses(
+        (status = StatusCode::OK, description = "Success", body = Vec<Invite>),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn user_invites(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+) -> Json<Vec<Invite>> {
+    Json(
+        InviteCode::find_by_creator(&state.db, sub)
+            .await
+            .expect("failed to retrieve invite codes from db")
+            .into_iter()
+            .map(Invite::from)
+            .collect(),
+    )
+}
+
+/// Private Endpoint: Creates single-use invite code if the user has not used up their quota
+#[utoipa::path(
+    post,
+    path = "/user/invites",
+    tag = "user",
+    responses(
+        (status = StatusCode::CREATED, description = "Created", body = Invite),
+        (status = StatusCode::FORBIDDEN, description = "Invite quota is used up"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn user_invite_create(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+) -> Result<(StatusCode, Json<Invite>), StatusCode> {
+    let Some(code) = InviteCode::new_within_quota(
+        &state.db,
+        &generate_code(INVITE_CODE_LEN),
+        sub,
+        state.config.invite_quota,
+    )
+    .await
+    .expect("failed to create invite code") else {
+        return Err(StatusCode::FORBIDDEN);
+    };
+
+    Ok((StatusCode::CREATED, Json(code.into())))
+}
+
+/// Private Endpoint: Changes the username, keeping the old one reserved for a while
+#[utoipa::path(
+    put,
+    path = "/user/username",
+    tag = "user",
+    request_body = UsernameChangeBody,
+    responses(
+        (status = StatusCode::OK, description = "Success", body = UserInfo),
+        (status = StatusCode::NOT_MODIFIED, description = "Nothing has changed"),
+        (status = StatusCode::BAD_REQUEST, description = "Invalid request"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::CONFLICT, description = "Conflicts with the current state"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+        (
+            status = StatusCode::TOO_MANY_REQUESTS,
+            description = "Username has been changed recently",
+        ),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn user_username(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+    Json(body): Json<UsernameChangeBody>,
+) -> impl IntoResponse {
+    if body.validate().is_err() {
+        return StatusCode::BAD_REQUEST.into_response();
+    }
+
+    let Some(mut user) = User::find_by_uuid(&state.db, sub)
+        .await
+        .expect("failed to retrieve user data from db")
+    else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    if user.username == body.username {
+        return StatusCode::NOT_MODIFIED.into_response();
+    }
+
+    if let Err(violation) = state.names.check(&body.username) {
+        return (StatusCode::CONFLICT, violation.to_string()).into_response();
+    }
+
+    if UsernameHistory::is_reserved(&state.db, &body.username, Some(sub))
+        .await
+        .expect("failed to check username history")
+    {
+        return (
+            StatusCode::CONFLICT,
+            format!("username '{}' already taken", body.username),
+        )
+            .into_response();
+    }
+
+    match user
+        .update_username(
+            &state.db,
+            body.username.clone(),
+            OffsetDateTime::now_utc() + Duration::seconds(state.config.username_reservation_period),
+            Some(Duration::seconds(state.config.username_change_cooldown)),
+        )
+        .await
+    {
+        Ok(None) => Json(UserInfo::from(user)).into_response(),
+        Ok(Some(available_at)) => (
+            StatusCode::TOO_MANY_REQUESTS,
+            format!(
+                "username can be changed after {}",
+                available_at.unix_timestamp()
+            ),
+        )
+            .into_response(),
+        Err(sqlx::Error::Database(err)) if err.constraint() == Some("User_username_key") => (
+            StatusCode::CONFLICT,
+            format!("username '{}' already taken", body.username),
+        )
+            .into_response(),
+        Err(err) => {
+            error!(?err);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Private Endpoint: Requests email change that has to be confirmed from the new address
+#[utoipa::path(
+    put,
+    path = "/user/email",
+    tag = "user",
+    request_body = EmailChangeBody,
+    responses(
+        (
+            status = StatusCode::ACCEPTED,
+            description = "Confirmation has been sent to the new address",
+        ),
+        (status = StatusCode::NOT_MODIFIED, description = "Nothing has changed"),
+        (status = StatusCode::BAD_REQUEST, description = "Invalid request"),
+        (status = StatusCode::UNAUTHORIZED, description = "Invalid password"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn user_email(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+    Json(body): Json<EmailChangeBody>,
+) -> StatusCode {
+    if body.validate().is_err() {
+        return StatusCode::BAD_REQUEST;
+    }
+
+    let Some(user) = User::find_by_uuid(&state.db, sub)
+        .await
+        .expect("failed to retrieve user data from db")
+    else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    if !verify_password(&body.password, &user.password) {
+        return StatusCode::UNAUTHORIZED;
+    }
+
+    if user.email.eq_ignore_ascii_case(&body.email) {
+        return StatusCode::NOT_MODIFIED;
+    }
+
+    let now = OffsetDateTime::now_utc();
+    let change = EmailChange::new(
+        &state.db,
+        &user,
+        body.email,
+        now + Duration::seconds(state.config.email_confirmation_period),
+        now + Duration::seconds(state.config.email_revert_period),
+    )
+    .await
+    .expect("failed to create email change");
+
+    let url = &state.config.public_url;
+    let delivery = async {
+        state
+            .mailer
+            .send(
+                &change.new_email,
+                "Confirm your new email",
+                &format!(
+                    "Confirm that this is the new email of '{}': {url}/v1/user/email/confirm?token={}",
+                    user.username, change.token
+                ),
+            )
+            .await?;
+        state
+            .mailer
+            .send(
+                &change.old_email,
+                "Your email is being changed",
+                &format!(
+                    "The email of '{}' is being changed to {}. If it was not you, revert it: \
+                    {url}/v1/user/email/revert?token={}",
+                    user.username, *change.new_email, change.revert_token
+                ),
+            )
+            .await
+    };
+
+    if let Err(err) = delivery.await {
+        error!(?err);
+        return StatusCode::INTERNAL_SERVER_ERROR;
+    }
+
+    StatusCode::ACCEPTED
+}
+
+/// Public Endpoint: Confirms email change with the token sent to the new address
+#[utoipa::path(
+    get,
+    path = "/user/email/confirm",
+    tag = "user",
+    params(EmailTokenQuery),
+    responses(
+        (status = StatusCode::OK, description = "Success"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::CONFLICT, description = "Conflicts with the current state"),
+    ),
+)]
+pub async fn user_email_confirm(
+    State(state): State<Arc<HubState>>,
+    Query(query): Query<EmailTokenQuery>,
+) -> StatusCode {
+    let Some(mut change) = EmailChange::find_by_token(&state.db, query.token)
+        .await
+        .expect("failed to retrieve email change from db")
+    else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    match change.confirm(&state.db).await {
+        Ok(false) => StatusCode::CONFLICT,
+        Ok(true) => {
+            send_notification(
+                &state,
+                change.sub,
+                NotificationKind::Security,
+                "Email changed",
+                &format!(
+                    "The email of your account has been changed to {}.",
+                    *change.new_email
+                ),
+            )
+            .await;
+            StatusCode::OK
+        }
+        Err(sqlx::Error::Database(err)) if err.constraint() == Some("User_email_key") => {
+            StatusCode::CONFLICT
+        }
+        Err(err) => {
+            error!(?err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Public Endpoint: Reverts email change with the token sent to the old address and ends all sessions
+#[utoipa::path(
+    get,
+    path = "/user/email/revert",
+    tag = "user",
+    params(EmailTokenQuery),
+    responses(
+        (status = StatusCode::OK, description = "Success"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::CONFLICT, description = "Old email has been taken"),
+    ),
+)]
+pub async fn user_email_revert(
+    State(state): State<Arc<HubState>>,
+    Query(query): Query<EmailTokenQuery>,
+) -> StatusCode {
+    let Some(change) = EmailChange::find_by_revert_token(&state.db, query.token)
+        .await
+        .expect("failed to retrieve email change from db")
+    else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    let sub = change.sub;
+
+    match change.revert(&state.db).await {
+        Ok(()) => {}
+        Err(sqlx::Error::Database(err)) if err.constraint() == Some("User_email_key") => {
+            return StatusCode::CONFLICT;
+        }
+        Err(err) => {
+            error!(?err);
+            return StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    }
+    Session::delete_all(&state.db, sub)
+        .await
+        .expect("failed to delete user sessions");
+    Gateway::notify(&state.db, sub, HubEvent::SessionRevoked { session: None }).await;
+    Gateway::leave_game(&state.db, sub).await;
+
+    StatusCode::OK
+}
+
+/// Private Endpoint: Schedules account deletion after the grace period and ends all sessions
+#[utoipa::path(
+    delete,
+    path = "/user",
+    tag = "user",
+    request_body = AccountDeleteBody,
+    responses(
+        (status = StatusCode::OK, description = "Success", body = UserData),
+        (status = StatusCode::BAD_REQUEST, description = "Invalid request"),
+        (status = StatusCode::UNAUTHORIZED, description = "Invalid password"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn user_delete(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+    Json(body): Json<AccountDeleteBody>,
+) -> Result<Json<UserData>, StatusCode> {
+    if body.validate().is_err() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let Some(mut user) = User::find_by_uuid(&state.db, sub)
+        .await
+        .expect("failed to retrieve user data from db")
+    else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    if !verify_password(&body.password, &user.password) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    user.schedule_deletion(
+        &state.db,
+        OffsetDateTime::now_utc() + Duration::seconds(state.config.deletion_grace_period),
+    )
+    .await
+    .expect("failed to schedule account deletion");
+
+    Session::delete_all(&state.db, sub)
+        .await
+        .expect("failed to delete user sessions");
+    Gateway::notify(&state.db, sub, HubEvent::SessionRevoked { session: None }).await;
+    Gateway::leave_game(&state.db, sub).await;
+
+    Ok(Json(user.into()))
+}
+
+fn export_response(export: &Export, keys: &Keys) -> ExportResponse {
+    ExportResponse {
+        uuid: export.uuid,
+        status: export.status,
+        url: (export.status == ExportStatus::Ready).then(|| {
+            format!(
+                "/v1/user/export/download?token={}",
+                ExportToken::new(export.sub, export.uuid).sign(keys)
+            )
+        }),
+        expires_at: export.expires_at.unix_timestamp(),
+        created_at: export.created_at.unix_timestamp(),
+    }
+}
+
+/// Private Endpoint: Starts generation of the personal data archive
+#[utoipa::path(
+    post,
+    path = "/user/export",
+    tag = "user",
+    responses(
+        (
+            status = StatusCode::ACCEPTED,
+            description = "Archive generation has started",
+            body = ExportResponse,
+        ),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+        (status = StatusCode::TOO_MANY_REQUESTS, description = "Export limit reached"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn user_export_create(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+) -> Result<(StatusCode, Json<ExportResponse>), StatusCode> {
+    let latest = Export::find_latest(&state.db, sub)
+        .await
+        .expect("failed to retrieve export from db");
+
+    let export = match latest {
+        Some(export) if export.status == ExportStatus::Pending => export,
+        _ => {
+            let Some(export) = Export::new(
+                &state.db,
+                sub,
+                OffsetDateTime::now_utc() + Duration::seconds(state.config.export_lifetime),
+                state.config.export_limit,
+            )
+            .await
+            .expect("failed to create export") else {
+                return Err(StatusCode::TOO_MANY_REQUESTS);
+            };
+
+            tokio::spawn(jobs::build_export(state.clone(), export.uuid, sub));
+
+            export
+        }
+    };
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(export_response(&export, &state.keys)),
+    ))
+}
+
+/// Private Endpoint: Returns status of the latest personal data archive and its download link
+#[utoipa::path(
+    get,
+    path = "/user/export",
+    tag = "user",
+    responses(
+        (status = StatusCode::OK, description = "Success", body = ExportResponse),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
+pub async fn user_export(
+    State(state): State<Arc<HubState>>,
+    AccessToken { sub, .. }: AccessToken,
+) -> Result<Json<ExportResponse>, StatusCode> {
+    match Export::find_latest(&state.db, sub)
+        .await
+        .expect("failed to retrieve export from db")
+    {
+        Some(export) => Ok(Json(export_response(&export, &state.keys))),
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+/// Public Endpoint: Downloads the personal data archive using the signed link
+#[utoipa::path(
+    get,
+    path = "/user/export/download",
+    tag = "user",
+    params(ExportDownloadQuery),
+    responses(
+        (status = StatusCode::OK, description = "Personal data archive", body = Object),
+        (status = StatusCode::FORBIDDEN, description = "Invalid or expired link"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+    ),
+)]
+pub async fn user_export_download(
+    State(state): State<Arc<HubState>>,
+    Query(query): Query<ExportDownloadQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let ExportToken { sub, jti, .. } =
+        ExportToken::decode(&query.token, &state.keys).map_err(|_| StatusCode::FORBIDDEN)?;
+
+    match Export::find_by_uuid(&state.db, jti)
+        .await
+        .expect("failed to retrieve export from db")
+    {
+        Some(Export {
+            sub: owner,
+            data: Some(data),
+            ..
+        }) if owner == sub => Ok((
+            [(
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"ecg-hub-export-{jti}.json\""),
+            )],
+            Json(data.0),
+        )),
+        _ => Err(StatusCode::NOT_FOUND),
+    }
+}
+
 /// Private Endpoint: Allows user to retrieve list of active sessions
+#[utoipa::path(
+    get,
+    path = "/user/sessions",
+    tag = "user",
+    responses(
+        (status = StatusCode::OK, description = "Success", body = SessionsResponse),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
 pub async fn user_sessions(
     State(state): State<Arc<HubState>>,
     AccessToken { sub, .. }: AccessToken,
@@ -252,13 +3856,7 @@ pub async fn user_sessions(
             Session::find_by(&state.db, $ct, sub, FindBy::Sub)
                 .await
                 .expect("Failed to execute query while searching for session (user/sessions)")
-                .map(|session| UserSession {
-                    uuid: session.uuid,
-                    ct: $ct,
-                    expires_at: session.exp.unix_timestamp(),
-                    updated_at: session.updated_at.unix_timestamp(),
-                    created_at: session.created_at.unix_timestamp(),
-                })
+                .map(|session| session.to_user_session($ct))
         };
     }
 
@@ -270,6 +3868,23 @@ pub async fn user_sessions(
 }
 
 /// Private Endpoint: Generates a new access token using the refresh token
+#[utoipa::path(
+    get,
+    path = "/token/refresh",
+    tag = "token",
+    responses(
+        (
+            status = StatusCode::OK,
+            description = "New access token, the rotated refresh token is set in a cookie",
+            body = String,
+            content_type = "text/plain",
+        ),
+        (status = StatusCode::FORBIDDEN, description = "Invalid refresh token"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing refresh token cookie"),
+    ),
+    security(("refresh_token" = [])),
+)]
 pub async fn token_refresh(
     State(state): State<Arc<HubState>>,
     mut jar: CookieJar,
@@ -288,6 +3903,7 @@ pub async fn token_refresh(
                         .await
                         .expect("Failed to refresh session");
                     jar = jar.add(RefreshToken::from((&session, ct)).to_cookie(&state.keys));
+                    metrics::refresh_rotation(ct);
                 }
 
                 Ok((jar, AccessToken::from((&session, ct)).sign(&state.keys)))
@@ -303,16 +3919,39 @@ pub async fn token_refresh(
 }
 
 /// Private Endpoint: Ends current session with the access token
+#[utoipa::path(
+    get,
+    path = "/token/revoke",
+    tag = "token",
+    responses(
+        (status = StatusCode::OK, description = "Success"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
 pub async fn token_revoke(
     State(state): State<Arc<HubState>>,
-    AccessToken { iss, ct, .. }: AccessToken,
+    AccessToken { iss, sub, ct, .. }: AccessToken,
 ) -> StatusCode {
     if let Some(session) = Session::find_by(&state.db, ct, iss, FindBy::Uuid)
         .await
         .expect("Failed to execute query while searching for session (token/revoke)")
     {
         match session.delete(&state.db, ct).await {
-            Ok(_) => StatusCode::OK,
+            Ok(_) => {
+                Gateway::notify(
+                    &state.db,
+                    sub,
+                    HubEvent::SessionRevoked { session: Some(iss) },
+                )
+                .await;
+                if ct == ClientType::Game {
+                    Gateway::leave_game(&state.db, sub).await;
+                }
+                StatusCode::OK
+            }
             Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     } else {
@@ -321,6 +3960,23 @@ pub async fn token_revoke(
 }
 
 /// Private Endpoint: Ends all user session and creates a new one for current client type
+#[utoipa::path(
+    get,
+    path = "/token/revoke_all",
+    tag = "token",
+    responses(
+        (
+            status = StatusCode::OK,
+            description = "Access token of the new session, the refresh token is set in a cookie",
+            body = String,
+            content_type = "text/plain",
+        ),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::NOT_FOUND, description = "Not found"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
 pub async fn token_revoke_all(
     State(state): State<Arc<HubState>>,
     jar: CookieJar,
@@ -346,6 +4002,9 @@ pub async fn token_revoke_all(
         delete_session!(ClientType::Game);
         delete_session!(ClientType::Mobile);
 
+        Gateway::notify(&state.db, sub, HubEvent::SessionRevoked { session: None }).await;
+        Gateway::leave_game(&state.db, sub).await;
+
         let session = Session::new(&state.db, ct, sub)
             .await
             .expect("Failed to create new session");
@@ -360,13 +4019,49 @@ pub async fn token_revoke_all(
 }
 
 /// Private Endpoint: Allows user to generate PIT for joining game servers
+#[utoipa::path(
+    get,
+    path = "/token/pit",
+    tag = "token",
+    params(PITQuery),
+    responses(
+        (
+            status = StatusCode::OK,
+            description = "Player identity token",
+            body = String,
+            content_type = "text/plain",
+        ),
+        (status = StatusCode::BAD_REQUEST, description = "Invalid request"),
+        (status = StatusCode::FORBIDDEN, description = "Invalid access token"),
+        (status = StatusCode::EXPECTATION_FAILED, description = "Missing access token"),
+    ),
+    security(("access_token" = [])),
+)]
 pub async fn token_pit(
     State(state): State<Arc<HubState>>,
     AccessToken { sub, ct, .. }: AccessToken,
     Query(query): Query<PITQuery>,
 ) -> Result<String, StatusCode> {
     if query.validate().is_ok() {
-        Ok(PlayerIdentityToken::new(query.sid, sub, ct).sign(&state.keys))
+        UserPresence::join(&state.db, sub, &query.sid)
+            .await
+            .expect("failed to update user presence");
+        Gateway::publish_presence(
+            &state.db,
+            sub,
+            Presence::InGame {
+                sid: query.sid.clone(),
+            },
+        )
+        .await;
+
+        let mut token = PlayerIdentityToken::new(query.sid, sub, ct);
+        if query.guild {
+            token = with_guild(&state, token).await;
+        }
+        metrics::pits_issued(1);
+
+        Ok(token.sign(&state.keys))
     } else {
         Err(StatusCode::BAD_REQUEST)
     }
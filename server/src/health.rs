@@ -0,0 +1,162 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use common::hub::{ComponentHealth, HealthReport, HealthStatus, HubMode};
+use jsonwebtoken::{decode, encode, get_current_timestamp, Algorithm, Header};
+use serde_json::{json, Value};
+use tokio::time::timeout;
+use tracing::{debug, warn};
+
+use crate::{app::HubState, error::Error, migrations};
+
+/// Checks whether the hub and its dependencies are able to serve requests.
+/// Details are only logged, the report is public.
+pub async fn check(state: &HubState) -> HealthReport {
+    let mut components = BTreeMap::new();
+
+    components.insert(String::from("hub"), check_hub(state));
+    components.insert(String::from("database"), check_database(state).await);
+    components.insert(String::from("pool"), check_pool(state));
+    components.insert(String::from("migrations"), check_migrations(state).await);
+    components.insert(String::from("keys"), check_keys(state));
+
+    for (name, component) in &mut components {
+        let Some(message) = component.message.take() else {
+            continue;
+        };
+
+        match component.status {
+            HealthStatus::Ok => {}
+            HealthStatus::Degraded => {
+                debug!(component = name, reason = message, "Component is degraded")
+            }
+            HealthStatus::Down => warn!(component = name, reason = message, "Component is down"),
+        }
+    }
+
+    HealthReport {
+        status: components
+            .values()
+            .map(|component| component.status)
+            .max()
+            .unwrap_or(HealthStatus::Ok),
+        components,
+    }
+}
+
+fn check_hub(state: &HubState) -> ComponentHealth {
+    if state.is_shutting_down() {
+        component(
+            HealthStatus::Down,
+            None,
+            Some(String::from("shutting down")),
+        )
+    } else {
+        component(HealthStatus::Ok, None, None)
+    }
+}
+
+async fn check_database(state: &HubState) -> ComponentHealth {
+    let started = Instant::now();
+    let result = timeout(
+        Duration::from_millis(state.config.health_timeout),
+        sqlx::query("SELECT 1").execute(&state.db),
+    )
+    .await;
+    let latency = started.elapsed().as_millis() as u64;
+
+    match result {
+        Ok(Ok(_)) if latency > state.config.health_db_latency => component(
+            HealthStatus::Degraded,
+            Some(latency),
+            Some(String::from("slow response")),
+        ),
+        Ok(Ok(_)) => component(HealthStatus::Ok, Some(latency), None),
+        Ok(Err(err)) => component(HealthStatus::Down, Some(latency), Some(err.to_string())),
+        Err(_) => component(
+            HealthStatus::Down,
+            Some(latency),
+            Some(String::from("timed out")),
+        ),
+    }
+}
+
+fn check_pool(state: &HubState) -> ComponentHealth {
+    let size = state.db.size();
+    let used = size.saturating_sub(state.db.num_idle() as u32);
+    let max = state.config.db_pool_max;
+
+    let status = if used * 100 >= max * u32::from(state.config.health_pool_saturation) {
+        HealthStatus::Degraded
+    } else {
+        HealthStatus::Ok
+    };
+
+    component(
+        status,
+        None,
+        Some(format!("{used} of {max} connections in use")),
+    )
+}
+
+async fn check_migrations(state: &HubState) -> ComponentHealth {
+    let started = Instant::now();
+    let result = timeout(
+        Duration::from_millis(state.config.health_timeout),
+        migrations::pending(&state.db),
+    )
+    .await;
+    let latency = Some(started.elapsed().as_millis() as u64);
+
+    match result {
+        Ok(Ok(pending)) if pending.is_empty() => component(HealthStatus::Ok, latency, None),
+        Ok(Ok(pending)) => component(
+            HealthStatus::Degraded,
+            latency,
+            Some(format!("{} migration(s) pending", pending.len())),
+        ),
+        Ok(Err(Error::MigrationError(err))) => component(HealthStatus::Down, latency, Some(err)),
+        Ok(Err(err)) => component(HealthStatus::Down, latency, Some(format!("{err:?}"))),
+        Err(_) => component(HealthStatus::Down, latency, Some(String::from("timed out"))),
+    }
+}
+
+/// Signs and verifies a short-lived token
+fn check_keys(state: &HubState) -> ComponentHealth {
+    let claims = json!({ "exp": get_current_timestamp() + 60 });
+
+    let verified = encode(
+        &Header::new(Algorithm::EdDSA),
+        &claims,
+        &state.keys.encoding,
+    )
+    .map(|token| decode::<Value>(&token, &state.keys.decoding, &state.keys.validation));
+
+    match verified {
+        Ok(Ok(_)) if state.config.private_key.is_none() && state.config.mode != HubMode::Debug => {
+            component(
+                HealthStatus::Degraded,
+                None,
+                Some(String::from(
+                    "ephemeral key, tokens will not survive restart",
+                )),
+            )
+        }
+        Ok(Ok(_)) => component(HealthStatus::Ok, None, None),
+        Ok(Err(err)) | Err(err) => component(HealthStatus::Down, None, Some(err.to_string())),
+    }
+}
+
+fn component(
+    status: HealthStatus,
+    latency: Option<u64>,
+    message: Option<String>,
+) -> ComponentHealth {
+    ComponentHealth {
+        status,
+        latency,
+        message,
+    }
+}
@@ -0,0 +1,140 @@
+use std::{sync::Arc, time::Duration};
+
+use common::events::Presence;
+use tokio::{sync::watch, task::JoinHandle, time::interval};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::{
+    app::HubState,
+    avatar::Avatar,
+    gateway::Gateway,
+    metrics,
+    models::entities::{
+        EmailChange, Export, Message, Notification, SpentChallenge, User, UserArchive, UserPresence,
+    },
+    storage::Storage,
+    DB,
+};
+
+/// Spawns background workers that maintain the hub state, they stop once the hub shuts down
+pub fn spawn(state: &Arc<HubState>) -> Vec<JoinHandle<()>> {
+    let mut handles = vec![
+        tokio::spawn(
+            state
+                .gateway
+                .listen(state.db.clone(), state.shutdown.subscribe()),
+        ),
+        tokio::spawn(maintenance(state.clone(), state.shutdown.subscribe())),
+        tokio::spawn(presence(state.clone(), state.shutdown.subscribe())),
+    ];
+
+    if state.config.metrics_enabled {
+        handles.push(tokio::spawn(metrics::sample(
+            state.clone(),
+            state.shutdown.subscribe(),
+        )));
+    }
+
+    handles
+}
+
+/// Periodically purges stale data
+pub async fn maintenance(state: Arc<HubState>, mut shutdown: watch::Receiver<bool>) {
+    let db = &state.db;
+    let mut interval = interval(Duration::from_secs(state.config.jobs_interval));
+    let message_retention = time::Duration::seconds(state.config.message_retention);
+    let notification_retention = time::Duration::seconds(state.config.notification_retention);
+    let export_timeout = time::Duration::seconds(state.config.export_timeout);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {},
+            _ = shutdown.changed() => break,
+        }
+
+        report(
+            "deleted accounts",
+            purge_deleted_accounts(db, &*state.storage).await,
+        );
+        report(
+            "expired email changes",
+            EmailChange::purge_expired(db).await,
+        );
+        match Export::fail_stalled(db, export_timeout).await {
+            Ok(0) => {}
+            Ok(count) => warn!(count, "Failed stalled exports"),
+            Err(err) => error!(?err, "Failed to fail stalled exports"),
+        }
+        report("expired exports", Export::purge_expired(db).await);
+        report("spent challenges", SpentChallenge::purge_expired(db).await);
+        report("messages", Message::purge(db, message_retention).await);
+        report(
+            "notifications",
+            Notification::purge(db, notification_retention).await,
+        );
+    }
+}
+
+/// Periodically takes users offline whose presence has not been refreshed in time
+pub async fn presence(state: Arc<HubState>, mut shutdown: watch::Receiver<bool>) {
+    let mut interval = interval(Duration::from_secs(state.config.presence_ttl) / 3);
+    let ttl = time::Duration::seconds(state.config.presence_ttl as i64);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {},
+            _ = shutdown.changed() => break,
+        }
+
+        match UserPresence::expire(&state.db, ttl).await {
+            Ok(expired) => {
+                for sub in expired {
+                    Gateway::publish_presence(&state.db, sub, Presence::Offline).await;
+                }
+            }
+            Err(err) => error!(?err, "Failed to expire user presence"),
+        }
+    }
+}
+
+/// Purges accounts whose deletion grace period has ended together with their avatars
+async fn purge_deleted_accounts(db: &DB, storage: &dyn Storage) -> Result<u64, sqlx::Error> {
+    let users = User::find_purgeable(db).await?;
+
+    for user in &users {
+        user.purge(db).await?;
+
+        if let Some(avatar) = user.profile().avatar {
+            if let Err(err) = Avatar::delete_unused(db, storage, &avatar).await {
+                error!(?err, "Failed to delete avatar of purged account");
+            }
+        }
+    }
+
+    Ok(users.len() as u64)
+}
+
+fn report(name: &str, result: Result<u64, sqlx::Error>) {
+    match result {
+        Ok(0) => {}
+        Ok(count) => info!(count, "Purged {name}"),
+        Err(err) => error!(?err, "Failed to purge {name}"),
+    }
+}
+
+/// Collects the user archive and stores it in the export job
+pub async fn build_export(state: Arc<HubState>, export: Uuid, sub: Uuid) {
+    let data = match UserArchive::collect(&state.db, sub).await {
+        Ok(Some(archive)) => serde_json::to_value(archive).ok(),
+        Ok(None) => None,
+        Err(err) => {
+            error!(?err, %export, "Failed to collect user archive");
+            None
+        }
+    };
+
+    if let Err(err) = Export::complete(&state.db, export, data).await {
+        error!(?err, %export, "Failed to store user archive");
+    }
+}
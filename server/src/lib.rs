@@ -1,10 +1,41 @@
+/// Implements `Debug` that prints the listed secret fields as redacted, so the values can not
+/// end up in logs
+macro_rules! redacted_debug {
+    ($type: ident { $($field: ident),* $(,)? }, { $($secret: ident),* $(,)? }) => {
+        impl std::fmt::Debug for $type {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.debug_struct(stringify!($type))
+                    $(.field(stringify!($field), &self.$field))*
+                    $(.field(stringify!($secret), &format_args!("{}", $crate::logging::REDACTED)))*
+                    .finish()
+            }
+        }
+    };
+}
+
 pub mod app;
+pub mod avatar;
+pub mod challenge;
+pub mod cli;
 pub mod config;
 pub mod error;
+pub mod gateway;
 pub mod handlers;
+pub mod health;
+pub mod jobs;
 pub mod keys;
+pub mod logging;
+pub mod mailer;
+pub mod metrics;
+pub mod migrations;
 pub mod models;
+pub mod names;
+pub mod openapi;
+pub mod seed;
+pub mod storage;
+pub mod telemetry;
 pub mod types;
 pub mod utils;
+pub mod versioning;
 
 pub type DB = sqlx::PgPool;
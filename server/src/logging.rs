@@ -0,0 +1,305 @@
+use std::{borrow::Cow, fmt, sync::Arc};
+
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tracing::{field::Field, Event, Subscriber};
+use tracing_appender::{
+    non_blocking::WorkerGuard,
+    rolling::{RollingFileAppender, Rotation},
+};
+use tracing_subscriber::{
+    field::{RecordFields, Visit},
+    fmt::{
+        format::Writer,
+        time::{FormatTime, SystemTime},
+        FmtContext, FormatEvent, FormatFields, FormattedFields,
+    },
+    registry::LookupSpan,
+    Layer,
+};
+
+use crate::{config::Config, error::Error};
+
+/// Value written instead of redacted fields
+pub const REDACTED: &str = "<redacted>";
+/// Fields whose names contain any of these words are never logged
+pub const REDACTED_FIELDS: &[&str] = &[
+    "password",
+    "token",
+    "cookie",
+    "seed",
+    "secret",
+    "authorization",
+    "private_key",
+    "db_pass",
+];
+
+lazy_static! {
+    /// `key=value` pairs inside of text, e.g. query parameters of URLs in emails and request spans
+    static ref PARAMETER: Regex = Regex::new(r#"(\w+)=([^&\s"']+)"#).unwrap();
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Default, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Default, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum LogRotation {
+    Hourly,
+    #[default]
+    Daily,
+    Never,
+}
+
+impl From<LogRotation> for Rotation {
+    fn from(rotation: LogRotation) -> Self {
+        match rotation {
+            LogRotation::Hourly => Rotation::HOURLY,
+            LogRotation::Daily => Rotation::DAILY,
+            LogRotation::Never => Rotation::NEVER,
+        }
+    }
+}
+
+/// Builds the log layer writing to stdout or rotating files in the configured format.
+/// The guard has to be kept alive until exit so that buffered lines are flushed.
+pub fn layer<S>(config: &Config) -> Result<(Box<dyn Layer<S> + Send + Sync>, WorkerGuard), Error>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let rules: Arc<[String]> = REDACTED_FIELDS
+        .iter()
+        .map(|field| field.to_string())
+        .chain(config.log_redact.iter().map(|field| field.to_lowercase()))
+        .collect();
+
+    let (writer, guard) = match &config.log_file {
+        Some(path) => {
+            let directory = path.parent().unwrap_or(path.as_ref());
+            let prefix = path.file_name().ok_or_else(|| {
+                Error::ConfigError(format!("log_file ({}) is not a file", path.display()))
+            })?;
+
+            tracing_appender::non_blocking(RollingFileAppender::new(
+                config.log_rotation.into(),
+                directory,
+                prefix,
+            ))
+        }
+        None => tracing_appender::non_blocking(std::io::stdout()),
+    };
+
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .with_ansi(config.log_file.is_none() && matches!(config.log_format, LogFormat::Text));
+
+    let layer = match config.log_format {
+        LogFormat::Text => layer
+            .fmt_fields(RedactedFields { rules, json: false })
+            .boxed(),
+        LogFormat::Json => layer
+            .fmt_fields(RedactedFields { rules, json: true })
+            .event_format(JsonFormat)
+            .boxed(),
+    };
+
+    Ok((layer, guard))
+}
+
+fn is_redacted(rules: &[String], field: &str) -> bool {
+    let field = field.to_lowercase();
+    rules.iter().any(|rule| field.contains(rule.as_str()))
+}
+
+/// Replaces values of sensitive `key=value` pairs inside of the text
+fn scrub<'a>(rules: &[String], text: &'a str) -> Cow<'a, str> {
+    if !text.contains('=') {
+        return Cow::Borrowed(text);
+    }
+
+    PARAMETER.replace_all(text, |captures: &Captures| {
+        if is_redacted(rules, &captures[1]) {
+            format!("{}={REDACTED}", &captures[1])
+        } else {
+            captures[0].to_string()
+        }
+    })
+}
+
+/// Collects fields into a JSON map, replacing sensitive values
+struct RedactingVisitor<'a> {
+    rules: &'a [String],
+    fields: Map<String, Value>,
+}
+
+impl RedactingVisitor<'_> {
+    fn insert(&mut self, field: &Field, value: Value) {
+        let value = match value {
+            _ if is_redacted(self.rules, field.name()) => Value::from(REDACTED),
+            Value::String(text) => Value::from(scrub(self.rules, &text).into_owned()),
+            value => value,
+        };
+
+        self.fields.insert(field.name().to_string(), value);
+    }
+}
+
+impl Visit for RedactingVisitor<'_> {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.insert(field, Value::from(format!("{value:?}")));
+    }
+}
+
+/// Formats span and event fields with redaction, as `key=value` pairs or as a JSON object
+pub struct RedactedFields {
+    rules: Arc<[String]>,
+    json: bool,
+}
+
+impl RedactedFields {
+    fn collect<R: RecordFields>(&self, fields: R) -> Map<String, Value> {
+        let mut visitor = RedactingVisitor {
+            rules: &self.rules,
+            fields: Map::new(),
+        };
+        fields.record(&mut visitor);
+
+        visitor.fields
+    }
+}
+
+impl<'writer> FormatFields<'writer> for RedactedFields {
+    fn format_fields<R: RecordFields>(
+        &self,
+        mut writer: Writer<'writer>,
+        fields: R,
+    ) -> fmt::Result {
+        let mut fields = self.collect(fields);
+
+        if self.json {
+            return write!(writer, "{}", Value::Object(fields));
+        }
+
+        let mut separator = "";
+        if let Some(message) = fields.remove("message") {
+            match message {
+                Value::String(message) => write!(writer, "{message}")?,
+                message => write!(writer, "{message}")?,
+            }
+            separator = " ";
+        }
+
+        for (key, value) in fields {
+            match value {
+                Value::String(value) => write!(writer, "{separator}{key}={value}")?,
+                value => write!(writer, "{separator}{key}={value}")?,
+            }
+            separator = " ";
+        }
+
+        Ok(())
+    }
+
+    fn add_fields(
+        &self,
+        current: &'writer mut FormattedFields<Self>,
+        fields: &tracing::span::Record<'_>,
+    ) -> fmt::Result {
+        if !self.json {
+            if !current.fields.is_empty() {
+                current.fields.push(' ');
+            }
+            return self.format_fields(current.as_writer(), fields);
+        }
+
+        let mut merged = match serde_json::from_str(&current.fields) {
+            Ok(Value::Object(merged)) => merged,
+            _ => Map::new(),
+        };
+        merged.extend(self.collect(fields));
+        current.fields = Value::Object(merged).to_string();
+
+        Ok(())
+    }
+}
+
+/// Writes every event as a single JSON line with the fields of its spans
+pub struct JsonFormat;
+
+impl<S> FormatEvent<S, RedactedFields> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, RedactedFields>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let mut timestamp = String::new();
+        SystemTime.format_time(&mut Writer::new(&mut timestamp))?;
+
+        let mut line = Map::new();
+        line.insert(String::from("timestamp"), Value::from(timestamp));
+        line.insert(
+            String::from("level"),
+            Value::from(event.metadata().level().as_str()),
+        );
+        line.insert(
+            String::from("target"),
+            Value::from(event.metadata().target()),
+        );
+        line.insert(
+            String::from("fields"),
+            Value::Object(ctx.field_format().collect(event)),
+        );
+
+        // Fields of the inner spans take precedence
+        let mut spans = Vec::new();
+        let mut context = Map::new();
+        if let Some(scope) = ctx.event_scope() {
+            for span in scope.from_root() {
+                spans.push(Value::from(span.name()));
+
+                if let Some(fields) = span.extensions().get::<FormattedFields<RedactedFields>>() {
+                    if let Ok(Value::Object(fields)) = serde_json::from_str(&fields.fields) {
+                        context.extend(fields);
+                    }
+                }
+            }
+        }
+        if !spans.is_empty() {
+            line.insert(String::from("spans"), Value::Array(spans));
+            line.insert(String::from("span"), Value::Object(context));
+        }
+
+        writeln!(writer, "{}", Value::Object(line))
+    }
+}
@@ -0,0 +1,22 @@
+use async_trait::async_trait;
+use tracing::info;
+
+use crate::error::Error;
+
+/// Outgoing email transport
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, to: &str, subject: &str, body: &str) -> Result<(), Error>;
+}
+
+/// Writes outgoing emails to the log instead of delivering them, tokens in the links are redacted
+/// by the log layer
+pub struct LogMailer;
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, to: &str, subject: &str, body: &str) -> Result<(), Error> {
+        info!(to, subject, body, "Outgoing email");
+        Ok(())
+    }
+}
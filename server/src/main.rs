@@ -1,31 +1,32 @@
-use ecg_hub::{app::run, config::Config, error::Error, utils::load_dotenv};
+use clap::Parser;
+use ecg_hub::{cli::Cli, config::Config, error::Error, telemetry::Telemetry, utils::load_dotenv};
 use tokio::runtime::Builder;
-use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt};
 
 fn main() -> Result<(), Error> {
+    let cli = Cli::parse();
+
     // Load variables from .env file
     let dotenv_loaded = load_dotenv()?;
 
-    // Parse config from env
-    let config: Config = envy::prefixed("HUB_").from_env()?;
+    // Merge config file, env and flags
+    let config = Config::load(cli.config.as_deref(), &cli.overrides())?;
 
-    // Start logger
-    tracing_subscriber::registry()
-        .with(config.log_filter())
-        .with(fmt::layer())
-        .init();
+    // Start logger and trace exporter
+    let telemetry = Telemetry::init(&config)?;
 
     if dotenv_loaded {
         tracing::info!(".env file has been loaded");
     }
 
-    // Run the server
-    Builder::new_current_thread()
+    // Run the command
+    let result = Builder::new_current_thread()
         .enable_io()
         .enable_time()
         .build()
         .unwrap()
-        .block_on(run(&config))?;
+        .block_on(cli.run(&config));
+
+    telemetry.shutdown();
 
-    Ok(())
+    result
 }
@@ -0,0 +1,127 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use axum::{extract::MatchedPath, http::Request, middleware::Next, response::Response};
+use common::user::ClientType;
+use metrics::{counter, gauge, histogram};
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use tokio::{sync::watch, time::interval};
+use tracing::error;
+
+use crate::{app::HubState, error::Error, models::entities::Session, versioning::LegacyRoute};
+
+pub const HTTP_REQUESTS: &str = "hub_http_requests_total";
+pub const HTTP_REQUEST_DURATION: &str = "hub_http_request_duration_seconds";
+pub const LOGINS: &str = "hub_logins_total";
+pub const REGISTRATIONS: &str = "hub_registrations_total";
+pub const PITS_ISSUED: &str = "hub_pits_issued_total";
+pub const REFRESH_ROTATIONS: &str = "hub_refresh_rotations_total";
+pub const ACTIVE_SESSIONS: &str = "hub_active_sessions";
+pub const DB_POOL_CONNECTIONS: &str = "hub_db_pool_connections";
+pub const DB_POOL_IDLE: &str = "hub_db_pool_idle";
+pub const DB_POOL_MAX: &str = "hub_db_pool_max";
+pub const PASSWORD_HASH_DURATION: &str = "hub_password_hash_duration_seconds";
+
+/// Interval of sampling the gauges that need database queries
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Installs global Prometheus recorder, metrics recorded before this are discarded
+pub fn install() -> Result<PrometheusHandle, Error> {
+    PrometheusBuilder::new()
+        .install_recorder()
+        .map_err(|err| Error::ConfigError(format!("failed to install metrics recorder: {err}")))
+}
+
+fn client_type(ct: ClientType) -> &'static str {
+    match ct {
+        ClientType::Web => "web",
+        ClientType::Game => "game",
+        ClientType::Mobile => "mobile",
+    }
+}
+
+/// Middleware that counts requests and measures their latency per route and status, with
+/// legacy unprefixed requests labeled apart from the v1 route they are served by
+pub async fn track<B>(request: Request<B>, next: Next<B>) -> Response {
+    let started = Instant::now();
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| String::from("unmatched"));
+    let legacy = request.extensions().get::<LegacyRoute>().is_some();
+
+    let response = next.run(request).await;
+
+    let labels = [
+        ("method", method),
+        ("route", route),
+        ("status", response.status().as_u16().to_string()),
+        ("legacy", legacy.to_string()),
+    ];
+    counter!(HTTP_REQUESTS, &labels).increment(1);
+    histogram!(HTTP_REQUEST_DURATION, &labels).record(started.elapsed().as_secs_f64());
+
+    response
+}
+
+pub fn login(ct: ClientType, success: bool) {
+    counter!(
+        LOGINS,
+        "client_type" => client_type(ct),
+        "result" => if success { "success" } else { "failure" },
+    )
+    .increment(1);
+}
+
+pub fn registration() {
+    counter!(REGISTRATIONS).increment(1);
+}
+
+pub fn pits_issued(count: u64) {
+    counter!(PITS_ISSUED).increment(count);
+}
+
+pub fn refresh_rotation(ct: ClientType) {
+    counter!(REFRESH_ROTATIONS, "client_type" => client_type(ct)).increment(1);
+}
+
+/// Records duration of Argon2 hashing or verification started at `started`
+pub fn password_hash(operation: &'static str, started: Instant) {
+    histogram!(PASSWORD_HASH_DURATION, "operation" => operation)
+        .record(started.elapsed().as_secs_f64());
+}
+
+/// Periodically samples the gauges that need database queries, so that scrapes do not hit the
+/// database
+pub async fn sample(state: Arc<HubState>, mut shutdown: watch::Receiver<bool>) {
+    let mut interval = interval(SAMPLE_INTERVAL);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {},
+            _ = shutdown.changed() => break,
+        }
+
+        for ct in [ClientType::Web, ClientType::Game, ClientType::Mobile] {
+            match Session::count_active(&state.db, ct).await {
+                Ok(count) => {
+                    gauge!(ACTIVE_SESSIONS, "client_type" => client_type(ct)).set(count as f64)
+                }
+                Err(err) => error!(?err, "Failed to count active sessions"),
+            }
+        }
+    }
+}
+
+/// Samples pool gauges and renders all metrics in Prometheus text format
+pub fn render(state: &HubState) -> String {
+    gauge!(DB_POOL_CONNECTIONS).set(state.db.size() as f64);
+    gauge!(DB_POOL_IDLE).set(state.db.num_idle() as f64);
+    gauge!(DB_POOL_MAX).set(state.config.db_pool_max as f64);
+
+    state.metrics.render()
+}
@@ -0,0 +1,324 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{pool::PoolConnection, Connection, Executor, Postgres};
+use tracing::{info, warn};
+
+use crate::{error::Error, DB};
+
+/// Reversible schema change embedded in the binary
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub up: &'static str,
+    pub down: &'static str,
+}
+
+macro_rules! migration {
+    ($version: literal, $file: literal) => {
+        Migration {
+            version: $version,
+            name: $file,
+            up: include_str!(concat!("../../migrations/", $file, ".up.sql")),
+            down: include_str!(concat!("../../migrations/", $file, ".down.sql")),
+        }
+    };
+}
+
+/// All migrations in the order they are applied
+pub const MIGRATIONS: &[Migration] = &[
+    migration!(1, "0001_init"),
+    migration!(2, "0002_account_deletion"),
+    migration!(3, "0003_exports"),
+    migration!(4, "0004_name_changes"),
+    migration!(5, "0005_friends"),
+    migration!(6, "0006_presence"),
+    migration!(7, "0007_messages"),
+    migration!(8, "0008_parties"),
+    migration!(9, "0009_guilds"),
+    migration!(10, "0010_notifications"),
+    migration!(11, "0011_invite_codes"),
+    migration!(12, "0012_challenges"),
+    migration!(13, "0013_reserved_accounts"),
+];
+
+impl Migration {
+    /// SHA-256 of the up script, used to detect edited migrations
+    pub fn checksum(&self) -> String {
+        hex::encode(Sha256::digest(self.up))
+    }
+}
+
+/// What the hub does with pending migrations on startup
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrationMode {
+    /// Applies pending migrations
+    #[default]
+    Auto,
+    /// Only warns about pending migrations
+    Verify,
+    /// Refuses to start while there are pending migrations
+    Refuse,
+    /// Prints pending SQL and exits without applying it
+    DryRun,
+}
+
+/// Migration recorded in the state table
+pub struct AppliedMigration {
+    pub version: i64,
+    pub name: String,
+    pub checksum: String,
+}
+
+/// Applies and reverts embedded migrations, holding an advisory lock so that only one hub
+/// instance migrates the database at a time
+pub struct Migrator {
+    conn: PoolConnection<Postgres>,
+}
+
+impl Migrator {
+    /// Arbitrary advisory lock key shared by all hub instances
+    const LOCK_KEY: i64 = 0x0ec9_4b00;
+
+    pub async fn new(db: &DB) -> Result<Self, Error> {
+        let mut conn = db.acquire().await?;
+
+        // Taken before the state table is created so that starting instances do not race
+        sqlx::query("SELECT pg_advisory_lock($1)")
+            .bind(Self::LOCK_KEY)
+            .execute(&mut conn)
+            .await?;
+
+        conn.execute(
+            r#"CREATE TABLE IF NOT EXISTS "SchemaMigration" (
+                version bigint primary key,
+                name text not null,
+                checksum text not null,
+                applied_at timestamptz not null default now()
+            )"#,
+        )
+        .await?;
+
+        let mut migrator = Self { conn };
+        migrator.adopt().await?;
+
+        Ok(migrator)
+    }
+
+    /// Records the initial migration as applied if the schema has been created from `init.sql`
+    /// before the migrations were versioned
+    async fn adopt(&mut self) -> Result<(), Error> {
+        let unversioned = sqlx::query_scalar::<_, bool>(
+            r#"SELECT to_regclass('"User"') IS NOT NULL
+                AND NOT EXISTS (SELECT 1 FROM "SchemaMigration")"#,
+        )
+        .fetch_one(&mut self.conn)
+        .await?;
+
+        if unversioned {
+            warn!(
+                "Adopting unversioned schema as migration 1, \
+                run `migrate baseline <version>` first if it has been applied from a newer init.sql"
+            );
+            self.baseline(1).await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn applied(&mut self) -> Result<Vec<AppliedMigration>, Error> {
+        fetch_applied(&mut self.conn).await
+    }
+
+    /// Returns migrations that have not been applied yet,
+    /// failing if an applied migration has been edited or is unknown to this binary
+    pub async fn pending(&mut self) -> Result<Vec<&'static Migration>, Error> {
+        verify(self.applied().await?)
+    }
+
+    /// Applies all pending migrations, each in its own transaction
+    pub async fn up(&mut self) -> Result<usize, Error> {
+        let pending = self.pending().await?;
+
+        for migration in &pending {
+            let mut tx = self.conn.begin().await?;
+
+            tx.execute(migration.up).await?;
+            record(&mut tx, migration).await?;
+
+            tx.commit().await?;
+            info!(migration.version, migration.name, "Applied migration");
+        }
+
+        Ok(pending.len())
+    }
+
+    /// Records pending migrations up to `target` as applied without running them,
+    /// for schemas that have been applied by hand
+    pub async fn baseline(&mut self, target: i64) -> Result<usize, Error> {
+        let pending = self.pending().await?;
+
+        let mut recorded = 0;
+        for migration in pending
+            .iter()
+            .filter(|migration| migration.version <= target)
+        {
+            record(&mut self.conn, migration).await?;
+            info!(
+                migration.version,
+                migration.name, "Recorded migration as applied"
+            );
+            recorded += 1;
+        }
+
+        Ok(recorded)
+    }
+
+    /// Reverts applied migrations newer than `target`, starting from the latest one
+    pub async fn down(&mut self, target: i64) -> Result<usize, Error> {
+        // Also verifies checksums of the applied migrations
+        self.pending().await?;
+
+        let mut reverted = 0;
+        for applied in self.applied().await?.iter().rev() {
+            if applied.version <= target {
+                break;
+            }
+
+            let migration = MIGRATIONS
+                .iter()
+                .find(|migration| migration.version == applied.version)
+                .expect("applied migration has been verified");
+
+            let mut tx = self.conn.begin().await?;
+
+            tx.execute(migration.down).await?;
+            sqlx::query(r#"DELETE FROM "SchemaMigration" WHERE version = $1"#)
+                .bind(migration.version)
+                .execute(&mut tx)
+                .await?;
+
+            tx.commit().await?;
+            info!(migration.version, migration.name, "Reverted migration");
+            reverted += 1;
+        }
+
+        Ok(reverted)
+    }
+
+    pub async fn unlock(mut self) -> Result<(), Error> {
+        sqlx::query("SELECT pg_advisory_unlock($1)")
+            .bind(Self::LOCK_KEY)
+            .execute(&mut self.conn)
+            .await?;
+
+        Ok(())
+    }
+}
+
+async fn record<'c, E>(executor: E, migration: &Migration) -> Result<(), Error>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    sqlx::query(r#"INSERT INTO "SchemaMigration" (version, name, checksum) VALUES ($1, $2, $3)"#)
+        .bind(migration.version)
+        .bind(migration.name)
+        .bind(migration.checksum())
+        .execute(executor)
+        .await?;
+
+    Ok(())
+}
+
+async fn fetch_applied<'c, E>(executor: E) -> Result<Vec<AppliedMigration>, Error>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    Ok(sqlx::query_as::<_, (i64, String, String)>(
+        r#"SELECT version, name, checksum FROM "SchemaMigration" ORDER BY version"#,
+    )
+    .fetch_all(executor)
+    .await?
+    .into_iter()
+    .map(|(version, name, checksum)| AppliedMigration {
+        version,
+        name,
+        checksum,
+    })
+    .collect())
+}
+
+/// Checks checksums of the applied migrations and returns the pending ones
+fn verify(applied: Vec<AppliedMigration>) -> Result<Vec<&'static Migration>, Error> {
+    let applied: HashMap<i64, AppliedMigration> = applied
+        .into_iter()
+        .map(|migration| (migration.version, migration))
+        .collect();
+
+    for (version, migration) in &applied {
+        match MIGRATIONS.iter().find(|known| known.version == *version) {
+            Some(known) if known.checksum() == migration.checksum => {}
+            Some(_) => {
+                return Err(Error::MigrationError(format!(
+                    "migration {version} ({}) has been edited after it was applied",
+                    migration.name
+                )))
+            }
+            None => {
+                return Err(Error::MigrationError(format!(
+                    "migration {version} ({}) is unknown, the binary is older than the database",
+                    migration.name
+                )))
+            }
+        }
+    }
+
+    Ok(MIGRATIONS
+        .iter()
+        .filter(|migration| !applied.contains_key(&migration.version))
+        .collect())
+}
+
+/// Returns pending migrations without taking the lock, e.g. for health checks
+pub async fn pending(db: &DB) -> Result<Vec<&'static Migration>, Error> {
+    verify(fetch_applied(db).await?)
+}
+
+/// Handles pending migrations according to the mode, returns `false` if the hub should not start
+pub async fn run(db: &DB, mode: MigrationMode) -> Result<bool, Error> {
+    let mut migrator = Migrator::new(db).await?;
+    let pending = migrator.pending().await?;
+
+    let start = match mode {
+        MigrationMode::Auto => {
+            migrator.up().await?;
+            true
+        }
+        MigrationMode::Verify => {
+            for migration in &pending {
+                warn!(migration.version, migration.name, "Migration is pending");
+            }
+            true
+        }
+        MigrationMode::Refuse if !pending.is_empty() => {
+            migrator.unlock().await?;
+            return Err(Error::MigrationError(format!(
+                "{} migration(s) pending, apply them before starting the hub",
+                pending.len()
+            )));
+        }
+        MigrationMode::Refuse => true,
+        MigrationMode::DryRun => {
+            for migration in &pending {
+                println!("-- {}\n{}", migration.name, migration.up);
+            }
+            false
+        }
+    };
+
+    migrator.unlock().await?;
+
+    Ok(start)
+}
@@ -1,6 +1,17 @@
 use std::collections::HashMap;
 
-use common::user::{ClientType, UserData, UserInfo, UserStatus};
+use common::{
+    events::{FriendPresence, Presence},
+    guild::{GuildMember, GuildPermissions, GuildProfile, GuildRequest, GuildRequestKind},
+    notification::{Notification as NotificationData, NotificationKind},
+    profile::{Profile, PublicProfile},
+    social::{
+        Conversation, DirectMessage, Friend, FriendStatus, PartyInfo, PartyInvite, PartyMember,
+    },
+    user::{
+        ClientType, ExportStatus, Invite, UserData, UserInfo, UserRole, UserSession, UserStatus,
+    },
+};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use sqlx::{
@@ -8,11 +19,15 @@ use sqlx::{
     types::{Json, Uuid},
     Error, FromRow,
 };
-use time::OffsetDateTime;
+use time::{Duration, OffsetDateTime};
+use tracing::instrument;
 
 use crate::{types::CiText, DB};
 
-use super::tokens::{RefreshToken, SecurityToken};
+use super::{
+    parsers::BroadcastSegment,
+    tokens::{RefreshToken, SecurityToken},
+};
 
 pub enum FindBy {
     Uuid,
@@ -25,7 +40,7 @@ pub enum FindBy {
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 
 /// Represents user account data
-#[derive(FromRow, Deserialize, Serialize, Clone, Debug)]
+#[derive(FromRow, Deserialize, Serialize, Clone)]
 pub struct User {
     pub uuid: Uuid,
     pub username: String,
@@ -33,13 +48,39 @@ pub struct User {
     pub password: String,
     pub other: Json<HashMap<String, Value>>,
     pub status: UserStatus,
+    pub role: UserRole,
     #[sqlx(rename = "updated_at")]
     pub updated: OffsetDateTime,
     #[sqlx(rename = "created_at")]
     pub created: OffsetDateTime,
+    /// Timestamp after which the account will be purged
+    pub deletion_at: Option<OffsetDateTime>,
+    /// Timestamp until which the user can not send messages
+    pub chat_banned_until: Option<OffsetDateTime>,
+    /// Invite code used to register the account
+    pub invite_code: Option<String>,
 }
 
+redacted_debug!(
+    User {
+        uuid,
+        username,
+        email,
+        other,
+        status,
+        role,
+        updated,
+        created,
+        deletion_at,
+        chat_banned_until,
+        invite_code,
+    },
+    { password }
+);
+
 impl User {
+    pub const PROFILE_KEY: &str = "profile";
+
     pub fn new(
         uuid: Uuid,
         username: String,
@@ -54,11 +95,16 @@ impl User {
             password,
             other: Json::default(),
             status,
+            role: UserRole::User,
             updated: OffsetDateTime::now_utc(),
             created: OffsetDateTime::now_utc(),
+            deletion_at: None,
+            chat_banned_until: None,
+            invite_code: None,
         }
     }
 
+    #[instrument(name = "User::find_by_username", skip_all)]
     pub async fn find_by_username(db: &DB, username: &str) -> Result<Option<Self>, Error> {
         sqlx::query_as(r#"SELECT * FROM "User" WHERE username = $1"#)
             .bind(username)
@@ -66,6 +112,7 @@ impl User {
             .await
     }
 
+    #[instrument(name = "User::find_by_uuid", skip_all)]
     pub async fn find_by_uuid(db: &DB, uuid: Uuid) -> Result<Option<Self>, Error> {
         sqlx::query_as(r#"SELECT * FROM "User" WHERE uuid = $1"#)
             .bind(uuid)
@@ -73,6 +120,21 @@ impl User {
             .await
     }
 
+    /// Resolves the account that used the username most recently
+    #[instrument(name = "User::find_by_former_username", skip_all)]
+    pub async fn find_by_former_username(db: &DB, username: &str) -> Result<Option<Self>, Error> {
+        sqlx::query_as(
+            r#"SELECT "User".* FROM "User"
+            JOIN "UsernameHistory" ON "UsernameHistory".sub = "User".uuid
+            WHERE "UsernameHistory".username = $1
+            ORDER BY "UsernameHistory".created_at DESC LIMIT 1"#,
+        )
+        .bind(username)
+        .fetch_optional(db)
+        .await
+    }
+
+    #[instrument(name = "User::insert", skip_all)]
     pub async fn insert(&self, db: &DB) -> Result<PgQueryResult, Error> {
         sqlx::query(r#"INSERT INTO "User" VALUES ($1, $2, $3, $4, $5, $6)"#)
             .bind(self.uuid)
@@ -85,6 +147,46 @@ impl User {
             .await
     }
 
+    /// Inserts the user consuming one use of the invite code, returns `false` if the code is not
+    /// redeemable
+    #[instrument(name = "User::insert_invited", skip_all)]
+    pub async fn insert_invited(&mut self, db: &DB, code: &str) -> Result<bool, Error> {
+        let mut tx = db.begin().await?;
+
+        let Some(code) = sqlx::query_scalar::<_, String>(
+            r#"UPDATE "InviteCode" SET uses = uses + 1
+            WHERE code = $1 AND (max_uses IS NULL OR uses < max_uses)
+                AND (expires_at IS NULL OR expires_at > now())
+            RETURNING code"#,
+        )
+        .bind(code)
+        .fetch_optional(&mut tx)
+        .await?
+        else {
+            return Ok(false);
+        };
+
+        sqlx::query(
+            r#"INSERT INTO "User" (uuid, username, email, password, other, status, invite_code)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)"#,
+        )
+        .bind(self.uuid)
+        .bind(&self.username)
+        .bind(&self.email)
+        .bind(&self.password)
+        .bind(&self.other)
+        .bind(self.status)
+        .bind(&code)
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+        self.invite_code = Some(code);
+
+        Ok(true)
+    }
+
+    #[instrument(name = "User::update_password", skip_all)]
     pub async fn update_password(&self, db: &DB) -> Result<PgQueryResult, Error> {
         sqlx::query(r#"UPDATE "User" SET password = $1 WHERE uuid = $2"#)
             .bind(self.password.clone())
@@ -92,6 +194,294 @@ impl User {
             .execute(db)
             .await
     }
+
+    #[instrument(name = "User::update_status", skip_all)]
+    pub async fn update_status(&mut self, db: &DB, status: UserStatus) -> Result<(), Error> {
+        sqlx::query(r#"UPDATE "User" SET status = $1 WHERE uuid = $2"#)
+            .bind(status)
+            .bind(self.uuid)
+            .execute(db)
+            .await?;
+        self.status = status;
+
+        Ok(())
+    }
+
+    /// Bans the user from sending direct messages until the timestamp, `None` lifts the ban
+    #[instrument(name = "User::update_chat_ban", skip_all)]
+    pub async fn update_chat_ban(
+        &mut self,
+        db: &DB,
+        until: Option<OffsetDateTime>,
+    ) -> Result<(), Error> {
+        sqlx::query(r#"UPDATE "User" SET chat_banned_until = $1 WHERE uuid = $2"#)
+            .bind(until)
+            .bind(self.uuid)
+            .execute(db)
+            .await?;
+        self.chat_banned_until = until;
+
+        Ok(())
+    }
+
+    #[instrument(name = "User::update_role", skip_all)]
+    pub async fn update_role(&mut self, db: &DB, role: UserRole) -> Result<(), Error> {
+        sqlx::query(r#"UPDATE "User" SET role = $1 WHERE uuid = $2"#)
+            .bind(role)
+            .bind(self.uuid)
+            .execute(db)
+            .await?;
+        self.role = role;
+
+        Ok(())
+    }
+
+    /// Renames the user and keeps the old username reserved until `reserved_until`.
+    /// If the username has been changed within `cooldown`, nothing is changed and the time the
+    /// next change is allowed at is returned.
+    #[instrument(name = "User::update_username", skip_all)]
+    pub async fn update_username(
+        &mut self,
+        db: &DB,
+        username: String,
+        reserved_until: OffsetDateTime,
+        cooldown: Option<Duration>,
+    ) -> Result<Option<OffsetDateTime>, Error> {
+        let mut tx = db.begin().await?;
+
+        // Serializes concurrent renames of the user
+        sqlx::query(r#"SELECT 1 FROM "User" WHERE uuid = $1 FOR UPDATE"#)
+            .bind(self.uuid)
+            .execute(&mut tx)
+            .await?;
+
+        if let Some(cooldown) = cooldown {
+            let last_change: Option<OffsetDateTime> = sqlx::query_scalar(
+                r#"SELECT max(created_at) FROM "UsernameHistory" WHERE sub = $1"#,
+            )
+            .bind(self.uuid)
+            .fetch_one(&mut tx)
+            .await?;
+
+            if let Some(available_at) = last_change
+                .map(|last_change| last_change + cooldown)
+                .filter(|available_at| *available_at > OffsetDateTime::now_utc())
+            {
+                return Ok(Some(available_at));
+            }
+        }
+
+        sqlx::query(
+            r#"INSERT INTO "UsernameHistory" (sub, username, reserved_until) VALUES ($1, $2, $3)"#,
+        )
+        .bind(self.uuid)
+        .bind(&self.username)
+        .bind(reserved_until)
+        .execute(&mut tx)
+        .await?;
+
+        sqlx::query(r#"UPDATE "User" SET username = $1 WHERE uuid = $2"#)
+            .bind(&username)
+            .bind(self.uuid)
+            .execute(&mut tx)
+            .await?;
+
+        tx.commit().await?;
+        self.username = username;
+
+        Ok(None)
+    }
+
+    /// Reads the profile from the `other` blob, falling back to defaults if it is malformed
+    pub fn profile(&self) -> Profile {
+        self.other
+            .get(Self::PROFILE_KEY)
+            .and_then(|value| Profile::deserialize(value).ok())
+            .unwrap_or_default()
+    }
+
+    #[instrument(name = "User::update_profile", skip_all)]
+    pub async fn update_profile(&mut self, db: &DB, profile: &Profile) -> Result<(), Error> {
+        let value = serde_json::to_value(profile).expect("failed to serialize profile");
+
+        sqlx::query(r#"UPDATE "User" SET other = jsonb_set(other, $1, $2) WHERE uuid = $3"#)
+            .bind([Self::PROFILE_KEY])
+            .bind(Json(&value))
+            .bind(self.uuid)
+            .execute(db)
+            .await?;
+        self.other.insert(Self::PROFILE_KEY.to_string(), value);
+
+        Ok(())
+    }
+
+    /// Checks whether any profile references the avatar
+    #[instrument(name = "User::is_avatar_used", skip_all)]
+    pub async fn is_avatar_used(db: &DB, hash: &str) -> Result<bool, Error> {
+        sqlx::query_scalar(
+            r#"SELECT EXISTS (SELECT 1 FROM "User" WHERE other -> $1 ->> 'avatar' = $2)"#,
+        )
+        .bind(Self::PROFILE_KEY)
+        .bind(hash)
+        .fetch_one(db)
+        .await
+    }
+
+    pub fn is_chat_banned(&self) -> bool {
+        self.chat_banned_until
+            .is_some_and(|until| until > OffsetDateTime::now_utc())
+    }
+
+    #[instrument(name = "User::schedule_deletion", skip_all)]
+    pub async fn schedule_deletion(&mut self, db: &DB, at: OffsetDateTime) -> Result<(), Error> {
+        self.status = UserStatus::PendingDeletion;
+        self.deletion_at = Some(at);
+
+        sqlx::query(r#"UPDATE "User" SET status = $1, deletion_at = $2 WHERE uuid = $3"#)
+            .bind(self.status)
+            .bind(self.deletion_at)
+            .bind(self.uuid)
+            .execute(db)
+            .await?;
+
+        Ok(())
+    }
+
+    #[instrument(name = "User::cancel_deletion", skip_all)]
+    pub async fn cancel_deletion(&mut self, db: &DB) -> Result<(), Error> {
+        self.status = UserStatus::Active;
+        self.deletion_at = None;
+
+        sqlx::query(r#"UPDATE "User" SET status = $1, deletion_at = NULL WHERE uuid = $2"#)
+            .bind(self.status)
+            .bind(self.uuid)
+            .execute(db)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Lists accounts whose deletion grace period has ended
+    #[instrument(name = "User::find_purgeable", skip_all)]
+    pub async fn find_purgeable(db: &DB) -> Result<Vec<Self>, Error> {
+        sqlx::query_as(r#"SELECT * FROM "User" WHERE status = $1 AND deletion_at <= now()"#)
+            .bind(UserStatus::PendingDeletion)
+            .fetch_all(db)
+            .await
+    }
+
+    /// Anonymises the account and removes everything it owns. Parties and guilds led by the user
+    /// pass to the longest standing (highest ranked) member or are disbanded if there is none.
+    #[instrument(name = "User::purge", skip_all)]
+    pub async fn purge(&self, db: &DB) -> Result<(), Error> {
+        let mut tx = db.begin().await?;
+
+        let party: Option<Uuid> =
+            sqlx::query_scalar(r#"SELECT uuid FROM "Party" WHERE leader = $1"#)
+                .bind(self.uuid)
+                .fetch_optional(&mut tx)
+                .await?;
+        if let Some(party) = party {
+            let successor: Option<Uuid> = sqlx::query_scalar(
+                r#"SELECT sub FROM "PartyMember" WHERE party = $1 AND sub <> $2
+                ORDER BY created_at LIMIT 1"#,
+            )
+            .bind(party)
+            .bind(self.uuid)
+            .fetch_optional(&mut tx)
+            .await?;
+
+            match successor {
+                Some(successor) => sqlx::query(r#"UPDATE "Party" SET leader = $1 WHERE uuid = $2"#)
+                    .bind(successor)
+                    .bind(party),
+                None => sqlx::query(r#"DELETE FROM "Party" WHERE uuid = $1"#).bind(party),
+            }
+            .execute(&mut tx)
+            .await?;
+        }
+
+        let guild: Option<(Uuid, Uuid)> = sqlx::query_as(
+            r#"SELECT "GuildMember".guild, "GuildMember".rank FROM "GuildMember"
+            JOIN "GuildRank" ON "GuildRank".uuid = "GuildMember".rank
+            WHERE "GuildMember".sub = $1 AND "GuildRank".position = 0"#,
+        )
+        .bind(self.uuid)
+        .fetch_optional(&mut tx)
+        .await?;
+        if let Some((guild, leader)) = guild {
+            let successor: Option<Uuid> = sqlx::query_scalar(
+                r#"SELECT "GuildMember".sub FROM "GuildMember"
+                JOIN "GuildRank" ON "GuildRank".uuid = "GuildMember".rank
+                WHERE "GuildMember".guild = $1 AND "GuildMember".sub <> $2
+                ORDER BY "GuildRank".position, "GuildMember".created_at LIMIT 1"#,
+            )
+            .bind(guild)
+            .bind(self.uuid)
+            .fetch_optional(&mut tx)
+            .await?;
+
+            match successor {
+                Some(successor) => {
+                    sqlx::query(r#"UPDATE "GuildMember" SET rank = $1 WHERE sub = $2"#)
+                        .bind(leader)
+                        .bind(successor)
+                        .execute(&mut tx)
+                        .await?;
+                }
+                None => {
+                    // Ranks are protected from deletion while members hold them
+                    sqlx::query(r#"DELETE FROM "GuildMember" WHERE guild = $1"#)
+                        .bind(guild)
+                        .execute(&mut tx)
+                        .await?;
+                    sqlx::query(r#"DELETE FROM "Guild" WHERE uuid = $1"#)
+                        .bind(guild)
+                        .execute(&mut tx)
+                        .await?;
+                }
+            }
+        }
+
+        for query in [
+            r#"DELETE FROM "WebSession" WHERE sub = $1"#,
+            r#"DELETE FROM "GameSession" WHERE sub = $1"#,
+            r#"DELETE FROM "MobileSession" WHERE sub = $1"#,
+            r#"DELETE FROM "UsernameHistory" WHERE sub = $1"#,
+            r#"DELETE FROM "EmailChange" WHERE sub = $1"#,
+            r#"DELETE FROM "Friendship" WHERE sub = $1 OR target = $1"#,
+            r#"DELETE FROM "Message" WHERE sender = $1 OR recipient = $1"#,
+            r#"DELETE FROM "Notification" WHERE sub = $1"#,
+            r#"DELETE FROM "PartyMember" WHERE sub = $1"#,
+            r#"DELETE FROM "PartyInvite" WHERE sub = $1 OR sender = $1"#,
+            r#"DELETE FROM "GuildMember" WHERE sub = $1"#,
+            r#"DELETE FROM "GuildRequest" WHERE sub = $1"#,
+            r#"DELETE FROM "Presence" WHERE sub = $1"#,
+            r#"DELETE FROM "Export" WHERE sub = $1"#,
+        ] {
+            sqlx::query(query).bind(self.uuid).execute(&mut tx).await?;
+        }
+
+        sqlx::query(
+            r#"UPDATE "User" SET
+                username = '~' || left(replace(uuid::text, '-', ''), 23),
+                email = uuid::text || '@deleted.invalid',
+                password = '',
+                other = '{}'::jsonb,
+                status = $1,
+                role = $2,
+                chat_banned_until = NULL,
+                invite_code = NULL
+            WHERE uuid = $3"#,
+        )
+        .bind(UserStatus::Deleted)
+        .bind(UserRole::User)
+        .bind(self.uuid)
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await
+    }
 }
 
 impl From<User> for UserData {
@@ -101,11 +491,38 @@ impl From<User> for UserData {
             username: user.username,
             email: user.email.0,
             status: user.status,
+            role: user.role,
+            deletion_at: user.deletion_at.map(OffsetDateTime::unix_timestamp),
             created_at: user.created.unix_timestamp(),
         }
     }
 }
 
+impl From<User> for PublicProfile {
+    fn from(user: User) -> Self {
+        let Profile {
+            display_name,
+            bio,
+            avatar,
+            country,
+            pronouns,
+            privacy,
+        } = user.profile();
+        let visible = privacy.public;
+
+        Self {
+            uuid: user.uuid,
+            username: user.username,
+            status: user.status,
+            display_name: display_name.filter(|_| visible),
+            bio: bio.filter(|_| visible && privacy.show_bio),
+            avatar: avatar.filter(|_| visible),
+            country: country.filter(|_| visible && privacy.show_country),
+            pronouns: pronouns.filter(|_| visible && privacy.show_pronouns),
+        }
+    }
+}
+
 impl From<User> for UserInfo {
     fn from(user: User) -> Self {
         Self {
@@ -199,6 +616,7 @@ impl Session {
         .concat()
     }
 
+    #[instrument(name = "Session::new", skip_all)]
     pub async fn new(db: &DB, client_type: ClientType, sub: Uuid) -> Result<Self, Error> {
         sqlx::query_as(&Self::query_new(client_type))
             .bind(sub)
@@ -207,6 +625,7 @@ impl Session {
             .await
     }
 
+    #[instrument(name = "Session::find_by", skip_all)]
     pub async fn find_by(
         db: &DB,
         client_type: ClientType,
@@ -219,10 +638,12 @@ impl Session {
             .await
     }
 
+    #[instrument(name = "Session::delete", skip_all)]
     pub async fn delete(&self, db: &DB, client_type: ClientType) -> Result<PgQueryResult, Error> {
         Self::delete_by(db, client_type, self.uuid, FindBy::Uuid).await
     }
 
+    #[instrument(name = "Session::delete_by", skip_all)]
     pub async fn delete_by(
         db: &DB,
         client_type: ClientType,
@@ -235,6 +656,60 @@ impl Session {
             .await
     }
 
+    /// Ends all sessions of the user across every client type
+    #[instrument(name = "Session::delete_all", skip_all)]
+    pub async fn delete_all(db: &DB, sub: Uuid) -> Result<(), Error> {
+        for ct in [ClientType::Web, ClientType::Game, ClientType::Mobile] {
+            Self::delete_by(db, ct, sub, FindBy::Sub).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns client type of an active session of the user, preferring game sessions
+    #[instrument(name = "Session::find_client_type", skip_all)]
+    pub async fn find_client_type(db: &DB, sub: Uuid) -> Result<Option<ClientType>, Error> {
+        for ct in [ClientType::Game, ClientType::Web, ClientType::Mobile] {
+            if let Some(session) = Self::find_by(db, ct, sub, FindBy::Sub).await? {
+                if session.exp > OffsetDateTime::now_utc() {
+                    return Ok(Some(ct));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    #[instrument(name = "Session::count_active", skip_all)]
+    pub async fn count_active(db: &DB, client_type: ClientType) -> Result<i64, Error> {
+        sqlx::query_scalar(&format!(
+            r#"SELECT COUNT(*) FROM "{}" WHERE exp > now()"#,
+            match client_type {
+                ClientType::Web => "WebSession",
+                ClientType::Game => "GameSession",
+                ClientType::Mobile => "MobileSession",
+            }
+        ))
+        .fetch_one(db)
+        .await
+    }
+
+    /// Deletes expired sessions of every client type
+    #[instrument(name = "Session::purge_expired", skip_all)]
+    pub async fn purge_expired(db: &DB) -> Result<u64, Error> {
+        let mut purged = 0;
+
+        for table in ["WebSession", "GameSession", "MobileSession"] {
+            purged += sqlx::query(&format!(r#"DELETE FROM "{table}" WHERE exp < now()"#))
+                .execute(db)
+                .await?
+                .rows_affected();
+        }
+
+        Ok(purged)
+    }
+
+    #[instrument(name = "Session::refresh", skip_all)]
     pub async fn refresh(&mut self, db: &DB, client_type: ClientType) -> Result<(), Error> {
         self.exp = OffsetDateTime::from_unix_timestamp(RefreshToken::new_exp()).unwrap();
         self.token = sqlx::query_scalar(&Self::query_refresh(client_type))
@@ -245,4 +720,1946 @@ impl Session {
 
         Ok(())
     }
+
+    pub fn to_user_session(&self, ct: ClientType) -> UserSession {
+        UserSession {
+            uuid: self.uuid,
+            ct,
+            expires_at: self.exp.unix_timestamp(),
+            updated_at: self.updated_at.unix_timestamp(),
+            created_at: self.created_at.unix_timestamp(),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Invite Code
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Represents code that allows registration while the hub is invite-only
+#[derive(FromRow, Clone, Debug)]
+pub struct InviteCode {
+    pub code: String,
+    /// Creator UUID, `None` if the creator has been deleted
+    pub creator: Option<Uuid>,
+    /// Maximum number of registrations, unlimited if `None`
+    pub max_uses: Option<i32>,
+    pub uses: i32,
+    pub expires_at: Option<OffsetDateTime>,
+    pub created_at: OffsetDateTime,
+}
+
+impl InviteCode {
+    #[instrument(name = "InviteCode::new", skip_all)]
+    pub async fn new(
+        db: &DB,
+        code: &str,
+        creator: Uuid,
+        max_uses: Option<i32>,
+        expires_at: Option<OffsetDateTime>,
+    ) -> Result<Self, Error> {
+        sqlx::query_as(
+            r#"INSERT INTO "InviteCode" (code, creator, max_uses, expires_at)
+            VALUES ($1, $2, $3, $4) RETURNING *"#,
+        )
+        .bind(code)
+        .bind(creator)
+        .bind(max_uses)
+        .bind(expires_at)
+        .fetch_one(db)
+        .await
+    }
+
+    /// Lists all codes from the newest to the oldest
+    #[instrument(name = "InviteCode::list", skip_all)]
+    pub async fn list(db: &DB) -> Result<Vec<Self>, Error> {
+        sqlx::query_as(r#"SELECT * FROM "InviteCode" ORDER BY created_at DESC"#)
+            .fetch_all(db)
+            .await
+    }
+
+    #[instrument(name = "InviteCode::find_by_creator", skip_all)]
+    pub async fn find_by_creator(db: &DB, creator: Uuid) -> Result<Vec<Self>, Error> {
+        sqlx::query_as(r#"SELECT * FROM "InviteCode" WHERE creator = $1 ORDER BY created_at DESC"#)
+            .bind(creator)
+            .fetch_all(db)
+            .await
+    }
+
+    /// Creates single-use code unless the user has already created `quota` codes
+    #[instrument(name = "InviteCode::new_within_quota", skip_all)]
+    pub async fn new_within_quota(
+        db: &DB,
+        code: &str,
+        creator: Uuid,
+        quota: i64,
+    ) -> Result<Option<Self>, Error> {
+        let mut tx = db.begin().await?;
+
+        // Serializes concurrent requests of the user
+        sqlx::query(r#"SELECT 1 FROM "User" WHERE uuid = $1 FOR UPDATE"#)
+            .bind(creator)
+            .execute(&mut tx)
+            .await?;
+
+        let code = sqlx::query_as(
+            r#"INSERT INTO "InviteCode" (code, creator, max_uses) SELECT $1, $2, 1
+            WHERE (SELECT count(*) FROM "InviteCode" WHERE creator = $2) < $3
+            RETURNING *"#,
+        )
+        .bind(code)
+        .bind(creator)
+        .bind(quota)
+        .fetch_optional(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(code)
+    }
+
+    /// Expires the code right away, registrations that used it keep referencing it
+    #[instrument(name = "InviteCode::revoke", skip_all)]
+    pub async fn revoke(db: &DB, code: &str) -> Result<bool, Error> {
+        sqlx::query(
+            r#"UPDATE "InviteCode" SET expires_at = now()
+            WHERE code = $1 AND (expires_at IS NULL OR expires_at > now())"#,
+        )
+        .bind(code)
+        .execute(db)
+        .await
+        .map(|result| result.rows_affected() > 0)
+    }
+}
+
+impl From<InviteCode> for Invite {
+    fn from(code: InviteCode) -> Self {
+        Self {
+            code: code.code,
+            creator: code.creator,
+            max_uses: code.max_uses,
+            uses: code.uses,
+            expires_at: code.expires_at.map(OffsetDateTime::unix_timestamp),
+            created_at: code.created_at.unix_timestamp(),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Spent Challenge
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Represents anti-bot challenge that has already been used, kept until the challenge expires
+#[derive(FromRow, Clone, Copy, Debug)]
+pub struct SpentChallenge {
+    /// Challenge UUID
+    pub jti: Uuid,
+    pub expires_at: OffsetDateTime,
+}
+
+impl SpentChallenge {
+    /// Marks the challenge as spent, returns `false` if it has been spent before
+    #[instrument(name = "SpentChallenge::spend", skip_all)]
+    pub async fn spend(db: &DB, jti: Uuid, exp: i64) -> Result<bool, Error> {
+        sqlx::query(
+            r#"INSERT INTO "SpentChallenge" (jti, expires_at) VALUES ($1, to_timestamp($2))
+            ON CONFLICT (jti) DO NOTHING"#,
+        )
+        .bind(jti)
+        .bind(exp as f64)
+        .execute(db)
+        .await
+        .map(|result| result.rows_affected() > 0)
+    }
+
+    #[instrument(name = "SpentChallenge::purge_expired", skip_all)]
+    pub async fn purge_expired(db: &DB) -> Result<u64, Error> {
+        sqlx::query(r#"DELETE FROM "SpentChallenge" WHERE expires_at < now()"#)
+            .execute(db)
+            .await
+            .map(|result| result.rows_affected())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Username & Email History
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Represents former username of the user
+#[derive(FromRow, Clone, Debug)]
+pub struct UsernameHistory {
+    /// User UUID
+    pub sub: Uuid,
+    /// Former username
+    pub username: String,
+    /// Timestamp until which the username can not be taken by others
+    pub reserved_until: OffsetDateTime,
+    /// Username change timestamp
+    pub created_at: OffsetDateTime,
+}
+
+impl UsernameHistory {
+    #[instrument(name = "UsernameHistory::find_by_sub", skip_all)]
+    pub async fn find_by_sub(db: &DB, sub: Uuid) -> Result<Vec<Self>, Error> {
+        sqlx::query_as(r#"SELECT * FROM "UsernameHistory" WHERE sub = $1 ORDER BY created_at DESC"#)
+            .bind(sub)
+            .fetch_all(db)
+            .await
+    }
+
+    /// Checks whether the username is still reserved by anyone except `sub`
+    #[instrument(name = "UsernameHistory::is_reserved", skip_all)]
+    pub async fn is_reserved(db: &DB, username: &str, sub: Option<Uuid>) -> Result<bool, Error> {
+        sqlx::query_scalar(
+            r#"SELECT EXISTS (SELECT 1 FROM "UsernameHistory"
+            WHERE username = $1 AND reserved_until > now() AND sub IS DISTINCT FROM $2)"#,
+        )
+        .bind(username)
+        .bind(sub)
+        .fetch_one(db)
+        .await
+    }
+}
+
+/// Represents pending or recently confirmed email change
+#[derive(FromRow, Clone, Debug)]
+pub struct EmailChange {
+    pub uuid: Uuid,
+    /// User UUID
+    pub sub: Uuid,
+    pub old_email: CiText,
+    pub new_email: CiText,
+    /// Confirmation token sent to the new address
+    pub token: Uuid,
+    /// Revert token sent to the old address
+    pub revert_token: Uuid,
+    /// Confirmation timestamp
+    pub confirmed_at: Option<OffsetDateTime>,
+    /// Confirmation deadline
+    pub expires_at: OffsetDateTime,
+    /// Revert deadline
+    pub revertable_until: OffsetDateTime,
+    /// Request timestamp
+    pub created_at: OffsetDateTime,
+}
+
+impl EmailChange {
+    #[instrument(name = "EmailChange::new", skip_all)]
+    pub async fn new(
+        db: &DB,
+        user: &User,
+        new_email: String,
+        expires_at: OffsetDateTime,
+        revertable_until: OffsetDateTime,
+    ) -> Result<Self, Error> {
+        sqlx::query_as(
+            r#"INSERT INTO "EmailChange" (sub, old_email, new_email, expires_at, revertable_until)
+            VALUES ($1, $2, $3, $4, $5) RETURNING *"#,
+        )
+        .bind(user.uuid)
+        .bind(user.email.clone())
+        .bind(CiText(new_email))
+        .bind(expires_at)
+        .bind(revertable_until)
+        .fetch_one(db)
+        .await
+    }
+
+    #[instrument(name = "EmailChange::find_by_sub", skip_all)]
+    pub async fn find_by_sub(db: &DB, sub: Uuid) -> Result<Vec<Self>, Error> {
+        sqlx::query_as(r#"SELECT * FROM "EmailChange" WHERE sub = $1 ORDER BY created_at"#)
+            .bind(sub)
+            .fetch_all(db)
+            .await
+    }
+
+    #[instrument(name = "EmailChange::find_by_token", skip_all)]
+    pub async fn find_by_token(db: &DB, token: Uuid) -> Result<Option<Self>, Error> {
+        sqlx::query_as(
+            r#"SELECT * FROM "EmailChange"
+            WHERE token = $1 AND confirmed_at IS NULL AND expires_at > now()"#,
+        )
+        .bind(token)
+        .fetch_optional(db)
+        .await
+    }
+
+    #[instrument(name = "EmailChange::find_by_revert_token", skip_all)]
+    pub async fn find_by_revert_token(db: &DB, token: Uuid) -> Result<Option<Self>, Error> {
+        sqlx::query_as(
+            r#"SELECT * FROM "EmailChange" WHERE revert_token = $1 AND revertable_until > now()"#,
+        )
+        .bind(token)
+        .fetch_optional(db)
+        .await
+    }
+
+    /// Applies the new email, returns `false` if the email has changed since the request
+    #[instrument(name = "EmailChange::confirm", skip_all)]
+    pub async fn confirm(&mut self, db: &DB) -> Result<bool, Error> {
+        let mut tx = db.begin().await?;
+
+        let changed = sqlx::query(r#"UPDATE "User" SET email = $1 WHERE uuid = $2 AND email = $3"#)
+            .bind(self.new_email.clone())
+            .bind(self.sub)
+            .bind(self.old_email.clone())
+            .execute(&mut tx)
+            .await?
+            .rows_affected()
+            > 0;
+
+        if !changed {
+            return Ok(false);
+        }
+
+        self.confirmed_at = Some(
+            sqlx::query_scalar(
+                r#"UPDATE "EmailChange" SET confirmed_at = now() WHERE uuid = $1
+                RETURNING confirmed_at"#,
+            )
+            .bind(self.uuid)
+            .fetch_one(&mut tx)
+            .await?,
+        );
+
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    /// Cancels the change and restores the old email if it has already been confirmed
+    #[instrument(name = "EmailChange::revert", skip_all)]
+    pub async fn revert(self, db: &DB) -> Result<(), Error> {
+        let mut tx = db.begin().await?;
+
+        if self.confirmed_at.is_some() {
+            sqlx::query(r#"UPDATE "User" SET email = $1 WHERE uuid = $2 AND email = $3"#)
+                .bind(self.old_email)
+                .bind(self.sub)
+                .bind(self.new_email)
+                .execute(&mut tx)
+                .await?;
+        }
+
+        sqlx::query(r#"DELETE FROM "EmailChange" WHERE uuid = $1"#)
+            .bind(self.uuid)
+            .execute(&mut tx)
+            .await?;
+
+        tx.commit().await
+    }
+
+    #[instrument(name = "EmailChange::purge_expired", skip_all)]
+    pub async fn purge_expired(db: &DB) -> Result<u64, Error> {
+        sqlx::query(r#"DELETE FROM "EmailChange" WHERE revertable_until <= now()"#)
+            .execute(db)
+            .await
+            .map(|result| result.rows_affected())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Friendship
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Represents directed relation between two users.
+/// Accepted friendship is stored in both directions, requests and blocks only from `sub`.
+#[derive(FromRow, Clone, Copy, Debug)]
+pub struct Friendship {
+    /// User UUID
+    pub sub: Uuid,
+    /// Related user UUID
+    pub target: Uuid,
+    pub status: FriendStatus,
+    /// Last status change timestamp
+    pub updated_at: OffsetDateTime,
+    /// Relation creation timestamp
+    pub created_at: OffsetDateTime,
+}
+
+impl Friendship {
+    #[instrument(name = "Friendship::find", skip_all)]
+    pub async fn find(db: &DB, sub: Uuid, target: Uuid) -> Result<Option<Self>, Error> {
+        sqlx::query_as(r#"SELECT * FROM "Friendship" WHERE sub = $1 AND target = $2"#)
+            .bind(sub)
+            .bind(target)
+            .fetch_optional(db)
+            .await
+    }
+
+    /// Checks whether any of the users blocks the other
+    #[instrument(name = "Friendship::is_blocked", skip_all)]
+    pub async fn is_blocked(db: &DB, a: Uuid, b: Uuid) -> Result<bool, Error> {
+        sqlx::query_scalar(
+            r#"SELECT EXISTS (SELECT 1 FROM "Friendship" WHERE status = $1
+            AND ((sub = $2 AND target = $3) OR (sub = $3 AND target = $2)))"#,
+        )
+        .bind(FriendStatus::Blocked)
+        .bind(a)
+        .bind(b)
+        .fetch_one(db)
+        .await
+    }
+
+    #[instrument(name = "Friendship::are_friends", skip_all)]
+    pub async fn are_friends(db: &DB, a: Uuid, b: Uuid) -> Result<bool, Error> {
+        Ok(matches!(
+            Self::find(db, a, b).await?,
+            Some(Self {
+                status: FriendStatus::Accepted,
+                ..
+            })
+        ))
+    }
+
+    /// Lists users with the given relation from `sub`
+    #[instrument(name = "Friendship::list", skip_all)]
+    pub async fn list(db: &DB, sub: Uuid, status: FriendStatus) -> Result<Vec<Friend>, Error> {
+        Self::query_list(
+            r#"SELECT "User".uuid, "User".username, "Friendship".status, "Friendship".updated_at
+            FROM "Friendship" JOIN "User" ON "User".uuid = "Friendship".target
+            WHERE "Friendship".sub = $1 AND "Friendship".status = $2
+            ORDER BY "User".username"#,
+            db,
+            sub,
+            status,
+        )
+        .await
+    }
+
+    #[instrument(name = "Friendship::list_uuids", skip_all)]
+    pub async fn list_uuids(db: &DB, sub: Uuid) -> Result<Vec<Uuid>, Error> {
+        sqlx::query_scalar(r#"SELECT target FROM "Friendship" WHERE sub = $1 AND status = $2"#)
+            .bind(sub)
+            .bind(FriendStatus::Accepted)
+            .fetch_all(db)
+            .await
+    }
+
+    /// Lists users that sent a friend request to `sub`
+    #[instrument(name = "Friendship::list_incoming", skip_all)]
+    pub async fn list_incoming(db: &DB, sub: Uuid) -> Result<Vec<Friend>, Error> {
+        Self::query_list(
+            r#"SELECT "User".uuid, "User".username, "Friendship".status, "Friendship".updated_at
+            FROM "Friendship" JOIN "User" ON "User".uuid = "Friendship".sub
+            WHERE "Friendship".target = $1 AND "Friendship".status = $2
+            ORDER BY "Friendship".updated_at DESC"#,
+            db,
+            sub,
+            FriendStatus::Pending,
+        )
+        .await
+    }
+
+    /// Lists users that `sub` blocks or is blocked by
+    #[instrument(name = "Friendship::list_blocks", skip_all)]
+    pub async fn list_blocks(db: &DB, sub: Uuid) -> Result<Vec<Uuid>, Error> {
+        sqlx::query_scalar(
+            r#"SELECT target FROM "Friendship" WHERE sub = $1 AND status = $2
+            UNION SELECT sub FROM "Friendship" WHERE target = $1 AND status = $2"#,
+        )
+        .bind(sub)
+        .bind(FriendStatus::Blocked)
+        .fetch_all(db)
+        .await
+    }
+
+    #[instrument(name = "Friendship::query_list", skip_all)]
+    async fn query_list(
+        query: &str,
+        db: &DB,
+        sub: Uuid,
+        status: FriendStatus,
+    ) -> Result<Vec<Friend>, Error> {
+        Ok(
+            sqlx::query_as::<_, (Uuid, String, FriendStatus, OffsetDateTime)>(query)
+                .bind(sub)
+                .bind(status)
+                .fetch_all(db)
+                .await?
+                .into_iter()
+                .map(|(uuid, username, status, since)| Friend {
+                    uuid,
+                    username,
+                    status,
+                    since: since.unix_timestamp(),
+                })
+                .collect(),
+        )
+    }
+
+    #[instrument(name = "Friendship::request", skip_all)]
+    pub async fn request(db: &DB, sub: Uuid, target: Uuid) -> Result<(), Error> {
+        sqlx::query(
+            r#"INSERT INTO "Friendship" (sub, target, status) VALUES ($1, $2, $3)
+            ON CONFLICT DO NOTHING"#,
+        )
+        .bind(sub)
+        .bind(target)
+        .bind(FriendStatus::Pending)
+        .execute(db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Accepts the friend request sent by `from` to `sub`
+    #[instrument(name = "Friendship::accept", skip_all)]
+    pub async fn accept(db: &DB, sub: Uuid, from: Uuid) -> Result<bool, Error> {
+        let mut tx = db.begin().await?;
+
+        let accepted = sqlx::query(
+            r#"UPDATE "Friendship" SET status = $1 WHERE sub = $2 AND target = $3 AND status = $4"#,
+        )
+        .bind(FriendStatus::Accepted)
+        .bind(from)
+        .bind(sub)
+        .bind(FriendStatus::Pending)
+        .execute(&mut tx)
+        .await?
+        .rows_affected()
+            > 0;
+
+        if accepted {
+            sqlx::query(
+                r#"INSERT INTO "Friendship" (sub, target, status) VALUES ($1, $2, $3)
+                ON CONFLICT (sub, target) DO UPDATE SET status = excluded.status"#,
+            )
+            .bind(sub)
+            .bind(from)
+            .bind(FriendStatus::Accepted)
+            .execute(&mut tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(accepted)
+    }
+
+    /// Deletes relation in both directions, except blocks made by `target`
+    #[instrument(name = "Friendship::remove", skip_all)]
+    pub async fn remove(db: &DB, sub: Uuid, target: Uuid) -> Result<bool, Error> {
+        sqlx::query(
+            r#"DELETE FROM "Friendship" WHERE (sub = $1 AND target = $2 AND status <> $3)
+            OR (sub = $2 AND target = $1 AND status <> $3)"#,
+        )
+        .bind(sub)
+        .bind(target)
+        .bind(FriendStatus::Blocked)
+        .execute(db)
+        .await
+        .map(|result| result.rows_affected() > 0)
+    }
+
+    /// Declines the friend request sent by `from` to `sub`
+    #[instrument(name = "Friendship::decline", skip_all)]
+    pub async fn decline(db: &DB, sub: Uuid, from: Uuid) -> Result<bool, Error> {
+        sqlx::query(r#"DELETE FROM "Friendship" WHERE sub = $1 AND target = $2 AND status = $3"#)
+            .bind(from)
+            .bind(sub)
+            .bind(FriendStatus::Pending)
+            .execute(db)
+            .await
+            .map(|result| result.rows_affected() > 0)
+    }
+
+    /// Blocks `target`, dropping friendship and pending requests between the users
+    #[instrument(name = "Friendship::block", skip_all)]
+    pub async fn block(db: &DB, sub: Uuid, target: Uuid) -> Result<(), Error> {
+        let mut tx = db.begin().await?;
+
+        sqlx::query(r#"DELETE FROM "Friendship" WHERE sub = $1 AND target = $2 AND status <> $3"#)
+            .bind(target)
+            .bind(sub)
+            .bind(FriendStatus::Blocked)
+            .execute(&mut tx)
+            .await?;
+
+        sqlx::query(
+            r#"INSERT INTO "Friendship" (sub, target, status) VALUES ($1, $2, $3)
+            ON CONFLICT (sub, target) DO UPDATE SET status = excluded.status"#,
+        )
+        .bind(sub)
+        .bind(target)
+        .bind(FriendStatus::Blocked)
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await
+    }
+
+    #[instrument(name = "Friendship::unblock", skip_all)]
+    pub async fn unblock(db: &DB, sub: Uuid, target: Uuid) -> Result<bool, Error> {
+        sqlx::query(r#"DELETE FROM "Friendship" WHERE sub = $1 AND target = $2 AND status = $3"#)
+            .bind(sub)
+            .bind(target)
+            .bind(FriendStatus::Blocked)
+            .execute(db)
+            .await
+            .map(|result| result.rows_affected() > 0)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Message
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Represents direct message between two users
+#[derive(FromRow, Clone, Debug)]
+pub struct Message {
+    pub uuid: Uuid,
+    /// Sender UUID
+    pub sender: Uuid,
+    /// Recipient UUID
+    pub recipient: Uuid,
+    pub body: String,
+    /// Timestamp when the recipient has read the message
+    pub read_at: Option<OffsetDateTime>,
+    /// Whether the sender has deleted the message for themselves
+    pub sender_deleted: bool,
+    /// Whether the recipient has deleted the message for themselves
+    pub recipient_deleted: bool,
+    pub created_at: OffsetDateTime,
+}
+
+impl Message {
+    #[instrument(name = "Message::new", skip_all)]
+    pub async fn new(db: &DB, sender: Uuid, recipient: Uuid, body: &str) -> Result<Self, Error> {
+        sqlx::query_as(
+            r#"INSERT INTO "Message" (sender, recipient, body) VALUES ($1, $2, $3) RETURNING *"#,
+        )
+        .bind(sender)
+        .bind(recipient)
+        .bind(body)
+        .fetch_one(db)
+        .await
+    }
+
+    /// Returns page of messages between the users visible to `sub` that are older than the
+    /// `before` message, newest first. The page is empty if `before` is not in the conversation.
+    #[instrument(name = "Message::history", skip_all)]
+    pub async fn history(
+        db: &DB,
+        sub: Uuid,
+        peer: Uuid,
+        before: Option<Uuid>,
+        limit: i64,
+    ) -> Result<Vec<Self>, Error> {
+        sqlx::query_as(
+            r#"SELECT * FROM "Message"
+            WHERE ((sender = $1 AND recipient = $2 AND NOT sender_deleted)
+                OR (sender = $2 AND recipient = $1 AND NOT recipient_deleted))
+                AND ($3::uuid IS NULL
+                    OR (created_at, uuid) < (SELECT created_at, uuid FROM "Message" WHERE uuid = $3
+                        AND ((sender = $1 AND recipient = $2) OR (sender = $2 AND recipient = $1))))
+            ORDER BY created_at DESC, uuid DESC LIMIT $4"#,
+        )
+        .bind(sub)
+        .bind(peer)
+        .bind(before)
+        .bind(limit)
+        .fetch_all(db)
+        .await
+    }
+
+    /// Lists all messages visible to the user
+    #[instrument(name = "Message::find_by_user", skip_all)]
+    pub async fn find_by_user(db: &DB, sub: Uuid) -> Result<Vec<Self>, Error> {
+        sqlx::query_as(
+            r#"SELECT * FROM "Message"
+            WHERE (sender = $1 AND NOT sender_deleted) OR (recipient = $1 AND NOT recipient_deleted)
+            ORDER BY created_at"#,
+        )
+        .bind(sub)
+        .fetch_all(db)
+        .await
+    }
+
+    /// Lists conversations of the user with the last message and number of unread messages
+    #[instrument(name = "Message::conversations", skip_all)]
+    pub async fn conversations(db: &DB, sub: Uuid) -> Result<Vec<Conversation>, Error> {
+        Ok(sqlx::query_as::<
+            _,
+            (
+                Uuid,
+                Uuid,
+                Uuid,
+                String,
+                Option<OffsetDateTime>,
+                OffsetDateTime,
+                Uuid,
+                String,
+                i64,
+            ),
+        >(
+            r#"WITH visible AS (
+                SELECT *, CASE WHEN sender = $1 THEN recipient ELSE sender END AS peer
+                FROM "Message"
+                WHERE (sender = $1 AND NOT sender_deleted)
+                    OR (recipient = $1 AND NOT recipient_deleted)
+            )
+            SELECT * FROM (
+                SELECT DISTINCT ON (peer) visible.uuid, visible.sender, visible.recipient,
+                    visible.body, visible.read_at, visible.created_at, visible.peer,
+                    "User".username,
+                    (SELECT count(*) FROM visible AS unread WHERE unread.peer = visible.peer
+                        AND unread.recipient = $1 AND unread.read_at IS NULL)
+                FROM visible JOIN "User" ON "User".uuid = visible.peer
+                ORDER BY visible.peer, visible.created_at DESC
+            ) AS conversations ORDER BY created_at DESC"#,
+        )
+        .bind(sub)
+        .fetch_all(db)
+        .await?
+        .into_iter()
+        .map(
+            |(uuid, sender, recipient, body, read_at, created_at, peer, username, unread)| {
+                Conversation {
+                    uuid: peer,
+                    username,
+                    last_message: DirectMessage {
+                        uuid,
+                        sender,
+                        recipient,
+                        body,
+                        read_at: read_at.map(OffsetDateTime::unix_timestamp),
+                        created_at: created_at.unix_timestamp(),
+                    },
+                    unread,
+                }
+            },
+        )
+        .collect())
+    }
+
+    /// Marks all messages sent by `peer` to `sub` as read
+    #[instrument(name = "Message::mark_read", skip_all)]
+    pub async fn mark_read(db: &DB, sub: Uuid, peer: Uuid) -> Result<u64, Error> {
+        sqlx::query(
+            r#"UPDATE "Message" SET read_at = now()
+            WHERE sender = $1 AND recipient = $2 AND read_at IS NULL"#,
+        )
+        .bind(peer)
+        .bind(sub)
+        .execute(db)
+        .await
+        .map(|result| result.rows_affected())
+    }
+
+    /// Hides the message of the conversation with `peer` from `sub` without affecting `peer`
+    #[instrument(name = "Message::delete_for", skip_all)]
+    pub async fn delete_for(db: &DB, sub: Uuid, peer: Uuid, uuid: Uuid) -> Result<bool, Error> {
+        sqlx::query(
+            r#"UPDATE "Message" SET
+                sender_deleted = sender_deleted OR sender = $1,
+                recipient_deleted = recipient_deleted OR recipient = $1
+            WHERE uuid = $3
+                AND ((sender = $1 AND recipient = $2) OR (sender = $2 AND recipient = $1))"#,
+        )
+        .bind(sub)
+        .bind(peer)
+        .bind(uuid)
+        .execute(db)
+        .await
+        .map(|result| result.rows_affected() > 0)
+    }
+
+    /// Deletes messages older than the retention period and messages deleted by both participants
+    #[instrument(name = "Message::purge", skip_all)]
+    pub async fn purge(db: &DB, retention: Duration) -> Result<u64, Error> {
+        sqlx::query(
+            r#"DELETE FROM "Message"
+            WHERE created_at < $1 OR (sender_deleted AND recipient_deleted)"#,
+        )
+        .bind(OffsetDateTime::now_utc() - retention)
+        .execute(db)
+        .await
+        .map(|result| result.rows_affected())
+    }
+}
+
+impl From<Message> for DirectMessage {
+    fn from(message: Message) -> Self {
+        Self {
+            uuid: message.uuid,
+            sender: message.sender,
+            recipient: message.recipient,
+            body: message.body,
+            read_at: message.read_at.map(OffsetDateTime::unix_timestamp),
+            created_at: message.created_at.unix_timestamp(),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Party
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Represents group of players that join game servers together
+#[derive(FromRow, Clone, Copy, Debug)]
+pub struct Party {
+    pub uuid: Uuid,
+    /// Leader UUID
+    pub leader: Uuid,
+    pub created_at: OffsetDateTime,
+}
+
+impl Party {
+    /// Creates party with the leader as its only member
+    #[instrument(name = "Party::new", skip_all)]
+    pub async fn new(db: &DB, leader: Uuid) -> Result<Self, Error> {
+        let mut tx = db.begin().await?;
+
+        let party: Self = sqlx::query_as(r#"INSERT INTO "Party" (leader) VALUES ($1) RETURNING *"#)
+            .bind(leader)
+            .fetch_one(&mut tx)
+            .await?;
+
+        sqlx::query(r#"INSERT INTO "PartyMember" (sub, party) VALUES ($1, $2)"#)
+            .bind(leader)
+            .bind(party.uuid)
+            .execute(&mut tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(party)
+    }
+
+    #[instrument(name = "Party::find_by_uuid", skip_all)]
+    pub async fn find_by_uuid(db: &DB, uuid: Uuid) -> Result<Option<Self>, Error> {
+        sqlx::query_as(r#"SELECT * FROM "Party" WHERE uuid = $1"#)
+            .bind(uuid)
+            .fetch_optional(db)
+            .await
+    }
+
+    #[instrument(name = "Party::find_by_member", skip_all)]
+    pub async fn find_by_member(db: &DB, sub: Uuid) -> Result<Option<Self>, Error> {
+        sqlx::query_as(
+            r#"SELECT "Party".* FROM "Party"
+            JOIN "PartyMember" ON "PartyMember".party = "Party".uuid
+            WHERE "PartyMember".sub = $1"#,
+        )
+        .bind(sub)
+        .fetch_optional(db)
+        .await
+    }
+
+    /// Lists members in the order they have joined
+    #[instrument(name = "Party::members", skip_all)]
+    pub async fn members(&self, db: &DB) -> Result<Vec<PartyMember>, Error> {
+        Ok(sqlx::query_as::<_, (Uuid, String, OffsetDateTime)>(
+            r#"SELECT "User".uuid, "User".username, "PartyMember".created_at FROM "PartyMember"
+            JOIN "User" ON "User".uuid = "PartyMember".sub
+            WHERE "PartyMember".party = $1 ORDER BY "PartyMember".created_at"#,
+        )
+        .bind(self.uuid)
+        .fetch_all(db)
+        .await?
+        .into_iter()
+        .map(|(uuid, username, joined_at)| PartyMember {
+            uuid,
+            username,
+            joined_at: joined_at.unix_timestamp(),
+        })
+        .collect())
+    }
+
+    #[instrument(name = "Party::info", skip_all)]
+    pub async fn info(&self, db: &DB) -> Result<PartyInfo, Error> {
+        Ok(PartyInfo {
+            uuid: self.uuid,
+            leader: self.leader,
+            members: self.members(db).await?,
+            created_at: self.created_at.unix_timestamp(),
+        })
+    }
+
+    #[instrument(name = "Party::invite", skip_all)]
+    pub async fn invite(&self, db: &DB, sub: Uuid, sender: Uuid) -> Result<(), Error> {
+        sqlx::query(
+            r#"INSERT INTO "PartyInvite" (party, sub, sender) VALUES ($1, $2, $3)
+            ON CONFLICT (party, sub) DO UPDATE SET sender = excluded.sender, created_at = now()"#,
+        )
+        .bind(self.uuid)
+        .bind(sub)
+        .bind(sender)
+        .execute(db)
+        .await?;
+
+        Ok(())
+    }
+
+    #[instrument(name = "Party::invites", skip_all)]
+    pub async fn invites(db: &DB, sub: Uuid) -> Result<Vec<PartyInvite>, Error> {
+        Ok(sqlx::query_as::<_, (Uuid, Uuid, String, OffsetDateTime)>(
+            r#"SELECT "PartyInvite".party, "User".uuid, "User".username, "PartyInvite".created_at
+            FROM "PartyInvite" JOIN "User" ON "User".uuid = "PartyInvite".sender
+            WHERE "PartyInvite".sub = $1 ORDER BY "PartyInvite".created_at DESC"#,
+        )
+        .bind(sub)
+        .fetch_all(db)
+        .await?
+        .into_iter()
+        .map(|(party, uuid, username, created_at)| PartyInvite {
+            party,
+            uuid,
+            username,
+            created_at: created_at.unix_timestamp(),
+        })
+        .collect())
+    }
+
+    /// Consumes the invite and adds the user to the party if it has room for them, the invite is
+    /// kept otherwise
+    #[instrument(name = "Party::accept", skip_all)]
+    pub async fn accept(&self, db: &DB, sub: Uuid, max_size: i64) -> Result<bool, Error> {
+        let mut tx = db.begin().await?;
+
+        // Serializes concurrent joins so the size check holds
+        sqlx::query(r#"SELECT 1 FROM "Party" WHERE uuid = $1 FOR UPDATE"#)
+            .bind(self.uuid)
+            .execute(&mut tx)
+            .await?;
+
+        let invited = sqlx::query(r#"DELETE FROM "PartyInvite" WHERE party = $1 AND sub = $2"#)
+            .bind(self.uuid)
+            .bind(sub)
+            .execute(&mut tx)
+            .await?
+            .rows_affected()
+            > 0;
+
+        let joined = invited
+            && sqlx::query(
+                r#"INSERT INTO "PartyMember" (sub, party) SELECT $1, $2
+                WHERE (SELECT count(*) FROM "PartyMember" WHERE party = $2) < $3"#,
+            )
+            .bind(sub)
+            .bind(self.uuid)
+            .bind(max_size)
+            .execute(&mut tx)
+            .await?
+            .rows_affected()
+                > 0;
+
+        if joined {
+            tx.commit().await?;
+        }
+
+        Ok(joined)
+    }
+
+    #[instrument(name = "Party::remove_member", skip_all)]
+    pub async fn remove_member(&self, db: &DB, sub: Uuid) -> Result<bool, Error> {
+        sqlx::query(r#"DELETE FROM "PartyMember" WHERE party = $1 AND sub = $2"#)
+            .bind(self.uuid)
+            .bind(sub)
+            .execute(db)
+            .await
+            .map(|result| result.rows_affected() > 0)
+    }
+
+    #[instrument(name = "Party::transfer", skip_all)]
+    pub async fn transfer(&mut self, db: &DB, leader: Uuid) -> Result<(), Error> {
+        sqlx::query(r#"UPDATE "Party" SET leader = $1 WHERE uuid = $2"#)
+            .bind(leader)
+            .bind(self.uuid)
+            .execute(db)
+            .await?;
+        self.leader = leader;
+
+        Ok(())
+    }
+
+    #[instrument(name = "Party::disband", skip_all)]
+    pub async fn disband(self, db: &DB) -> Result<(), Error> {
+        sqlx::query(r#"DELETE FROM "Party" WHERE uuid = $1"#)
+            .bind(self.uuid)
+            .execute(db)
+            .await?;
+
+        Ok(())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Guild
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Represents long-lived group of players with ranks
+#[derive(FromRow, Clone, Debug)]
+pub struct Guild {
+    pub uuid: Uuid,
+    pub tag: CiText,
+    pub name: String,
+    pub description: String,
+    pub updated_at: OffsetDateTime,
+    pub created_at: OffsetDateTime,
+}
+
+/// Represents rank of the guild with its permissions
+#[derive(FromRow, Clone, Debug)]
+pub struct GuildRank {
+    pub uuid: Uuid,
+    /// Guild UUID
+    pub guild: Uuid,
+    pub name: String,
+    pub permissions: i32,
+    /// Lower position means higher rank, the leader rank has position 0
+    pub position: i16,
+}
+
+impl GuildRank {
+    pub fn permissions(&self) -> GuildPermissions {
+        GuildPermissions(self.permissions)
+    }
+
+    pub fn can(&self, permissions: GuildPermissions) -> bool {
+        self.permissions().contains(permissions)
+    }
+
+    pub fn is_leader(&self) -> bool {
+        self.position == 0
+    }
+
+    /// Returns `true` if the rank is strictly above the other one
+    pub fn outranks(&self, other: &Self) -> bool {
+        self.position < other.position
+    }
+
+    #[instrument(name = "GuildRank::find_by_uuid", skip_all)]
+    pub async fn find_by_uuid(db: &DB, guild: Uuid, uuid: Uuid) -> Result<Option<Self>, Error> {
+        sqlx::query_as(r#"SELECT * FROM "GuildRank" WHERE guild = $1 AND uuid = $2"#)
+            .bind(guild)
+            .bind(uuid)
+            .fetch_optional(db)
+            .await
+    }
+
+    #[instrument(name = "GuildRank::find_by_member", skip_all)]
+    pub async fn find_by_member(db: &DB, sub: Uuid) -> Result<Option<Self>, Error> {
+        sqlx::query_as(
+            r#"SELECT "GuildRank".* FROM "GuildRank"
+            JOIN "GuildMember" ON "GuildMember".rank = "GuildRank".uuid
+            WHERE "GuildMember".sub = $1"#,
+        )
+        .bind(sub)
+        .fetch_optional(db)
+        .await
+    }
+
+    #[instrument(name = "GuildRank::new", skip_all)]
+    pub async fn new(
+        db: &DB,
+        guild: Uuid,
+        name: &str,
+        permissions: GuildPermissions,
+        position: i16,
+    ) -> Result<Self, Error> {
+        sqlx::query_as(
+            r#"INSERT INTO "GuildRank" (guild, name, permissions, position)
+            VALUES ($1, $2, $3, $4) RETURNING *"#,
+        )
+        .bind(guild)
+        .bind(name)
+        .bind(permissions.0)
+        .bind(position)
+        .fetch_one(db)
+        .await
+    }
+
+    #[instrument(name = "GuildRank::update", skip_all)]
+    pub async fn update(&self, db: &DB) -> Result<(), Error> {
+        sqlx::query(r#"UPDATE "GuildRank" SET name = $1, permissions = $2 WHERE uuid = $3"#)
+            .bind(&self.name)
+            .bind(self.permissions)
+            .bind(self.uuid)
+            .execute(db)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Deletes the rank unless some member still holds it
+    #[instrument(name = "GuildRank::delete", skip_all)]
+    pub async fn delete(self, db: &DB) -> Result<bool, Error> {
+        sqlx::query(
+            r#"DELETE FROM "GuildRank" WHERE uuid = $1
+            AND NOT EXISTS (SELECT 1 FROM "GuildMember" WHERE rank = $1)"#,
+        )
+        .bind(self.uuid)
+        .execute(db)
+        .await
+        .map(|result| result.rows_affected() > 0)
+    }
+}
+
+impl From<GuildRank> for common::guild::GuildRank {
+    fn from(rank: GuildRank) -> Self {
+        Self {
+            uuid: rank.uuid,
+            name: rank.name,
+            permissions: GuildPermissions(rank.permissions),
+            position: rank.position,
+        }
+    }
+}
+
+impl Guild {
+    /// Creates guild with the default ranks and the founder as its leader
+    #[instrument(name = "Guild::new", skip_all)]
+    pub async fn new(
+        db: &DB,
+        founder: Uuid,
+        tag: &str,
+        name: &str,
+        description: &str,
+    ) -> Result<Self, Error> {
+        let mut tx = db.begin().await?;
+
+        let guild: Self = sqlx::query_as(
+            r#"INSERT INTO "Guild" (tag, name, description) VALUES ($1, $2, $3) RETURNING *"#,
+        )
+        .bind(tag)
+        .bind(name)
+        .bind(description)
+        .fetch_one(&mut tx)
+        .await?;
+
+        let ranks = [
+            ("Leader", GuildPermissions::ALL),
+            (
+                "Officer",
+                GuildPermissions::INVITE | GuildPermissions::APPROVE | GuildPermissions::KICK,
+            ),
+            ("Member", GuildPermissions::NONE),
+        ];
+
+        let mut leader = None;
+        for (position, (rank, permissions)) in ranks.into_iter().enumerate() {
+            let uuid: Uuid = sqlx::query_scalar(
+                r#"INSERT INTO "GuildRank" (guild, name, permissions, position)
+                VALUES ($1, $2, $3, $4) RETURNING uuid"#,
+            )
+            .bind(guild.uuid)
+            .bind(rank)
+            .bind(permissions.0)
+            .bind(position as i16)
+            .fetch_one(&mut tx)
+            .await?;
+            leader.get_or_insert(uuid);
+        }
+
+        sqlx::query(r#"INSERT INTO "GuildMember" (sub, guild, rank) VALUES ($1, $2, $3)"#)
+            .bind(founder)
+            .bind(guild.uuid)
+            .bind(leader)
+            .execute(&mut tx)
+            .await?;
+
+        sqlx::query(r#"DELETE FROM "GuildRequest" WHERE sub = $1"#)
+            .bind(founder)
+            .execute(&mut tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(guild)
+    }
+
+    #[instrument(name = "Guild::find_by_uuid", skip_all)]
+    pub async fn find_by_uuid(db: &DB, uuid: Uuid) -> Result<Option<Self>, Error> {
+        sqlx::query_as(r#"SELECT * FROM "Guild" WHERE uuid = $1"#)
+            .bind(uuid)
+            .fetch_optional(db)
+            .await
+    }
+
+    #[instrument(name = "Guild::find_by_tag", skip_all)]
+    pub async fn find_by_tag(db: &DB, tag: &str) -> Result<Option<Self>, Error> {
+        sqlx::query_as(r#"SELECT * FROM "Guild" WHERE tag = $1"#)
+            .bind(tag)
+            .fetch_optional(db)
+            .await
+    }
+
+    #[instrument(name = "Guild::find_by_member", skip_all)]
+    pub async fn find_by_member(db: &DB, sub: Uuid) -> Result<Option<Self>, Error> {
+        sqlx::query_as(
+            r#"SELECT "Guild".* FROM "Guild"
+            JOIN "GuildMember" ON "GuildMember".guild = "Guild".uuid
+            WHERE "GuildMember".sub = $1"#,
+        )
+        .bind(sub)
+        .fetch_optional(db)
+        .await
+    }
+
+    #[instrument(name = "Guild::update", skip_all)]
+    pub async fn update(&self, db: &DB) -> Result<(), Error> {
+        sqlx::query(r#"UPDATE "Guild" SET name = $1, description = $2 WHERE uuid = $3"#)
+            .bind(&self.name)
+            .bind(&self.description)
+            .bind(self.uuid)
+            .execute(db)
+            .await?;
+
+        Ok(())
+    }
+
+    #[instrument(name = "Guild::profile", skip_all)]
+    pub async fn profile(&self, db: &DB) -> Result<GuildProfile, Error> {
+        let members: i64 =
+            sqlx::query_scalar(r#"SELECT count(*) FROM "GuildMember" WHERE guild = $1"#)
+                .bind(self.uuid)
+                .fetch_one(db)
+                .await?;
+
+        Ok(GuildProfile {
+            uuid: self.uuid,
+            tag: self.tag.0.clone(),
+            name: self.name.clone(),
+            description: self.description.clone(),
+            members,
+            created_at: self.created_at.unix_timestamp(),
+        })
+    }
+
+    /// Lists ranks from the highest to the lowest
+    #[instrument(name = "Guild::ranks", skip_all)]
+    pub async fn ranks(&self, db: &DB) -> Result<Vec<GuildRank>, Error> {
+        sqlx::query_as(r#"SELECT * FROM "GuildRank" WHERE guild = $1 ORDER BY position"#)
+            .bind(self.uuid)
+            .fetch_all(db)
+            .await
+    }
+
+    /// Lists members ordered by their rank and then by the time they have joined
+    #[instrument(name = "Guild::members", skip_all)]
+    pub async fn members(&self, db: &DB) -> Result<Vec<GuildMember>, Error> {
+        Ok(sqlx::query_as::<_, (Uuid, String, Uuid, OffsetDateTime)>(
+            r#"SELECT "User".uuid, "User".username, "GuildMember".rank, "GuildMember".created_at
+            FROM "GuildMember"
+            JOIN "User" ON "User".uuid = "GuildMember".sub
+            JOIN "GuildRank" ON "GuildRank".uuid = "GuildMember".rank
+            WHERE "GuildMember".guild = $1
+            ORDER BY "GuildRank".position, "GuildMember".created_at"#,
+        )
+        .bind(self.uuid)
+        .fetch_all(db)
+        .await?
+        .into_iter()
+        .map(|(uuid, username, rank, joined_at)| GuildMember {
+            uuid,
+            username,
+            rank,
+            joined_at: joined_at.unix_timestamp(),
+        })
+        .collect())
+    }
+
+    #[instrument(name = "Guild::member_uuids", skip_all)]
+    pub async fn member_uuids(&self, db: &DB) -> Result<Vec<Uuid>, Error> {
+        sqlx::query_scalar(r#"SELECT sub FROM "GuildMember" WHERE guild = $1"#)
+            .bind(self.uuid)
+            .fetch_all(db)
+            .await
+    }
+
+    /// Records invite or application, an opposite pending request makes the user join right away
+    #[instrument(name = "Guild::request", skip_all)]
+    pub async fn request(&self, db: &DB, sub: Uuid, kind: GuildRequestKind) -> Result<bool, Error> {
+        let opposite = match kind {
+            GuildRequestKind::Invite => GuildRequestKind::Application,
+            GuildRequestKind::Application => GuildRequestKind::Invite,
+        };
+
+        if self.accept(db, sub, opposite).await? {
+            return Ok(true);
+        }
+
+        sqlx::query(
+            r#"INSERT INTO "GuildRequest" (guild, sub, kind) VALUES ($1, $2, $3)
+            ON CONFLICT (guild, sub) DO UPDATE SET kind = excluded.kind, created_at = now()"#,
+        )
+        .bind(self.uuid)
+        .bind(sub)
+        .bind(kind)
+        .execute(db)
+        .await?;
+
+        Ok(false)
+    }
+
+    /// Removes pending request of the given kind, returns `true` if there was one
+    #[instrument(name = "Guild::take_request", skip_all)]
+    pub async fn take_request(
+        &self,
+        db: &DB,
+        sub: Uuid,
+        kind: GuildRequestKind,
+    ) -> Result<bool, Error> {
+        sqlx::query(r#"DELETE FROM "GuildRequest" WHERE guild = $1 AND sub = $2 AND kind = $3"#)
+            .bind(self.uuid)
+            .bind(sub)
+            .bind(kind)
+            .execute(db)
+            .await
+            .map(|result| result.rows_affected() > 0)
+    }
+
+    /// Lists pending requests of the given kind, either of the guild or of the user
+    #[instrument(name = "Guild::requests", skip_all)]
+    pub async fn requests(
+        db: &DB,
+        by: FindBy,
+        uuid: Uuid,
+        kind: GuildRequestKind,
+    ) -> Result<Vec<GuildRequest>, Error> {
+        let column = match by {
+            FindBy::Sub => "sub",
+            _ => "guild",
+        };
+
+        Ok(
+            sqlx::query_as::<_, (Uuid, String, Uuid, String, OffsetDateTime)>(&format!(
+                r#"SELECT "Guild".uuid, "Guild".tag::text, "User".uuid, "User".username,
+            "GuildRequest".created_at FROM "GuildRequest"
+            JOIN "Guild" ON "Guild".uuid = "GuildRequest".guild
+            JOIN "User" ON "User".uuid = "GuildRequest".sub
+            WHERE "GuildRequest".{column} = $1 AND "GuildRequest".kind = $2
+            ORDER BY "GuildRequest".created_at DESC"#
+            ))
+            .bind(uuid)
+            .bind(kind)
+            .fetch_all(db)
+            .await?
+            .into_iter()
+            .map(|(guild, tag, uuid, username, created_at)| GuildRequest {
+                guild,
+                tag,
+                uuid,
+                username,
+                kind,
+                created_at: created_at.unix_timestamp(),
+            })
+            .collect(),
+        )
+    }
+
+    /// Consumes pending request of the given kind and adds the user with the lowest rank, the
+    /// request is kept if they are already in a guild
+    #[instrument(name = "Guild::accept", skip_all)]
+    pub async fn accept(&self, db: &DB, sub: Uuid, kind: GuildRequestKind) -> Result<bool, Error> {
+        let mut tx = db.begin().await?;
+
+        let requested = sqlx::query(
+            r#"DELETE FROM "GuildRequest" WHERE guild = $1 AND sub = $2 AND kind = $3"#,
+        )
+        .bind(self.uuid)
+        .bind(sub)
+        .bind(kind)
+        .execute(&mut tx)
+        .await?
+        .rows_affected()
+            > 0;
+
+        let joined = requested
+            && sqlx::query(
+                r#"INSERT INTO "GuildMember" (sub, guild, rank)
+                SELECT $1, $2, uuid FROM "GuildRank" WHERE guild = $2
+                ORDER BY position DESC LIMIT 1
+                ON CONFLICT (sub) DO NOTHING"#,
+            )
+            .bind(sub)
+            .bind(self.uuid)
+            .execute(&mut tx)
+            .await?
+            .rows_affected()
+                > 0;
+
+        if joined {
+            sqlx::query(r#"DELETE FROM "GuildRequest" WHERE sub = $1"#)
+                .bind(sub)
+                .execute(&mut tx)
+                .await?;
+            tx.commit().await?;
+        }
+
+        Ok(joined)
+    }
+
+    /// UUID of the member holding the leader rank
+    #[instrument(name = "Guild::leader", skip_all)]
+    pub async fn leader(&self, db: &DB) -> Result<Option<Uuid>, Error> {
+        sqlx::query_scalar(
+            r#"SELECT "GuildMember".sub FROM "GuildMember"
+            JOIN "GuildRank" ON "GuildRank".uuid = "GuildMember".rank
+            WHERE "GuildMember".guild = $1 AND "GuildRank".position = 0"#,
+        )
+        .bind(self.uuid)
+        .fetch_optional(db)
+        .await
+    }
+
+    #[instrument(name = "Guild::set_rank", skip_all)]
+    pub async fn set_rank(&self, db: &DB, sub: Uuid, rank: Uuid) -> Result<bool, Error> {
+        sqlx::query(r#"UPDATE "GuildMember" SET rank = $1 WHERE guild = $2 AND sub = $3"#)
+            .bind(rank)
+            .bind(self.uuid)
+            .bind(sub)
+            .execute(db)
+            .await
+            .map(|result| result.rows_affected() > 0)
+    }
+
+    /// Swaps ranks of the leader and the given member
+    #[instrument(name = "Guild::transfer", skip_all)]
+    pub async fn transfer(&self, db: &DB, leader: Uuid, sub: Uuid) -> Result<bool, Error> {
+        sqlx::query(
+            r#"UPDATE "GuildMember" AS member SET rank = other.rank
+            FROM "GuildMember" AS other
+            WHERE member.guild = $1 AND other.guild = $1
+            AND ((member.sub = $2 AND other.sub = $3) OR (member.sub = $3 AND other.sub = $2))"#,
+        )
+        .bind(self.uuid)
+        .bind(leader)
+        .bind(sub)
+        .execute(db)
+        .await
+        .map(|result| result.rows_affected() == 2)
+    }
+
+    #[instrument(name = "Guild::remove_member", skip_all)]
+    pub async fn remove_member(&self, db: &DB, sub: Uuid) -> Result<bool, Error> {
+        sqlx::query(r#"DELETE FROM "GuildMember" WHERE guild = $1 AND sub = $2"#)
+            .bind(self.uuid)
+            .bind(sub)
+            .execute(db)
+            .await
+            .map(|result| result.rows_affected() > 0)
+    }
+
+    #[instrument(name = "Guild::disband", skip_all)]
+    pub async fn disband(self, db: &DB) -> Result<(), Error> {
+        let mut tx = db.begin().await?;
+
+        // Ranks are protected from deletion while members hold them
+        sqlx::query(r#"DELETE FROM "GuildMember" WHERE guild = $1"#)
+            .bind(self.uuid)
+            .execute(&mut tx)
+            .await?;
+
+        sqlx::query(r#"DELETE FROM "Guild" WHERE uuid = $1"#)
+            .bind(self.uuid)
+            .execute(&mut tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Presence
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Represents user presence shared by all hub instances
+#[derive(FromRow, Clone, Debug)]
+pub struct UserPresence {
+    /// User UUID
+    pub sub: Uuid,
+    /// Number of open gateway connections
+    pub connections: i32,
+    /// Server ID of the last joined game server
+    pub sid: Option<String>,
+    /// Last presence change or heartbeat timestamp
+    pub updated_at: OffsetDateTime,
+}
+
+impl UserPresence {
+    pub fn presence(self) -> Presence {
+        Self::derive(self.connections, self.sid)
+    }
+
+    fn derive(connections: i32, sid: Option<String>) -> Presence {
+        match (sid, connections) {
+            (Some(sid), _) => Presence::InGame { sid },
+            (None, 0) => Presence::Offline,
+            (None, _) => Presence::Online,
+        }
+    }
+
+    /// Registers new gateway connection, returns `true` if the user has just come online
+    #[instrument(name = "UserPresence::connect", skip_all)]
+    pub async fn connect(db: &DB, sub: Uuid) -> Result<bool, Error> {
+        sqlx::query_scalar::<_, i32>(
+            r#"INSERT INTO "Presence" (sub, connections) VALUES ($1, 1)
+            ON CONFLICT (sub) DO UPDATE SET connections = "Presence".connections + 1
+            RETURNING connections"#,
+        )
+        .bind(sub)
+        .fetch_one(db)
+        .await
+        .map(|connections| connections == 1)
+    }
+
+    /// Unregisters gateway connection, returns `true` if the user has gone offline
+    #[instrument(name = "UserPresence::disconnect", skip_all)]
+    pub async fn disconnect(db: &DB, sub: Uuid) -> Result<bool, Error> {
+        sqlx::query_scalar::<_, i32>(
+            r#"UPDATE "Presence" SET connections = greatest(connections - 1, 0),
+            sid = CASE WHEN connections <= 1 THEN NULL ELSE sid END
+            WHERE sub = $1 RETURNING connections"#,
+        )
+        .bind(sub)
+        .fetch_optional(db)
+        .await
+        .map(|connections| connections == Some(0))
+    }
+
+    /// Marks the user as playing on the game server
+    #[instrument(name = "UserPresence::join", skip_all)]
+    pub async fn join(db: &DB, sub: Uuid, sid: &str) -> Result<(), Error> {
+        sqlx::query(
+            r#"INSERT INTO "Presence" (sub, sid) VALUES ($1, $2)
+            ON CONFLICT (sub) DO UPDATE SET sid = excluded.sid"#,
+        )
+        .bind(sub)
+        .bind(sid)
+        .execute(db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Clears game server of the user, e.g. when their sessions are ended.
+    /// Returns the new presence if the user has been in game.
+    #[instrument(name = "UserPresence::leave", skip_all)]
+    pub async fn leave(db: &DB, sub: Uuid) -> Result<Option<Presence>, Error> {
+        sqlx::query_scalar::<_, i32>(
+            r#"UPDATE "Presence" SET sid = NULL WHERE sub = $1 AND sid IS NOT NULL
+            RETURNING connections"#,
+        )
+        .bind(sub)
+        .fetch_optional(db)
+        .await
+        .map(|connections| connections.map(|connections| Self::derive(connections, None)))
+    }
+
+    /// Keeps presence of the user with an open gateway connection from expiring
+    #[instrument(name = "UserPresence::heartbeat", skip_all)]
+    pub async fn heartbeat(db: &DB, sub: Uuid) -> Result<(), Error> {
+        sqlx::query(r#"UPDATE "Presence" SET updated_at = now() WHERE sub = $1"#)
+            .bind(sub)
+            .execute(db)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Resets presence that has not been refreshed within `ttl`, returns users who have gone
+    /// offline
+    #[instrument(name = "UserPresence::expire", skip_all)]
+    pub async fn expire(db: &DB, ttl: Duration) -> Result<Vec<Uuid>, Error> {
+        sqlx::query_scalar(
+            r#"UPDATE "Presence" SET connections = 0, sid = NULL
+            WHERE (connections > 0 OR sid IS NOT NULL) AND updated_at < $1
+            RETURNING sub"#,
+        )
+        .bind(OffsetDateTime::now_utc() - ttl)
+        .fetch_all(db)
+        .await
+    }
+
+    /// Lists presence of all friends of the user
+    #[instrument(name = "UserPresence::find_friends", skip_all)]
+    pub async fn find_friends(db: &DB, sub: Uuid) -> Result<Vec<FriendPresence>, Error> {
+        Ok(sqlx::query_as::<_, (Uuid, i32, Option<String>)>(
+            r#"SELECT "Friendship".target, coalesce("Presence".connections, 0), "Presence".sid
+            FROM "Friendship" LEFT JOIN "Presence" ON "Presence".sub = "Friendship".target
+            WHERE "Friendship".sub = $1 AND "Friendship".status = $2"#,
+        )
+        .bind(sub)
+        .bind(FriendStatus::Accepted)
+        .fetch_all(db)
+        .await?
+        .into_iter()
+        .map(|(uuid, connections, sid)| FriendPresence {
+            uuid,
+            presence: Self::derive(connections, sid),
+        })
+        .collect())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Notification
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Represents notification in the inbox of the user
+#[derive(FromRow, Clone, Debug)]
+pub struct Notification {
+    pub uuid: Uuid,
+    /// User UUID
+    pub sub: Uuid,
+    pub kind: NotificationKind,
+    pub title: String,
+    pub body: String,
+    pub read_at: Option<OffsetDateTime>,
+    pub created_at: OffsetDateTime,
+}
+
+impl Notification {
+    #[instrument(name = "Notification::new", skip_all)]
+    pub async fn new(
+        db: &DB,
+        sub: Uuid,
+        kind: NotificationKind,
+        title: &str,
+        body: &str,
+    ) -> Result<Self, Error> {
+        sqlx::query_as(
+            r#"INSERT INTO "Notification" (sub, kind, title, body) VALUES ($1, $2, $3, $4)
+            RETURNING *"#,
+        )
+        .bind(sub)
+        .bind(kind)
+        .bind(title)
+        .bind(body)
+        .fetch_one(db)
+        .await
+    }
+
+    /// Delivers the same notification to every user matching the segment, returns the recipients
+    #[instrument(name = "Notification::broadcast", skip_all)]
+    pub async fn broadcast(
+        db: &DB,
+        uuid: Uuid,
+        segment: &BroadcastSegment,
+        title: &str,
+        body: &str,
+    ) -> Result<Vec<Uuid>, Error> {
+        sqlx::query_scalar(
+            r#"INSERT INTO "Notification" (uuid, sub, kind, title, body)
+            SELECT $1, "User".uuid, $2, $3, $4 FROM "User"
+            LEFT JOIN "Presence" ON "Presence".sub = "User".uuid
+            WHERE "User".status <> $5
+                AND ($6::smallint IS NULL OR "User".status = $6)
+                AND ($7::smallint IS NULL OR "User".role = $7)
+                AND ($8::text IS NULL OR "User".other #>> '{profile,country}' = $8)
+                AND ($9::boolean IS NULL OR (coalesce("Presence".connections, 0) > 0) = $9)
+                AND "User".created_at >= coalesce($10, '-infinity')
+                AND "User".created_at < coalesce($11, 'infinity')
+            RETURNING sub"#,
+        )
+        .bind(uuid)
+        .bind(NotificationKind::System)
+        .bind(title)
+        .bind(body)
+        .bind(UserStatus::Deleted)
+        .bind(segment.status)
+        .bind(segment.role)
+        .bind(&segment.country)
+        .bind(segment.online)
+        .bind(segment.created_after())
+        .bind(segment.created_before())
+        .fetch_all(db)
+        .await
+    }
+
+    /// Lists notifications of the user older than the `before` notification from the newest to
+    /// the oldest
+    #[instrument(name = "Notification::list", skip_all)]
+    pub async fn list(
+        db: &DB,
+        sub: Uuid,
+        before: Option<Uuid>,
+        limit: i64,
+        unread: bool,
+    ) -> Result<Vec<Self>, Error> {
+        sqlx::query_as(
+            r#"SELECT * FROM "Notification"
+            WHERE sub = $1 AND (NOT $3 OR read_at IS NULL)
+                AND ($2::uuid IS NULL OR (created_at, uuid) <
+                    (SELECT created_at, uuid FROM "Notification" WHERE sub = $1 AND uuid = $2))
+            ORDER BY created_at DESC, uuid DESC LIMIT $4"#,
+        )
+        .bind(sub)
+        .bind(before)
+        .bind(unread)
+        .bind(limit)
+        .fetch_all(db)
+        .await
+    }
+
+    #[instrument(name = "Notification::count_unread", skip_all)]
+    pub async fn count_unread(db: &DB, sub: Uuid) -> Result<i64, Error> {
+        sqlx::query_scalar(
+            r#"SELECT count(*) FROM "Notification" WHERE sub = $1 AND read_at IS NULL"#,
+        )
+        .bind(sub)
+        .fetch_one(db)
+        .await
+    }
+
+    /// Marks the notification as read, or all notifications of the user if `uuid` is `None`
+    #[instrument(name = "Notification::mark_read", skip_all)]
+    pub async fn mark_read(db: &DB, sub: Uuid, uuid: Option<Uuid>) -> Result<u64, Error> {
+        sqlx::query(
+            r#"UPDATE "Notification" SET read_at = now()
+            WHERE sub = $1 AND ($2::uuid IS NULL OR uuid = $2) AND read_at IS NULL"#,
+        )
+        .bind(sub)
+        .bind(uuid)
+        .execute(db)
+        .await
+        .map(|result| result.rows_affected())
+    }
+
+    #[instrument(name = "Notification::delete", skip_all)]
+    pub async fn delete(db: &DB, sub: Uuid, uuid: Uuid) -> Result<bool, Error> {
+        sqlx::query(r#"DELETE FROM "Notification" WHERE sub = $1 AND uuid = $2"#)
+            .bind(sub)
+            .bind(uuid)
+            .execute(db)
+            .await
+            .map(|result| result.rows_affected() > 0)
+    }
+
+    /// Deletes notifications older than the retention period
+    #[instrument(name = "Notification::purge", skip_all)]
+    pub async fn purge(db: &DB, retention: Duration) -> Result<u64, Error> {
+        sqlx::query(r#"DELETE FROM "Notification" WHERE created_at < $1"#)
+            .bind(OffsetDateTime::now_utc() - retention)
+            .execute(db)
+            .await
+            .map(|result| result.rows_affected())
+    }
+}
+
+impl From<Notification> for NotificationData {
+    fn from(notification: Notification) -> Self {
+        Self {
+            uuid: notification.uuid,
+            kind: notification.kind,
+            title: notification.title,
+            body: notification.body,
+            read: notification.read_at.is_some(),
+            created_at: notification.created_at.unix_timestamp(),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Export
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Represents personal data export job
+#[derive(FromRow, Clone, Debug)]
+pub struct Export {
+    /// Export UUID
+    pub uuid: Uuid,
+    /// User UUID
+    pub sub: Uuid,
+    /// Export job status
+    pub status: ExportStatus,
+    /// Generated archive
+    pub data: Option<Json<Value>>,
+    /// Expire timestamp
+    pub expires_at: OffsetDateTime,
+    /// Export creation timestamp
+    pub created_at: OffsetDateTime,
+}
+
+impl Export {
+    /// Creates export job unless the user already has `limit` unexpired exports
+    #[instrument(name = "Export::new", skip_all)]
+    pub async fn new(
+        db: &DB,
+        sub: Uuid,
+        expires_at: OffsetDateTime,
+        limit: i64,
+    ) -> Result<Option<Self>, Error> {
+        let mut tx = db.begin().await?;
+
+        // Serializes concurrent requests of the user
+        sqlx::query(r#"SELECT 1 FROM "User" WHERE uuid = $1 FOR UPDATE"#)
+            .bind(sub)
+            .execute(&mut tx)
+            .await?;
+
+        let export = sqlx::query_as(
+            r#"INSERT INTO "Export" (sub, expires_at) SELECT $1, $2
+            WHERE (SELECT count(*) FROM "Export" WHERE sub = $1 AND expires_at > now()) < $3
+            RETURNING *"#,
+        )
+        .bind(sub)
+        .bind(expires_at)
+        .bind(limit)
+        .fetch_optional(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(export)
+    }
+
+    #[instrument(name = "Export::find_by_uuid", skip_all)]
+    pub async fn find_by_uuid(db: &DB, uuid: Uuid) -> Result<Option<Self>, Error> {
+        sqlx::query_as(r#"SELECT * FROM "Export" WHERE uuid = $1 AND expires_at > now()"#)
+            .bind(uuid)
+            .fetch_optional(db)
+            .await
+    }
+
+    #[instrument(name = "Export::find_latest", skip_all)]
+    pub async fn find_latest(db: &DB, sub: Uuid) -> Result<Option<Self>, Error> {
+        sqlx::query_as(
+            r#"SELECT * FROM "Export" WHERE sub = $1 AND expires_at > now()
+            ORDER BY created_at DESC LIMIT 1"#,
+        )
+        .bind(sub)
+        .fetch_optional(db)
+        .await
+    }
+
+    #[instrument(name = "Export::complete", skip_all)]
+    pub async fn complete(db: &DB, uuid: Uuid, data: Option<Value>) -> Result<(), Error> {
+        sqlx::query(r#"UPDATE "Export" SET status = $1, data = $2 WHERE uuid = $3"#)
+            .bind(if data.is_some() {
+                ExportStatus::Ready
+            } else {
+                ExportStatus::Failed
+            })
+            .bind(data.map(Json))
+            .bind(uuid)
+            .execute(db)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Fails pending exports whose job has been interrupted, e.g. by a restart
+    #[instrument(name = "Export::fail_stalled", skip_all)]
+    pub async fn fail_stalled(db: &DB, timeout: Duration) -> Result<u64, Error> {
+        sqlx::query(r#"UPDATE "Export" SET status = $1 WHERE status = $2 AND created_at < $3"#)
+            .bind(ExportStatus::Failed)
+            .bind(ExportStatus::Pending)
+            .bind(OffsetDateTime::now_utc() - timeout)
+            .execute(db)
+            .await
+            .map(|result| result.rows_affected())
+    }
+
+    #[instrument(name = "Export::purge_expired", skip_all)]
+    pub async fn purge_expired(db: &DB) -> Result<u64, Error> {
+        sqlx::query(r#"DELETE FROM "Export" WHERE expires_at <= now()"#)
+            .execute(db)
+            .await
+            .map(|result| result.rows_affected())
+    }
+}
+
+/// Machine-readable archive of everything the hub holds about the user
+#[derive(Serialize, Debug)]
+pub struct UserArchive {
+    pub user: UserData,
+    pub other: HashMap<String, Value>,
+    pub former_usernames: Vec<String>,
+    pub sessions: Vec<UserSession>,
+    pub friends: Vec<Friend>,
+    pub friend_requests: Vec<Friend>,
+    pub blocked: Vec<Friend>,
+    pub messages: Vec<DirectMessage>,
+    pub notifications: Vec<NotificationData>,
+    pub party: Option<PartyInfo>,
+    pub guild: Option<GuildProfile>,
+    pub guild_rank: Option<common::guild::GuildRank>,
+    pub invite_code: Option<String>,
+    pub email_changes: Vec<EmailChangeRecord>,
+}
+
+/// Email change as included in the user archive, without its tokens
+#[derive(Serialize, Debug)]
+pub struct EmailChangeRecord {
+    pub old_email: String,
+    pub new_email: String,
+    pub confirmed_at: Option<i64>,
+    pub created_at: i64,
+}
+
+impl From<EmailChange> for EmailChangeRecord {
+    fn from(change: EmailChange) -> Self {
+        Self {
+            old_email: change.old_email.0,
+            new_email: change.new_email.0,
+            confirmed_at: change.confirmed_at.map(OffsetDateTime::unix_timestamp),
+            created_at: change.created_at.unix_timestamp(),
+        }
+    }
+}
+
+impl UserArchive {
+    #[instrument(name = "UserArchive::collect", skip_all)]
+    pub async fn collect(db: &DB, sub: Uuid) -> Result<Option<Self>, Error> {
+        let Some(user) = User::find_by_uuid(db, sub).await? else {
+            return Ok(None);
+        };
+
+        let mut sessions = Vec::new();
+        for ct in [ClientType::Web, ClientType::Game, ClientType::Mobile] {
+            if let Some(session) = Session::find_by(db, ct, sub, FindBy::Sub).await? {
+                sessions.push(session.to_user_session(ct));
+            }
+        }
+
+        let former_usernames = UsernameHistory::find_by_sub(db, sub)
+            .await?
+            .into_iter()
+            .map(|history| history.username)
+            .collect();
+
+        let party = match Party::find_by_member(db, sub).await? {
+            Some(party) => Some(party.info(db).await?),
+            None => None,
+        };
+        let guild = match Guild::find_by_member(db, sub).await? {
+            Some(guild) => Some(guild.profile(db).await?),
+            None => None,
+        };
+
+        Ok(Some(Self {
+            invite_code: user.invite_code.clone(),
+            other: user.other.0.clone(),
+            user: user.into(),
+            former_usernames,
+            sessions,
+            friends: Friendship::list(db, sub, FriendStatus::Accepted).await?,
+            friend_requests: Friendship::list(db, sub, FriendStatus::Pending).await?,
+            blocked: Friendship::list(db, sub, FriendStatus::Blocked).await?,
+            messages: Message::find_by_user(db, sub)
+                .await?
+                .into_iter()
+                .map(DirectMessage::from)
+                .collect(),
+            notifications: Notification::list(db, sub, None, i64::MAX, false)
+                .await?
+                .into_iter()
+                .map(NotificationData::from)
+                .collect(),
+            party,
+            guild,
+            guild_rank: GuildRank::find_by_member(db, sub).await?.map(Into::into),
+            email_changes: EmailChange::find_by_sub(db, sub)
+                .await?
+                .into_iter()
+                .map(EmailChangeRecord::from)
+                .collect(),
+        }))
+    }
 }
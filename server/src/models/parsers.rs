@@ -1,18 +1,31 @@
-use common::user::ClientType;
+use common::{
+    challenge::ChallengeSolution,
+    guild::GuildPermissions,
+    profile::{Profile, ProfilePrivacy},
+    user::{ClientType, UserRole, UserStatus},
+};
 use lazy_static::lazy_static;
 use regex::Regex;
 use serde::Deserialize;
+use time::OffsetDateTime;
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
-use validator::Validate;
+use validator::{Validate, ValidationError};
 
 lazy_static! {
     /// Regular expression for username
     pub static ref USERNAME_REGEX: Regex = Regex::new("^[a-zA-Z0-9_]{3,24}$").unwrap();
     /// Regular expression for Server ID (e.g. "eYp1Zl1td14E")
     pub static ref SID_REGEX: Regex = Regex::new("^[a-zA-Z0-9]{12}$").unwrap();
+    /// Regular expression for ISO 3166-1 alpha-2 country code, empty string clears the field
+    pub static ref COUNTRY_REGEX: Regex = Regex::new("^([A-Z]{2})?$").unwrap();
+    /// Regular expression for invite code
+    pub static ref INVITE_CODE_REGEX: Regex = Regex::new("^[a-zA-Z0-9_-]{4,32}$").unwrap();
+    /// Regular expression for guild tag (e.g. "ECG")
+    pub static ref GUILD_TAG_REGEX: Regex = Regex::new("^[a-zA-Z0-9]{2,5}$").unwrap();
 }
 
-#[derive(Deserialize, Default, Debug)]
+#[derive(Deserialize, Default, ToSchema, Debug)]
 #[serde(rename_all = "lowercase")]
 pub enum KeyFormat {
     #[default]
@@ -20,26 +33,32 @@ pub enum KeyFormat {
     Pem,
 }
 
-#[derive(Deserialize, Default, Debug)]
+#[derive(Deserialize, Default, IntoParams, Debug)]
+#[into_params(parameter_in = Query)]
 #[serde(default)]
 pub struct KeyFormatQuery {
     pub format: KeyFormat,
 }
 
-#[derive(Validate, Deserialize, Debug)]
+#[derive(Validate, Deserialize, IntoParams, Debug)]
+#[into_params(parameter_in = Query)]
 pub struct UserInfoQuery {
     pub uuid: Option<Uuid>,
     #[validate(regex = "USERNAME_REGEX")]
     pub username: Option<String>,
 }
 
-#[derive(Validate, Deserialize, Debug)]
+#[derive(Validate, Deserialize, IntoParams, Debug)]
+#[into_params(parameter_in = Query)]
 pub struct PITQuery {
     #[validate(regex = "SID_REGEX")]
     pub sid: String,
+    /// Include guild UUID and tag claims
+    #[serde(default)]
+    pub guild: bool,
 }
 
-#[derive(Validate, Deserialize, Debug)]
+#[derive(Validate, Deserialize, ToSchema)]
 pub struct RegisterBody {
     #[validate(regex = "USERNAME_REGEX")]
     pub username: String,
@@ -47,9 +66,14 @@ pub struct RegisterBody {
     pub email: String,
     #[validate(length(min = 6, max = 64))]
     pub password: String,
+    /// Required when registration is invite-only
+    #[validate(length(min = 1, max = 32))]
+    pub invite: Option<String>,
+    /// Required when anti-bot challenge is enabled
+    pub challenge: Option<ChallengeSolution>,
 }
 
-#[derive(Validate, Deserialize, Debug)]
+#[derive(Validate, Deserialize, ToSchema)]
 pub struct LoginBody {
     #[validate(regex = "USERNAME_REGEX")]
     pub username: String,
@@ -59,10 +83,271 @@ pub struct LoginBody {
     pub ct: ClientType,
 }
 
-#[derive(Validate, Deserialize, Debug)]
+#[derive(Validate, Deserialize, ToSchema)]
 pub struct PasswordChangeBody {
     #[validate(length(min = 6, max = 64))]
     pub old_password: String,
     #[validate(length(min = 6, max = 64))]
     pub new_password: String,
 }
+
+#[derive(Validate, Deserialize, ToSchema, Debug)]
+pub struct UsernameChangeBody {
+    #[validate(regex = "USERNAME_REGEX")]
+    pub username: String,
+}
+
+#[derive(Validate, Deserialize, ToSchema)]
+pub struct EmailChangeBody {
+    #[validate(email)]
+    pub email: String,
+    #[validate(length(min = 6, max = 64))]
+    pub password: String,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct EmailTokenQuery {
+    pub token: Uuid,
+}
+
+#[derive(Validate, Deserialize, ToSchema)]
+pub struct AccountDeleteBody {
+    #[validate(length(min = 6, max = 64))]
+    pub password: String,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportDownloadQuery {
+    pub token: String,
+}
+
+redacted_debug!(
+    RegisterBody {
+        username,
+        email,
+        invite,
+        challenge
+    },
+    { password }
+);
+redacted_debug!(LoginBody { username, ct }, { password });
+redacted_debug!(PasswordChangeBody {}, { old_password, new_password });
+redacted_debug!(EmailChangeBody { email }, { password });
+redacted_debug!(EmailTokenQuery {}, { token });
+redacted_debug!(AccountDeleteBody {}, { password });
+redacted_debug!(ExportDownloadQuery {}, { token });
+
+/// Partial profile update, empty strings clear the field
+#[derive(Validate, Deserialize, ToSchema, Debug)]
+pub struct ProfilePatchBody {
+    #[validate(length(max = 32))]
+    pub display_name: Option<String>,
+    #[validate(length(max = 256))]
+    pub bio: Option<String>,
+    #[validate(regex = "COUNTRY_REGEX")]
+    pub country: Option<String>,
+    #[validate(length(max = 24))]
+    pub pronouns: Option<String>,
+    pub privacy: Option<ProfilePrivacy>,
+}
+
+impl ProfilePatchBody {
+    pub fn apply(self, profile: &mut Profile) {
+        fn patch(field: &mut Option<String>, value: Option<String>) {
+            if let Some(value) = value {
+                let value = value.trim();
+                *field = (!value.is_empty()).then(|| value.to_string());
+            }
+        }
+
+        patch(&mut profile.display_name, self.display_name);
+        patch(&mut profile.bio, self.bio);
+        patch(&mut profile.country, self.country);
+        patch(&mut profile.pronouns, self.pronouns);
+
+        if let Some(privacy) = self.privacy {
+            profile.privacy = privacy;
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema, Debug)]
+pub struct FriendBody {
+    pub uuid: Uuid,
+}
+
+#[derive(Deserialize, IntoParams, Debug)]
+#[into_params(parameter_in = Query)]
+pub struct GatewayQuery {
+    pub token: Option<String>,
+}
+
+/// Maximum size of the JSON encoded message body, keeps the gateway event delivering the message
+/// below the NOTIFY payload limit
+const MESSAGE_BODY_MAX_BYTES: usize = 6000;
+
+#[derive(Validate, Deserialize, ToSchema, Debug)]
+pub struct MessageBody {
+    pub recipient: Uuid,
+    #[validate(length(min = 1, max = 2000), custom = "validate_message_body")]
+    pub body: String,
+}
+
+fn validate_message_body(body: &str) -> Result<(), ValidationError> {
+    match serde_json::to_string(body) {
+        Ok(encoded) if encoded.len() <= MESSAGE_BODY_MAX_BYTES => Ok(()),
+        _ => Err(ValidationError::new("too_large")),
+    }
+}
+
+#[derive(Deserialize, Default, IntoParams, Debug)]
+#[into_params(parameter_in = Query)]
+#[serde(default)]
+pub struct HistoryQuery {
+    /// Returns messages older than the message with the UUID
+    pub before: Option<Uuid>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Validate, Deserialize, ToSchema, Debug)]
+pub struct ChatBanBody {
+    /// Length of the ban in seconds, lifts the ban if omitted
+    #[validate(range(min = 1, max = 315360000))]
+    pub duration: Option<i64>,
+}
+
+#[derive(Deserialize, ToSchema, Debug)]
+pub struct PartyBody {
+    pub party: Uuid,
+}
+
+#[derive(Validate, Deserialize, ToSchema, Debug)]
+pub struct GuildCreateBody {
+    #[validate(regex = "GUILD_TAG_REGEX")]
+    pub tag: String,
+    #[validate(length(min = 3, max = 32))]
+    pub name: String,
+    #[validate(length(max = 512))]
+    #[serde(default)]
+    pub description: String,
+}
+
+#[derive(Validate, Deserialize, ToSchema, Debug)]
+pub struct GuildPatchBody {
+    #[validate(length(min = 3, max = 32))]
+    pub name: Option<String>,
+    #[validate(length(max = 512))]
+    pub description: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema, Debug)]
+pub struct GuildBody {
+    pub guild: Uuid,
+}
+
+#[derive(Validate, Deserialize, ToSchema, Debug)]
+pub struct GuildRankBody {
+    #[validate(length(min = 1, max = 24))]
+    pub name: String,
+    #[serde(default)]
+    pub permissions: GuildPermissions,
+    /// Position below the highest rank (must be greater than 0)
+    #[validate(range(min = 1))]
+    pub position: i16,
+}
+
+#[derive(Validate, Deserialize, ToSchema, Debug)]
+pub struct GuildRankPatchBody {
+    #[validate(length(min = 1, max = 24))]
+    pub name: Option<String>,
+    pub permissions: Option<GuildPermissions>,
+}
+
+#[derive(Deserialize, ToSchema, Debug)]
+pub struct GuildMemberRankBody {
+    pub rank: Uuid,
+}
+
+#[derive(Deserialize, IntoParams, Debug)]
+#[into_params(parameter_in = Query)]
+pub struct NotificationQuery {
+    /// Returns notifications older than the notification with the UUID
+    pub before: Option<Uuid>,
+    pub limit: Option<i64>,
+    /// Returns only unread notifications
+    #[serde(default)]
+    pub unread: bool,
+}
+
+/// Filters recipients of a broadcast, every field narrows the segment down
+#[derive(Validate, Deserialize, Default, ToSchema, Debug)]
+#[serde(default)]
+pub struct BroadcastSegment {
+    pub status: Option<UserStatus>,
+    pub role: Option<UserRole>,
+    #[validate(regex = "COUNTRY_REGEX")]
+    pub country: Option<String>,
+    /// Whether the user is connected to the gateway
+    pub online: Option<bool>,
+    #[validate(range(min = 0, max = 253402300799))]
+    pub created_after: Option<i64>,
+    #[validate(range(min = 0, max = 253402300799))]
+    pub created_before: Option<i64>,
+}
+
+impl BroadcastSegment {
+    pub fn is_everyone(&self) -> bool {
+        self.status.is_none()
+            && self.role.is_none()
+            && self.country.is_none()
+            && self.online.is_none()
+            && self.created_after.is_none()
+            && self.created_before.is_none()
+    }
+
+    pub fn created_after(&self) -> Option<OffsetDateTime> {
+        self.created_after
+            .and_then(|timestamp| OffsetDateTime::from_unix_timestamp(timestamp).ok())
+    }
+
+    pub fn created_before(&self) -> Option<OffsetDateTime> {
+        self.created_before
+            .and_then(|timestamp| OffsetDateTime::from_unix_timestamp(timestamp).ok())
+    }
+}
+
+#[derive(Validate, Deserialize, ToSchema, Debug)]
+pub struct BroadcastBody {
+    #[validate(length(min = 1, max = 64))]
+    pub title: String,
+    #[validate(length(min = 1, max = 2048), custom = "validate_broadcast_body")]
+    pub body: String,
+    #[validate]
+    #[serde(default)]
+    pub segment: BroadcastSegment,
+}
+
+/// Leaves room in the NOTIFY payload for the title and a chunk of recipients
+const BROADCAST_BODY_MAX_BYTES: usize = 4000;
+
+fn validate_broadcast_body(body: &str) -> Result<(), ValidationError> {
+    match serde_json::to_string(body) {
+        Ok(encoded) if encoded.len() <= BROADCAST_BODY_MAX_BYTES => Ok(()),
+        _ => Err(ValidationError::new("too_large")),
+    }
+}
+
+#[derive(Validate, Deserialize, Default, ToSchema, Debug)]
+#[serde(default)]
+pub struct InviteCreateBody {
+    /// Custom code, random one is generated if `None`
+    #[validate(regex = "INVITE_CODE_REGEX")]
+    pub code: Option<String>,
+    #[validate(range(min = 1))]
+    pub max_uses: Option<i32>,
+    /// Lifetime of the code in seconds up to 10 years, never expires if `None`
+    #[validate(range(min = 1, max = 315360000))]
+    pub lifetime: Option<i64>,
+}
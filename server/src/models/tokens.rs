@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use axum::{
     extract::{FromRef, FromRequestParts},
     headers::{authorization::Bearer, Authorization},
@@ -5,16 +7,17 @@ use axum::{
     TypedHeader,
 };
 use axum_extra::extract::cookie::Cookie;
-use common::user::ClientType;
+use common::user::{ClientType, UserRole};
 use hyper::StatusCode;
 use jsonwebtoken::{decode, encode, get_current_timestamp, Algorithm, Header};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use time::Duration;
+use tracing::{field, info_span, Span};
 use uuid::Uuid;
 
-use crate::keys::Keys;
+use crate::{app::HubState, keys::Keys};
 
-use super::entities::Session;
+use super::entities::{Session, User};
 
 pub trait SecurityToken: DeserializeOwned + Serialize
 where
@@ -30,11 +33,21 @@ where
         get_current_timestamp() as i64 + Self::LIFETIME
     }
 
+    /// Short type name used in spans
+    fn kind() -> &'static str {
+        let name = std::any::type_name::<Self>();
+        name.rsplit("::").next().unwrap_or(name)
+    }
+
     fn sign(&self, keys: &Keys) -> String {
+        let _span = info_span!("token.sign", kind = Self::kind()).entered();
+
         encode(&Header::new(Algorithm::EdDSA), self, &keys.encoding).expect("Failed to sign token")
     }
 
     fn decode(token: &str, keys: &Keys) -> Result<Self, jsonwebtoken::errors::Error> {
+        let _span = info_span!("token.decode", kind = Self::kind()).entered();
+
         decode(token, &keys.decoding, &keys.validation).map(|data| data.claims)
     }
 }
@@ -162,8 +175,97 @@ where
                 .map_err(|_| StatusCode::EXPECTATION_FAILED)?;
 
         let keys = Keys::from_ref(state);
+        let token = Self::decode(bearer.token(), &keys).map_err(|_| StatusCode::FORBIDDEN)?;
+
+        let span = Span::current();
+        span.record("user", field::display(token.sub));
+        span.record("client_type", field::debug(token.ct));
+
+        Ok(token)
+    }
+}
+
+/// Authenticates game server requests with one of the configured server keys
+pub struct ServerKey;
+
+#[async_trait::async_trait]
+impl FromRequestParts<Arc<HubState>> for ServerKey {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<HubState>,
+    ) -> Result<Self, Self::Rejection> {
+        let TypedHeader(Authorization(bearer)) =
+            TypedHeader::<Authorization<Bearer>>::from_request_parts(parts, state)
+                .await
+                .map_err(|_| StatusCode::EXPECTATION_FAILED)?;
+
+        if state
+            .config
+            .server_keys
+            .iter()
+            .any(|key| key == bearer.token())
+        {
+            Ok(Self)
+        } else {
+            Err(StatusCode::FORBIDDEN)
+        }
+    }
+}
+
+/// Authenticates the user and checks that they hold at least the given role
+async fn require_role(
+    parts: &mut Parts,
+    state: &Arc<HubState>,
+    role: UserRole,
+) -> Result<Uuid, StatusCode> {
+    let AccessToken { sub, .. } = AccessToken::from_request_parts(parts, state).await?;
+
+    match User::find_by_uuid(&state.db, sub)
+        .await
+        .expect("failed to retrieve user data from db")
+    {
+        Some(user) if user.role >= role => Ok(sub),
+        _ => Err(StatusCode::FORBIDDEN),
+    }
+}
+
+/// Authenticates requests of the users with the admin role
+pub struct Admin {
+    pub sub: Uuid,
+}
+
+#[async_trait::async_trait]
+impl FromRequestParts<Arc<HubState>> for Admin {
+    type Rejection = StatusCode;
 
-        Self::decode(bearer.token(), &keys).map_err(|_| StatusCode::FORBIDDEN)
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<HubState>,
+    ) -> Result<Self, Self::Rejection> {
+        require_role(parts, state, UserRole::Admin)
+            .await
+            .map(|sub| Self { sub })
+    }
+}
+
+/// Authenticates requests of the staff, i.e. users with the moderator role or higher
+pub struct Staff {
+    pub sub: Uuid,
+}
+
+#[async_trait::async_trait]
+impl FromRequestParts<Arc<HubState>> for Staff {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<HubState>,
+    ) -> Result<Self, Self::Rejection> {
+        require_role(parts, state, UserRole::Moderator)
+            .await
+            .map(|sub| Self { sub })
     }
 }
 
@@ -182,6 +284,15 @@ pub struct PlayerIdentityToken {
     pub nbf: i64,
     /// Client Type
     pub ct: ClientType,
+    /// Party UUID, shared by all members who requested PIT together
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub party: Option<Uuid>,
+    /// Guild UUID, included on request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub guild: Option<Uuid>,
+    /// Guild tag, included on request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
 }
 
 impl PlayerIdentityToken {
@@ -201,6 +312,9 @@ impl PlayerIdentityToken {
             exp,
             nbf,
             ct,
+            party: None,
+            guild: None,
+            tag: None,
         }
     }
 
@@ -214,9 +328,46 @@ impl PlayerIdentityToken {
             ct,
         )
     }
+
+    pub fn with_party(mut self, party: Uuid) -> Self {
+        self.party = Some(party);
+        self
+    }
+
+    pub fn with_guild(mut self, guild: Uuid, tag: String) -> Self {
+        self.guild = Some(guild);
+        self.tag = Some(tag);
+        self
+    }
 }
 
 impl SecurityToken for PlayerIdentityToken {
     /// PIT lifetime: 15 seconds
     const LIFETIME: i64 = 15;
 }
+
+/// Contains personal data export download token claims
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ExportToken {
+    /// User UUID
+    pub sub: Uuid,
+    /// Export UUID
+    pub jti: Uuid,
+    /// Expire time (UTC timestamp)
+    pub exp: i64,
+}
+
+impl ExportToken {
+    pub fn new(sub: Uuid, jti: Uuid) -> Self {
+        Self {
+            sub,
+            jti,
+            exp: Self::new_exp(),
+        }
+    }
+}
+
+impl SecurityToken for ExportToken {
+    /// Export download link lifetime: 1 hour
+    const LIFETIME: i64 = 60 * 60;
+}
@@ -0,0 +1,179 @@
+use std::{collections::HashSet, fmt};
+
+use regex::{Regex, RegexBuilder};
+
+use crate::{config::Config, error::Error};
+
+/// Reason why the username can not be claimed
+#[derive(PartialEq, Eq, Debug)]
+pub enum NameViolation {
+    /// Username is reserved for the hub or its staff
+    Reserved,
+    /// Username contains a word from the profanity list
+    Profane,
+}
+
+impl fmt::Display for NameViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NameViolation::Reserved => write!(f, "username is reserved"),
+            NameViolation::Profane => write!(f, "username is not allowed"),
+        }
+    }
+}
+
+/// Checks usernames against the reserved names and the profanity list from the config.
+/// All matching is case-insensitive.
+pub struct NameFilter {
+    exact: HashSet<String>,
+    prefixes: Vec<String>,
+    patterns: Vec<Regex>,
+    profanity: Vec<String>,
+}
+
+impl NameFilter {
+    pub fn new(config: &Config) -> Result<Self, Error> {
+        let patterns = config
+            .reserved_patterns
+            .iter()
+            .map(|pattern| {
+                RegexBuilder::new(pattern)
+                    .case_insensitive(true)
+                    .build()
+                    .map_err(|err| Error::ConfigError(format!("invalid reserved pattern: {err}")))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            exact: config
+                .reserved_names
+                .iter()
+                .map(|name| name.to_lowercase())
+                .collect(),
+            prefixes: config
+                .reserved_prefixes
+                .iter()
+                .map(|prefix| prefix.to_lowercase())
+                .collect(),
+            patterns,
+            profanity: config
+                .profanity
+                .iter()
+                .map(|word| Self::normalize(word))
+                .collect(),
+        })
+    }
+
+    /// Maps common character substitutions back to letters, e.g. "h4x0r" to "haxor"
+    fn normalize(name: &str) -> String {
+        name.chars()
+            .filter(|c| *c != '_')
+            .map(|c| match c.to_ascii_lowercase() {
+                '0' => 'o',
+                '1' => 'i',
+                '3' => 'e',
+                '4' => 'a',
+                '5' => 's',
+                '7' => 't',
+                c => c,
+            })
+            .collect()
+    }
+
+    pub fn check(&self, username: &str) -> Result<(), NameViolation> {
+        let lowercase = username.to_lowercase();
+
+        if self.exact.contains(&lowercase)
+            || self
+                .prefixes
+                .iter()
+                .any(|prefix| lowercase.starts_with(prefix))
+            || self
+                .patterns
+                .iter()
+                .any(|pattern| pattern.is_match(username))
+        {
+            return Err(NameViolation::Reserved);
+        }
+
+        let normalized = Self::normalize(username);
+        if self
+            .profanity
+            .iter()
+            .any(|word| !word.is_empty() && normalized.contains(word))
+        {
+            return Err(NameViolation::Profane);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter() -> NameFilter {
+        NameFilter::new(&Config {
+            reserved_names: vec![String::from("Admin")],
+            reserved_prefixes: vec![String::from("staff")],
+            reserved_patterns: vec![String::from("^mod[0-9]+$")],
+            profanity: vec![String::from("haxor")],
+            ..Default::default()
+        })
+        .expect("failed to build name filter")
+    }
+
+    #[test]
+    fn exact_names_are_reserved() {
+        let filter = filter();
+
+        assert_eq!(filter.check("admin"), Err(NameViolation::Reserved));
+        assert_eq!(filter.check("ADMIN"), Err(NameViolation::Reserved));
+        assert_eq!(filter.check("admins"), Ok(()));
+    }
+
+    #[test]
+    fn prefixes_are_reserved() {
+        let filter = filter();
+
+        assert_eq!(filter.check("StaffMember"), Err(NameViolation::Reserved));
+        assert_eq!(filter.check("mystaff"), Ok(()));
+    }
+
+    #[test]
+    fn patterns_are_reserved() {
+        let filter = filter();
+
+        assert_eq!(filter.check("MOD42"), Err(NameViolation::Reserved));
+        assert_eq!(filter.check("mod42x"), Ok(()));
+        assert_eq!(filter.check("modern"), Ok(()));
+    }
+
+    #[test]
+    fn invalid_pattern_is_rejected() {
+        let config = Config {
+            reserved_patterns: vec![String::from("(")],
+            ..Default::default()
+        };
+
+        assert!(NameFilter::new(&config).is_err());
+    }
+
+    #[test]
+    fn profanity_is_matched_after_normalization() {
+        let filter = filter();
+
+        assert_eq!(filter.check("haxor"), Err(NameViolation::Profane));
+        assert_eq!(filter.check("xX_H4x0r_Xx"), Err(NameViolation::Profane));
+        assert_eq!(filter.check("h_a_x_o_r"), Err(NameViolation::Profane));
+        assert_eq!(filter.check("hacker"), Ok(()));
+    }
+
+    #[test]
+    fn normalize_maps_substitutions() {
+        assert_eq!(NameFilter::normalize("h4x0r"), "haxor");
+        assert_eq!(NameFilter::normalize("L33T_5p34k"), "leetspeak");
+        assert_eq!(NameFilter::normalize("1_7"), "it");
+    }
+}
@@ -0,0 +1,173 @@
+use utoipa::{
+    openapi::{
+        security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme},
+        OpenApi as Document,
+    },
+    Modify, OpenApi,
+};
+
+use crate::{
+    handlers,
+    models::{parsers::KeyFormat, tokens::RefreshToken},
+};
+
+/// OpenAPI document generated from the handlers, every route of `HubState::build_router` has to
+/// be listed in `paths` of the document or of the nested API version
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "ECG Hub",
+        description = "Versioned routes can also be requested without the prefix by sending the \
+            `Accept-Version` header, unprefixed requests without it are deprecated aliases of v1"
+    ),
+    paths(
+        handlers::openapi,
+        handlers::status,
+        handlers::health_ready,
+        handlers::health_live,
+    ),
+    nest((path = "/v1", api = V1)),
+    modifiers(&Hub),
+    tags(
+        (name = "hub", description = "Hub status, keys and documentation"),
+        (name = "health", description = "Liveness and readiness probes"),
+        (name = "gateway", description = "WebSocket connection receiving hub events"),
+        (name = "profile", description = "Public player profiles and avatars"),
+        (name = "user", description = "Account of the user"),
+        (name = "token", description = "Sessions and tokens"),
+        (name = "friends", description = "Friends and blocked users"),
+        (name = "messages", description = "Direct messages between friends"),
+        (name = "party", description = "Parties joining game servers together"),
+        (name = "guild", description = "Guilds, their ranks and members"),
+        (name = "notifications", description = "Notification inbox of the user"),
+        (name = "admin", description = "Hub administration"),
+        (name = "server", description = "Endpoints for game servers"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Routes of `HubState::v1_router`
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        handlers::pubkey,
+        handlers::challenge,
+        handlers::gateway,
+        handlers::profile,
+        handlers::avatar,
+        handlers::user_delete,
+        handlers::user_info,
+        handlers::user_data,
+        handlers::user_login,
+        handlers::user_register,
+        handlers::user_password,
+        handlers::user_profile,
+        handlers::user_profile_update,
+        handlers::user_avatar,
+        handlers::user_avatar_delete,
+        handlers::user_invites,
+        handlers::user_invite_create,
+        handlers::user_username,
+        handlers::user_email,
+        handlers::user_email_confirm,
+        handlers::user_email_revert,
+        handlers::user_sessions,
+        handlers::user_export,
+        handlers::user_export_create,
+        handlers::user_export_download,
+        handlers::friends,
+        handlers::friends_requests,
+        handlers::friends_presence,
+        handlers::friends_blocked,
+        handlers::friends_request,
+        handlers::friends_accept,
+        handlers::friends_decline,
+        handlers::friends_remove,
+        handlers::friends_block,
+        handlers::friends_unblock,
+        handlers::messages,
+        handlers::messages_send,
+        handlers::messages_history,
+        handlers::messages_read,
+        handlers::messages_delete,
+        handlers::party,
+        handlers::party_create,
+        handlers::party_invites,
+        handlers::party_invite,
+        handlers::party_accept,
+        handlers::party_leave,
+        handlers::party_kick,
+        handlers::party_transfer,
+        handlers::party_pit,
+        handlers::guild,
+        handlers::guild_create,
+        handlers::guild_update,
+        handlers::guild_disband,
+        handlers::guild_invites,
+        handlers::guild_applications,
+        handlers::guild_invite,
+        handlers::guild_apply,
+        handlers::guild_accept,
+        handlers::guild_decline,
+        handlers::guild_approve,
+        handlers::guild_reject,
+        handlers::guild_leave,
+        handlers::guild_kick,
+        handlers::guild_transfer,
+        handlers::guild_rank_create,
+        handlers::guild_rank_update,
+        handlers::guild_rank_delete,
+        handlers::guild_member_rank,
+        handlers::guild_profile,
+        handlers::guild_members,
+        handlers::notifications,
+        handlers::notifications_unread,
+        handlers::notifications_read,
+        handlers::notification_read,
+        handlers::notification_delete,
+        handlers::admin_broadcast,
+        handlers::admin_invites,
+        handlers::admin_invite_create,
+        handlers::admin_invite_revoke,
+        handlers::admin_user_username,
+        handlers::admin_user_chat_ban,
+        handlers::server_blocks,
+        handlers::token_refresh,
+        handlers::token_revoke,
+        handlers::token_revoke_all,
+        handlers::token_pit,
+    ),
+    // Schemas referenced only by query parameters are not collected automatically
+    components(schemas(KeyFormat))
+)]
+struct V1;
+
+/// Registers the schemes referenced by the `security` of the paths and drops the empty license
+/// taken from the manifest
+struct Hub;
+
+impl Modify for Hub {
+    fn modify(&self, openapi: &mut Document) {
+        openapi.info.license = None;
+
+        let components = openapi.components.get_or_insert_with(Default::default);
+
+        components.add_security_scheme(
+            "access_token",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .build(),
+            ),
+        );
+        components.add_security_scheme(
+            "server_key",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+        components.add_security_scheme(
+            "refresh_token",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new(RefreshToken::COOKIE_NAME))),
+        );
+    }
+}
@@ -0,0 +1,36 @@
+use common::user::UserStatus;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{models::entities::User, utils::hash_password, DB};
+
+/// Creates active test users `test1`..`testN` whose passwords match their usernames.
+/// Users that already exist are left untouched.
+pub async fn seed_users(db: &DB, count: u32) -> Result<(), sqlx::Error> {
+    let mut created = 0;
+
+    for i in 1..=count {
+        let username = format!("test{i}");
+
+        if User::find_by_username(db, &username).await?.is_some() {
+            continue;
+        }
+
+        User::new(
+            Uuid::new_v4(),
+            username.clone(),
+            format!("{username}@example.com"),
+            hash_password(&username),
+            UserStatus::Active,
+        )
+        .insert(db)
+        .await?;
+        created += 1;
+    }
+
+    if created > 0 {
+        info!(created, "Seeded test users");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,55 @@
+use std::{io::ErrorKind, path::PathBuf};
+
+use async_trait::async_trait;
+use tokio::fs;
+
+use crate::error::Error;
+
+/// Blob storage for user uploaded content
+#[async_trait]
+pub trait Storage: Send + Sync {
+    async fn put(&self, key: &str, data: &[u8]) -> Result<(), Error>;
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error>;
+    /// Removes the blob, missing blobs are not an error
+    async fn delete(&self, key: &str) -> Result<(), Error>;
+}
+
+/// Stores blobs as files under the root directory
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    async fn put(&self, key: &str, data: &[u8]) -> Result<(), Error> {
+        let path = self.root.join(key);
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        Ok(fs::write(path, data).await?)
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        match fs::read(self.root.join(key)).await {
+            Ok(data) => Ok(Some(data)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), Error> {
+        match fs::remove_file(self.root.join(key)).await {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use axum::{
+    extract::MatchedPath,
+    http::{HeaderMap, HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+use opentelemetry::{
+    global,
+    propagation::Extractor,
+    trace::{TraceContextExt, TraceId, TracerProvider},
+};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{propagation::TraceContextPropagator, trace::SdkTracerProvider, Resource};
+use rand::{rngs::OsRng, RngCore};
+use tracing::{field, info_span, Instrument, Span};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+
+use uuid::Uuid;
+
+use crate::{config::Config, error::Error, logging, versioning::LegacyRoute};
+
+pub const TRACE_ID_HEADER: &str = "x-trace-id";
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+/// Longest request id accepted from the caller
+const REQUEST_ID_MAX_LEN: usize = 64;
+
+/// Keeps the log writer and the trace exporter alive, pending lines and spans are flushed on
+/// shutdown
+pub struct Telemetry {
+    provider: Option<SdkTracerProvider>,
+    _log_guard: WorkerGuard,
+}
+
+impl Telemetry {
+    /// Installs the global subscriber that writes logs and exports spans over OTLP if configured
+    pub fn init(config: &Config) -> Result<Self, Error> {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+
+        let provider = match &config.otlp_endpoint {
+            Some(endpoint) => {
+                let exporter = SpanExporter::builder()
+                    .with_http()
+                    .with_endpoint(endpoint)
+                    .build()
+                    .map_err(|err| {
+                        Error::ConfigError(format!("failed to build OTLP exporter: {err}"))
+                    })?;
+
+                Some(
+                    SdkTracerProvider::builder()
+                        .with_batch_exporter(exporter)
+                        .with_resource(
+                            Resource::builder()
+                                .with_service_name(config.otlp_service_name.clone())
+                                .build(),
+                        )
+                        .build(),
+                )
+            }
+            None => None,
+        };
+
+        let (log_layer, log_guard) = logging::layer(config)?;
+
+        tracing_subscriber::registry()
+            .with(config.log_filter())
+            .with(log_layer)
+            .with(provider.as_ref().map(|provider| {
+                tracing_opentelemetry::layer().with_tracer(provider.tracer("ecg-hub"))
+            }))
+            .init();
+
+        Ok(Self {
+            provider,
+            _log_guard: log_guard,
+        })
+    }
+
+    pub fn shutdown(self) {
+        if let Some(provider) = self.provider {
+            if let Err(err) = provider.shutdown() {
+                eprintln!("Failed to flush traces: {err}");
+            }
+        }
+    }
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
+
+/// Request id of the caller if it is safe to log and echo back
+fn request_id(headers: &HeaderMap) -> Option<String> {
+    let id = headers.get(REQUEST_ID_HEADER)?.to_str().ok()?;
+
+    (!id.is_empty()
+        && id.len() <= REQUEST_ID_MAX_LEN
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+    .then(|| id.to_string())
+}
+
+/// Middleware that wraps the request in a span continuing the W3C trace context of the caller,
+/// returns the trace id in the `X-Trace-Id` header and propagates or generates `X-Request-Id`
+pub async fn trace<B>(request: Request<B>, next: Next<B>) -> Response {
+    let parent = global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(request.headers()))
+    });
+    let request_id =
+        request_id(request.headers()).unwrap_or_else(|| Uuid::new_v4().hyphenated().to_string());
+
+    let span = info_span!(
+        "request",
+        otel.kind = "server",
+        http.method = %request.method(),
+        http.target = %request.uri().path(),
+        http.route = field::Empty,
+        legacy = request.extensions().get::<LegacyRoute>().is_some(),
+        http.status_code = field::Empty,
+        request_id = %request_id,
+        trace_id = field::Empty,
+        user = field::Empty,
+        client_type = field::Empty,
+    );
+    let _ = span.set_parent(parent.clone());
+
+    // Exported spans carry the trace id, otherwise it is taken from the caller or generated
+    let trace_id = [
+        span.context().span().span_context().trace_id(),
+        parent.span().span_context().trace_id(),
+    ]
+    .into_iter()
+    .find(|trace_id| *trace_id != TraceId::INVALID)
+    .unwrap_or_else(|| {
+        let mut bytes = [0; 16];
+        OsRng.fill_bytes(&mut bytes);
+        TraceId::from_bytes(bytes)
+    });
+    span.record("trace_id", field::display(trace_id));
+
+    let mut response = next.run(request).instrument(span.clone()).await;
+
+    span.record("http.status_code", response.status().as_u16());
+    response.headers_mut().insert(
+        TRACE_ID_HEADER,
+        HeaderValue::from_str(&trace_id.to_string()).expect("trace id is valid header value"),
+    );
+    response.headers_mut().insert(
+        REQUEST_ID_HEADER,
+        HeaderValue::from_str(&request_id).expect("request id is valid header value"),
+    );
+
+    response
+}
+
+/// Route middleware that records the matched route template in the request span
+pub async fn route<B>(request: Request<B>, next: Next<B>) -> Response {
+    if let Some(path) = request.extensions().get::<MatchedPath>() {
+        Span::current().record("http.route", path.as_str());
+    }
+
+    next.run(request).await
+}
@@ -1,7 +1,10 @@
-use argon2::{password_hash::SaltString, Argon2, PasswordHasher};
-use rand::rngs::OsRng;
+use std::time::Instant;
 
-use crate::error::Error;
+use argon2::{password_hash::SaltString, Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use rand::{rngs::OsRng, seq::SliceRandom};
+use tracing::error;
+
+use crate::{error::Error, metrics};
 
 pub fn load_dotenv() -> Result<bool, Error> {
     Ok(match dotenvy::dotenv() {
@@ -12,8 +15,40 @@ pub fn load_dotenv() -> Result<bool, Error> {
 }
 
 pub fn hash_password(password: &str) -> String {
-    Argon2::default()
+    let started = Instant::now();
+    let hash = Argon2::default()
         .hash_password(password.as_bytes(), &SaltString::generate(&mut OsRng))
         .expect("Failed to generate password hash")
-        .to_string()
+        .to_string();
+    metrics::password_hash("hash", started);
+
+    hash
+}
+
+/// Verifies the password against the stored hash, unparsable hashes never match
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    match PasswordHash::new(hash) {
+        Ok(hash) => {
+            let started = Instant::now();
+            let verified = Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok();
+            metrics::password_hash("verify", started);
+
+            verified
+        }
+        Err(err) => {
+            error!(?err, "Failed to parse password hash");
+            false
+        }
+    }
+}
+
+/// Generates random code that is easy to read and type, i.e. without characters like "0" and "O"
+pub fn generate_code(len: usize) -> String {
+    const CHARSET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+    (0..len)
+        .map(|_| *CHARSET.choose(&mut OsRng).expect("charset is not empty") as char)
+        .collect()
 }
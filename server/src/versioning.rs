@@ -0,0 +1,132 @@
+use std::{
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use axum::{
+    extract::State,
+    http::{header, HeaderValue, Request, StatusCode, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use common::hub::HubApiVersion;
+
+use crate::{app::HubState, config::API_VERSIONS};
+
+pub const ACCEPT_VERSION_HEADER: &str = "accept-version";
+pub const DEPRECATION_HEADER: &str = "deprecation";
+pub const SUNSET_HEADER: &str = "sunset";
+/// Unprefixed routes are deprecated since 2026-10-18
+pub const LEGACY_DEPRECATED_AT: i64 = 1792281600;
+
+/// Discovery and operational paths served outside of the versioned API
+const UNVERSIONED: &[&str] = &[
+    "/",
+    "/status",
+    "/health",
+    "/health/live",
+    "/health/ready",
+    "/openapi.json",
+    "/metrics",
+    "/docs",
+];
+
+/// Version of the API from the path prefix
+fn path_version(path: &str) -> Option<HubApiVersion> {
+    path.strip_prefix('/')?.split('/').next()?.parse().ok()
+}
+
+/// Request extension marking an unprefixed request routed to v1 as a legacy route, so that
+/// metrics and spans can tell it apart from v1 traffic sharing its matched path
+#[derive(Clone, Copy, Debug)]
+pub struct LegacyRoute;
+
+fn prefix(request: &mut Request<impl Sized>, version: HubApiVersion) {
+    let path_and_query = request
+        .uri()
+        .path_and_query()
+        .map(|path_and_query| path_and_query.as_str())
+        .unwrap_or("/");
+
+    let mut parts = request.uri().clone().into_parts();
+    parts.path_and_query = Some(
+        format!("/{}{path_and_query}", version.as_str())
+            .parse()
+            .expect("prefixed path is valid"),
+    );
+    *request.uri_mut() = Uri::from_parts(parts).expect("prefixed uri is valid");
+}
+
+/// Middleware that routes unprefixed requests to the version from the `Accept-Version` header,
+/// or to v1 with deprecation headers while legacy routes are enabled.
+/// Has to wrap the router, since rewriting the path inside of it does not affect the routing.
+pub async fn negotiate<B>(
+    State(state): State<Arc<HubState>>,
+    mut request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let path = request.uri().path();
+    if UNVERSIONED.contains(&path) || path.starts_with("/docs/") || path_version(path).is_some() {
+        return next.run(request).await;
+    }
+
+    match request.headers().get(ACCEPT_VERSION_HEADER) {
+        Some(value) => {
+            let Some(version) = value
+                .to_str()
+                .ok()
+                .and_then(|value| value.trim().parse().ok())
+                .filter(|version| API_VERSIONS.contains(version))
+            else {
+                return StatusCode::NOT_ACCEPTABLE.into_response();
+            };
+
+            prefix(&mut request, version);
+            next.run(request).await
+        }
+        None if state.config.legacy_routes => {
+            prefix(&mut request, HubApiVersion::V1);
+            request.extensions_mut().insert(LegacyRoute);
+            let successor = request.uri().path().to_string();
+
+            let mut response = next.run(request).await;
+            deprecate(
+                &mut response,
+                LEGACY_DEPRECATED_AT,
+                state.config.legacy_sunset,
+                Some(&successor),
+            );
+
+            response
+        }
+        None => next.run(request).await,
+    }
+}
+
+/// Marks the response of a deprecated endpoint with `Deprecation` (RFC 9745), `Sunset`
+/// (RFC 8594) and successor `Link` headers
+pub fn deprecate(
+    response: &mut Response,
+    deprecated_at: i64,
+    sunset: Option<i64>,
+    successor: Option<&str>,
+) {
+    let headers = response.headers_mut();
+
+    headers.insert(
+        DEPRECATION_HEADER,
+        HeaderValue::from_str(&format!("@{deprecated_at}")).expect("date is valid header value"),
+    );
+    if let Some(sunset) = sunset {
+        let date = httpdate::fmt_http_date(UNIX_EPOCH + Duration::from_secs(sunset.max(0) as u64));
+        headers.insert(
+            SUNSET_HEADER,
+            HeaderValue::from_str(&date).expect("date is valid header value"),
+        );
+    }
+    if let Some(value) = successor.and_then(|path| {
+        HeaderValue::from_str(&format!("<{path}>; rel=\"successor-version\"")).ok()
+    }) {
+        headers.insert(header::LINK, value);
+    }
+}
@@ -0,0 +1,188 @@
+//! Fails when the OpenAPI document drifts from the routes of the hub
+
+use std::{collections::BTreeSet, sync::Arc, time::Instant};
+
+use axum::{
+    body::Body,
+    http::{header::ALLOW, Method, Request, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Router,
+};
+use common::hub::HubMode;
+use ecg_hub::{
+    app::HubState, challenge::NoChallenge, config::Config, gateway::Gateway, mailer::LogMailer,
+    names::NameFilter, openapi::ApiDoc, storage::LocalStorage,
+};
+use metrics_exporter_prometheus::PrometheusBuilder;
+use regex::Regex;
+use serde_json::Value;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use tokio::sync::watch;
+use tower::ServiceExt;
+use utoipa::OpenApi;
+
+/// Routes that are served but intentionally left out of the document
+const UNDOCUMENTED: &[&str] = &[
+    // Aliases of `/status` and `/health/ready`
+    "/",
+    "/health",
+    // Prometheus text format
+    "/metrics",
+    // Swagger UI, Debug mode only
+    "/docs",
+    "/docs/",
+    "/docs/{file}",
+];
+const METHODS: &[&str] = &["get", "post", "put", "patch", "delete"];
+
+/// Operations documented in the OpenAPI document
+fn documented() -> BTreeSet<(String, String)> {
+    let document = serde_json::to_value(ApiDoc::openapi()).unwrap();
+    assert_eq!(document["openapi"], "3.1.0");
+
+    let Value::Object(paths) = &document["paths"] else {
+        panic!("document has no paths");
+    };
+
+    paths
+        .iter()
+        .flat_map(|(path, item)| {
+            METHODS
+                .iter()
+                .filter(|method| item.get(**method).is_some())
+                .map(move |method| (method.to_string(), path.clone()))
+        })
+        .collect()
+}
+
+async fn router() -> Router {
+    let config = Config {
+        mode: HubMode::Debug,
+        ..Default::default()
+    };
+
+    // Requests never reach the handlers, so the database is not needed
+    let state = HubState {
+        keys: config.keys(),
+        db: PgPoolOptions::new().connect_lazy_with(PgConnectOptions::new()),
+        mailer: Box::new(LogMailer),
+        storage: Box::new(LocalStorage::new(config.storage_path.clone())),
+        gateway: Gateway::new(),
+        names: NameFilter::new(&config).unwrap(),
+        challenger: Box::new(NoChallenge),
+        started: Instant::now(),
+        shutdown: watch::channel(false).0,
+        metrics: PrometheusBuilder::new().build_recorder().handle(),
+        config,
+    };
+
+    Arc::new(state).build_router()
+}
+
+/// Paths registered in the router, read from its debug representation as axum cannot list them
+fn paths(router: &Router) -> BTreeSet<String> {
+    let path = Regex::new(r#"RouteId\(\d+\): "([^"]+)""#).unwrap();
+
+    path.captures_iter(&format!("{router:?}"))
+        .map(|captures| captures[1].to_string())
+        .filter(|path| !path.contains("__private__axum"))
+        .collect()
+}
+
+/// Operations served by the router, read from the `Allow` header of a method no route accepts
+async fn routed(router: &Router) -> BTreeSet<(String, String)> {
+    let param = Regex::new(r"[:*](\w+)").unwrap();
+    let mut routed = BTreeSet::new();
+
+    for path in paths(router) {
+        let uri = param.replace_all(&path, "00000000-0000-0000-0000-000000000000");
+        let path = param.replace_all(&path, "{$1}").into_owned();
+        if UNDOCUMENTED.contains(&path.as_str()) {
+            continue;
+        }
+
+        let request = Request::builder()
+            .method(Method::TRACE)
+            .uri(uri.as_ref())
+            .body(Body::empty())
+            .unwrap();
+
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(
+            response.status(),
+            StatusCode::METHOD_NOT_ALLOWED,
+            "{path} is not routed"
+        );
+
+        let allow = response.headers()[ALLOW].to_str().unwrap().to_lowercase();
+        routed.extend(
+            allow
+                .split(',')
+                .filter(|method| METHODS.contains(method))
+                .map(|method| (method.to_string(), path.clone())),
+        );
+    }
+
+    routed
+}
+
+#[test]
+fn every_schema_is_defined() {
+    let document = ApiDoc::openapi().to_json().unwrap();
+    let defined = serde_json::to_value(ApiDoc::openapi().components.unwrap().schemas).unwrap();
+
+    let reference = Regex::new(r"#/components/schemas/(\w+)").unwrap();
+    for captures in reference.captures_iter(&document) {
+        assert!(
+            defined.get(&captures[1]).is_some(),
+            "schema {} is referenced but not defined",
+            &captures[1]
+        );
+    }
+}
+
+#[tokio::test]
+async fn every_route_is_documented() {
+    let documented = documented();
+    let routed = routed(&router().await).await;
+    assert!(!routed.is_empty(), "no routes found in the router");
+
+    let missing: Vec<_> = routed.difference(&documented).collect();
+    let stale: Vec<_> = documented.difference(&routed).collect();
+
+    assert!(
+        missing.is_empty(),
+        "routes missing from the document: {missing:?}"
+    );
+    assert!(
+        stale.is_empty(),
+        "documented operations not routed: {stale:?}"
+    );
+}
+
+#[tokio::test]
+async fn every_operation_is_routed() {
+    async fn matched(_: Request<Body>, _: Next<Body>) -> Response {
+        StatusCode::IM_A_TEAPOT.into_response()
+    }
+
+    let router = router().await.route_layer(middleware::from_fn(matched));
+    let param = Regex::new(r"\{\w+\}").unwrap();
+
+    for (method, path) in documented() {
+        let uri = param.replace_all(&path, "00000000-0000-0000-0000-000000000000");
+        let request = Request::builder()
+            .method(Method::from_bytes(method.to_uppercase().as_bytes()).unwrap())
+            .uri(uri.as_ref())
+            .body(Body::empty())
+            .unwrap();
+
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(
+            response.status(),
+            StatusCode::IM_A_TEAPOT,
+            "{method} {path} is not routed"
+        );
+    }
+}